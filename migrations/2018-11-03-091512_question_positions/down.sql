@@ -0,0 +1,2 @@
+ALTER TABLE question_options DROP COLUMN position;
+ALTER TABLE test_questions DROP COLUMN position;
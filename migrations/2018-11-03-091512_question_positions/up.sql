@@ -0,0 +1,19 @@
+ALTER TABLE test_questions ADD COLUMN position INT NOT NULL DEFAULT 0;
+ALTER TABLE question_options ADD COLUMN position INT NOT NULL DEFAULT 0;
+
+-- Keep the existing insertion order for rows created before positions.
+UPDATE test_questions
+SET position = ordered.position
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY test_paper_id ORDER BY id) - 1 AS position
+    FROM test_questions
+) AS ordered
+WHERE test_questions.id = ordered.id;
+
+UPDATE question_options
+SET position = ordered.position
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY test_question_id ORDER BY id) - 1 AS position
+    FROM question_options
+) AS ordered
+WHERE question_options.id = ordered.id;
@@ -0,0 +1,2 @@
+ALTER TABLE test_attempts DROP COLUMN shuffle_seed;
+ALTER TABLE test_papers DROP COLUMN shuffle;
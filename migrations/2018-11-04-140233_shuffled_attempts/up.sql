@@ -0,0 +1,5 @@
+ALTER TABLE test_papers ADD COLUMN shuffle BOOL NOT NULL DEFAULT FALSE;
+
+-- Seed used to shuffle the questions and options of an attempt. It is null
+-- when the attempt was started without shuffling.
+ALTER TABLE test_attempts ADD COLUMN shuffle_seed BIGINT;
@@ -0,0 +1,9 @@
+DROP TABLE attempt_questions;
+DROP TABLE draw_rules;
+
+DELETE FROM test_questions WHERE test_paper_id IS NULL;
+ALTER TABLE test_questions DROP CONSTRAINT question_owner;
+ALTER TABLE test_questions DROP COLUMN question_bank_id;
+ALTER TABLE test_questions ALTER COLUMN test_paper_id SET NOT NULL;
+
+DROP TABLE question_banks;
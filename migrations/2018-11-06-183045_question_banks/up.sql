@@ -0,0 +1,48 @@
+CREATE TABLE question_banks (
+    id SERIAL PRIMARY KEY,
+    uuid UUID UNIQUE NOT NULL DEFAULT uuid_generate_v4(),
+    name TEXT NOT NULL,
+    description TEXT
+);
+
+-- A question either belongs to a test paper or to a question bank.
+ALTER TABLE test_questions ALTER COLUMN test_paper_id DROP NOT NULL;
+ALTER TABLE test_questions ADD COLUMN question_bank_id INT
+    REFERENCES question_banks (id)
+    ON DELETE CASCADE;
+ALTER TABLE test_questions ADD CONSTRAINT question_owner
+    CHECK ((test_paper_id IS NULL) <> (question_bank_id IS NULL));
+
+CREATE TABLE draw_rules (
+    id SERIAL PRIMARY KEY,
+    uuid UUID UNIQUE NOT NULL DEFAULT uuid_generate_v4(),
+    test_paper_id INT NOT NULL,
+    question_bank_id INT NOT NULL,
+    question_count INT NOT NULL CHECK (question_count > 0),
+
+    FOREIGN KEY (test_paper_id)
+        REFERENCES test_papers (id)
+        ON DELETE CASCADE,
+
+    FOREIGN KEY (question_bank_id)
+        REFERENCES question_banks (id)
+        ON DELETE CASCADE
+);
+
+-- Questions frozen for an attempt when it is started.
+CREATE TABLE attempt_questions (
+    id SERIAL PRIMARY KEY,
+    test_attempt_id INT NOT NULL,
+    test_question_id INT NOT NULL,
+    position INT NOT NULL,
+
+    UNIQUE (test_attempt_id, test_question_id),
+
+    FOREIGN KEY (test_attempt_id)
+        REFERENCES test_attempts (id)
+        ON DELETE CASCADE,
+
+    FOREIGN KEY (test_question_id)
+        REFERENCES test_questions (id)
+        ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+DROP INDEX test_questions_tags;
+DROP INDEX test_questions_topic;
+
+ALTER TABLE test_questions DROP COLUMN difficulty;
+ALTER TABLE test_questions DROP COLUMN tags;
+ALTER TABLE test_questions DROP COLUMN topic;
+
+DROP TYPE DIFFICULTY_TYPE;
@@ -0,0 +1,8 @@
+CREATE TYPE DIFFICULTY_TYPE AS ENUM ('Easy', 'Medium', 'Hard');
+
+ALTER TABLE test_questions ADD COLUMN topic TEXT;
+ALTER TABLE test_questions ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE test_questions ADD COLUMN difficulty DIFFICULTY_TYPE;
+
+CREATE INDEX test_questions_topic ON test_questions (topic);
+CREATE INDEX test_questions_tags ON test_questions USING GIN (tags);
@@ -0,0 +1,2 @@
+ALTER TABLE question_options DROP COLUMN feedback;
+ALTER TABLE test_questions DROP COLUMN explanation;
@@ -0,0 +1,2 @@
+ALTER TABLE test_questions ADD COLUMN explanation TEXT;
+ALTER TABLE question_options ADD COLUMN feedback TEXT;
@@ -0,0 +1,3 @@
+ALTER TABLE test_papers DROP COLUMN status;
+
+DROP TYPE PAPER_STATUS;
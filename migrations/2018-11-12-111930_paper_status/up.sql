@@ -0,0 +1,6 @@
+CREATE TYPE PAPER_STATUS AS ENUM ('Draft', 'Published', 'Archived');
+
+ALTER TABLE test_papers ADD COLUMN status PAPER_STATUS NOT NULL DEFAULT 'Draft';
+
+-- Papers created before the lifecycle were already listed to everyone.
+UPDATE test_papers SET status = 'Published';
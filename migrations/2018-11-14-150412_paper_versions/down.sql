@@ -0,0 +1,6 @@
+DROP INDEX test_attempts_test_paper_id;
+
+DELETE FROM test_papers WHERE NOT is_latest;
+ALTER TABLE test_papers DROP COLUMN is_latest;
+ALTER TABLE test_papers DROP COLUMN previous_version_id;
+ALTER TABLE test_papers DROP COLUMN version;
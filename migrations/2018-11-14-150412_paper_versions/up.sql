@@ -0,0 +1,10 @@
+-- Every version of a test paper is a row of its own. Editing a paper which
+-- has been attempted copies it into a new version, leaving the attempted
+-- version and its answers untouched.
+ALTER TABLE test_papers ADD COLUMN version INT NOT NULL DEFAULT 1;
+ALTER TABLE test_papers ADD COLUMN previous_version_id INT
+    REFERENCES test_papers (id)
+    ON DELETE SET NULL;
+ALTER TABLE test_papers ADD COLUMN is_latest BOOL NOT NULL DEFAULT TRUE;
+
+CREATE INDEX test_attempts_test_paper_id ON test_attempts (test_paper_id);
@@ -0,0 +1,5 @@
+DROP INDEX test_papers_created_at;
+DROP INDEX users_created_at;
+
+ALTER TABLE test_papers DROP COLUMN created_at;
+ALTER TABLE users DROP COLUMN created_at;
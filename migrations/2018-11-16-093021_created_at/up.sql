@@ -0,0 +1,8 @@
+-- Existing rows get the time of this migration as their creation time.
+ALTER TABLE users ADD COLUMN created_at TIMESTAMP NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc');
+ALTER TABLE test_papers ADD COLUMN created_at TIMESTAMP NOT NULL
+    DEFAULT (now() AT TIME ZONE 'utc');
+
+CREATE INDEX users_created_at ON users (created_at);
+CREATE INDEX test_papers_created_at ON test_papers (created_at);
@@ -0,0 +1,10 @@
+DROP INDEX question_options_search_trigrams;
+DROP INDEX question_options_search_words;
+DROP INDEX test_questions_search_trigrams;
+DROP INDEX test_questions_search_words;
+DROP INDEX users_search_trigrams;
+DROP INDEX users_search_words;
+
+DROP FUNCTION user_search_text(TEXT, TEXT, TEXT, TEXT);
+
+DROP EXTENSION IF EXISTS pg_trgm;
@@ -0,0 +1,35 @@
+-- Users and questions are searched both by their words and by trigrams, so
+-- that misspelled queries still find something.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- Text of a user which is searched.
+CREATE FUNCTION user_search_text(
+    first_name TEXT,
+    last_name TEXT,
+    email TEXT,
+    contact TEXT
+) RETURNS TEXT AS $$
+    SELECT coalesce(first_name, '') || ' ' || coalesce(last_name, '') || ' '
+        || email || ' ' || coalesce(contact, '')
+$$ LANGUAGE SQL IMMUTABLE;
+
+CREATE INDEX users_search_words ON users USING GIN (
+    to_tsvector('simple', user_search_text(first_name, last_name, email, contact))
+);
+CREATE INDEX users_search_trigrams ON users USING GIN (
+    user_search_text(first_name, last_name, email, contact) gin_trgm_ops
+);
+
+CREATE INDEX test_questions_search_words ON test_questions USING GIN (
+    to_tsvector('english', question)
+);
+CREATE INDEX test_questions_search_trigrams ON test_questions USING GIN (
+    question gin_trgm_ops
+);
+
+CREATE INDEX question_options_search_words ON question_options USING GIN (
+    to_tsvector('english', option)
+);
+CREATE INDEX question_options_search_trigrams ON question_options USING GIN (
+    option gin_trgm_ops
+);
@@ -0,0 +1,3 @@
+ALTER TABLE test_schedules DROP COLUMN lock_version;
+ALTER TABLE test_questions DROP COLUMN lock_version;
+ALTER TABLE test_papers DROP COLUMN lock_version;
@@ -0,0 +1,5 @@
+-- Incremented on every update, so that an update made from an outdated
+-- copy of a row can be rejected.
+ALTER TABLE test_papers ADD COLUMN lock_version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE test_questions ADD COLUMN lock_version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE test_schedules ADD COLUMN lock_version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,92 @@
+//! Maintenance tasks for the `paryxa-admin` binary. These run against the
+//! same database and models as the server.
+
+use backup::{Backup, RestoreSummary};
+use chrono::NaiveDateTime;
+use diesel::{result::Error as DieselError, PgConnection};
+use errors::{Error, SResult};
+use models::{
+    test_attempt::TestAttempt, test_paper::TestPaper, test_schedule::TestSchedule, user::User,
+};
+use password_policy;
+use serde_json;
+use std::io::{self, Read, Write};
+use uuid::Uuid;
+
+embed_migrations!("migrations");
+
+/// Runs the pending migrations, printing the ones it runs.
+pub fn run_migrations(conn: &PgConnection) -> SResult<()> {
+    embedded_migrations::run_with_output(conn, &mut io::stdout())?;
+    Ok(())
+}
+
+/// Creates an admin with the given email, or makes the existing user with
+/// that email an admin. The password is only set for a new user.
+pub fn create_admin(email: &str, password: &str, conn: &PgConnection) -> SResult<User> {
+    match User::find_by_email(email, conn) {
+        Ok(user) => user.promote(conn),
+        Err(Error::Diesel(DieselError::NotFound)) => {
+            password_policy::check(password, email)?;
+            User::create_admin(email.to_string(), password, conn)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+pub fn promote(email: &str, conn: &PgConnection) -> SResult<User> {
+    User::find_by_email(email, conn)?.promote(conn)
+}
+
+/// Sets a new password for a user. A running server keeps accepting the
+/// old password of a cached login until the cache entry expires.
+pub fn reset_password(email: &str, password: &str, conn: &PgConnection) -> SResult<User> {
+    let user = User::find_by_email(email, conn)?;
+    password_policy::check(password, &user.email)?;
+    user.reset_password(password, conn)
+}
+
+/// Writes a JSON backup of the users, the question banks and the test
+/// papers, with the password hashes of the users only when asked for.
+pub fn export_backup<W: Write>(
+    writer: W,
+    with_password_hashes: bool,
+    conn: &PgConnection,
+) -> SResult<()> {
+    let backup = Backup::create(with_password_hashes, conn)?;
+    Ok(serde_json::to_writer_pretty(writer, &backup)?)
+}
+
+/// Restores a JSON backup written by `export_backup`.
+pub fn import_backup<R: Read>(reader: R, conn: &PgConnection) -> SResult<RestoreSummary> {
+    let backup: Backup = serde_json::from_reader(reader)?;
+    backup.restore(conn)
+}
+
+/// An attempt which is being taken right now.
+pub struct ActiveAttempt {
+    pub id: Uuid,
+    pub email: String,
+    pub test_paper: String,
+    pub start_time: NaiveDateTime,
+    pub end_time: NaiveDateTime,
+}
+
+/// Unfinished attempts of the tests which are happening right now.
+pub fn active_attempts(conn: &PgConnection) -> SResult<Vec<ActiveAttempt>> {
+    let mut active = Vec::new();
+    for attempt in TestAttempt::find_all_unfinished(conn)? {
+        let schedule = TestSchedule::find(attempt.test_schedule_id, conn)?;
+        if !schedule.is_happening() {
+            continue;
+        }
+        active.push(ActiveAttempt {
+            id: attempt.uuid,
+            email: User::find(attempt.user_id, conn)?.email,
+            test_paper: TestPaper::find(attempt.test_paper_id, conn)?.name,
+            start_time: attempt.start_time,
+            end_time: schedule.end_time(),
+        });
+    }
+    Ok(active)
+}
@@ -0,0 +1,575 @@
+//! A JSON backup of the users and the content, i.e. the question banks and
+//! the test papers with their questions, options, draw rules and schedules.
+//! Every row keeps its uuid so that restoring the same backup again updates
+//! the rows created the first time instead of duplicating them.
+
+use chrono::NaiveDateTime;
+use bcrypt;
+use diesel::{self, dsl::sql, prelude::*, sql_types};
+use errors::{Error, SResult};
+use models::{
+    draw_rule::DrawRule,
+    question_bank::QuestionBank,
+    question_option::QuestionOption,
+    test_paper::{PaperStatus, TestPaper, TestType},
+    test_question::{Difficulty, QuestionOwner, TestQuestion},
+    test_schedule::TestSchedule,
+    user::{Gender, User, UserType},
+};
+use schema::{
+    draw_rules, question_banks, question_options, test_papers, test_questions, test_schedules,
+    users,
+};
+use uuid::Uuid;
+
+/// Version of the backup format. Bumped whenever a backup of an older
+/// version cannot be restored as is.
+pub const BACKUP_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Backup {
+    version: u32,
+    #[serde(default)]
+    users: Vec<UserBackup>,
+    question_banks: Vec<BankBackup>,
+    test_papers: Vec<PaperBackup>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct UserBackup {
+    uuid: Uuid,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    email: String,
+    /// Hash of the password, which is only backed up when asked for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    gender: Option<Gender>,
+    contact: Option<String>,
+    #[serde(rename = "type")]
+    type_: UserType,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BankBackup {
+    uuid: Uuid,
+    name: String,
+    description: Option<String>,
+    questions: Vec<QuestionBackup>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PaperBackup {
+    uuid: Uuid,
+    name: String,
+    description: Option<String>,
+    #[serde(rename = "type")]
+    type_: TestType,
+    shuffle: bool,
+    status: PaperStatus,
+    questions: Vec<QuestionBackup>,
+    draw_rules: Vec<DrawRuleBackup>,
+    schedules: Vec<ScheduleBackup>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct QuestionBackup {
+    uuid: Uuid,
+    question: String,
+    topic: Option<String>,
+    tags: Vec<String>,
+    difficulty: Option<Difficulty>,
+    explanation: Option<String>,
+    options: Vec<OptionBackup>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OptionBackup {
+    uuid: Uuid,
+    option: String,
+    is_correct: Option<bool>,
+    feedback: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DrawRuleBackup {
+    uuid: Uuid,
+    question_bank: Uuid,
+    question_count: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ScheduleBackup {
+    uuid: Uuid,
+    time: NaiveDateTime,
+    duration: i32,
+}
+
+/// What a restore has changed.
+pub struct RestoreSummary {
+    pub users: usize,
+    pub question_banks: usize,
+    pub test_papers: usize,
+    /// Test papers and questions left untouched with the reason why.
+    pub skipped: Vec<(Uuid, String)>,
+}
+
+impl Backup {
+    /// Backs up the users, the question banks and the latest versions of
+    /// the test papers. The password hashes of the users are left out unless
+    /// asked for. Attempts are not a part of the content.
+    pub fn create(with_password_hashes: bool, conn: &PgConnection) -> SResult<Backup> {
+        let users = users::table
+            .order(users::id)
+            .load::<User>(conn)?
+            .into_iter()
+            .map(|user| UserBackup {
+                password: if with_password_hashes {
+                    Some(user.password_hash().to_string())
+                } else {
+                    None
+                },
+                uuid: user.uuid,
+                first_name: user.first_name,
+                last_name: user.last_name,
+                email: user.email,
+                gender: user.gender,
+                contact: user.contact,
+                type_: user.type_,
+                created_at: user.created_at,
+            }).collect();
+
+        let mut banks = Vec::new();
+        for bank in QuestionBank::find_all(conn)? {
+            banks.push(BankBackup {
+                questions: backup_questions(QuestionOwner::QuestionBank(bank.id), conn)?,
+                uuid: bank.uuid,
+                name: bank.name,
+                description: bank.description,
+            });
+        }
+
+        let mut papers = Vec::new();
+        for paper in TestPaper::find_all(conn)? {
+            let mut draw_rules = Vec::new();
+            for rule in DrawRule::find_all_for_test_paper(paper.id, conn)? {
+                draw_rules.push(DrawRuleBackup {
+                    uuid: rule.uuid,
+                    question_bank: QuestionBank::find(rule.question_bank_id, conn)?.uuid,
+                    question_count: rule.question_count,
+                });
+            }
+            let schedules = TestSchedule::find_all_for_test_paper(paper.id, conn)?
+                .into_iter()
+                .map(|schedule| ScheduleBackup {
+                    uuid: schedule.uuid,
+                    time: schedule.time,
+                    duration: schedule.duration,
+                }).collect();
+
+            papers.push(PaperBackup {
+                questions: backup_questions(QuestionOwner::TestPaper(paper.id), conn)?,
+                draw_rules,
+                schedules,
+                uuid: paper.uuid,
+                name: paper.name,
+                description: paper.description,
+                type_: paper.type_,
+                shuffle: paper.shuffle,
+                status: paper.status,
+            });
+        }
+
+        Ok(Backup {
+            version: BACKUP_VERSION,
+            users,
+            question_banks: banks,
+            test_papers: papers,
+        })
+    }
+
+    /// Restores a backup, inserting the missing rows and updating the
+    /// existing ones by their uuids, whose lock versions are bumped so that
+    /// edits made from before the restore are rejected. Nothing is ever
+    /// deleted. Test papers which have been attempted or replaced by a newer
+    /// version, and bank questions which have been attempted, are skipped as
+    /// their content must not change. So are the users whose email another
+    /// user has, and the rows whose uuid belongs to another parent.
+    ///
+    /// Users keep their password unless the backup has its hash. A new user
+    /// without one cannot sign in until an admin resets the password.
+    pub fn restore(self, conn: &PgConnection) -> SResult<RestoreSummary> {
+        if self.version != BACKUP_VERSION {
+            Err(Error::UnsupportedBackup(self.version))?;
+        }
+
+        conn.transaction(|| {
+            let mut summary = RestoreSummary {
+                users: 0,
+                question_banks: 0,
+                test_papers: 0,
+                skipped: Vec::new(),
+            };
+
+            for user in self.users {
+                if restore_user(user, &mut summary.skipped, conn)? {
+                    summary.users += 1;
+                }
+            }
+
+            for bank in self.question_banks {
+                let row = BankRow {
+                    uuid: bank.uuid,
+                    name: bank.name,
+                    description: bank.description,
+                };
+                let bank_id = diesel::insert_into(question_banks::table)
+                    .values(&row)
+                    .on_conflict(question_banks::uuid)
+                    .do_update()
+                    .set(&row)
+                    .returning(question_banks::id)
+                    .get_result(conn)?;
+                let owner = QuestionOwner::QuestionBank(bank_id);
+                restore_questions(bank.questions, owner, &mut summary.skipped, conn)?;
+                summary.question_banks += 1;
+            }
+
+            for paper in self.test_papers {
+                let existing: Option<TestPaper> = test_papers::table
+                    .filter(test_papers::uuid.eq(paper.uuid))
+                    .get_result(conn)
+                    .optional()?;
+                if let Some(existing) = existing {
+                    if !existing.is_latest {
+                        summary
+                            .skipped
+                            .push((paper.uuid, "A newer version of the test paper exists.".into()));
+                        continue;
+                    }
+                    if existing.has_attempts(conn)? {
+                        summary
+                            .skipped
+                            .push((paper.uuid, "The test paper has been attempted.".into()));
+                        continue;
+                    }
+                }
+
+                let row = PaperRow {
+                    uuid: paper.uuid,
+                    name: paper.name,
+                    description: paper.description,
+                    type_: paper.type_,
+                    shuffle: paper.shuffle,
+                    status: paper.status,
+                };
+                let paper_id = diesel::insert_into(test_papers::table)
+                    .values(&row)
+                    .on_conflict(test_papers::uuid)
+                    .do_update()
+                    .set((
+                        &row,
+                        test_papers::lock_version.eq(test_papers::lock_version + 1),
+                    ))
+                    .returning(test_papers::id)
+                    .get_result(conn)?;
+                let owner = QuestionOwner::TestPaper(paper_id);
+                restore_questions(paper.questions, owner, &mut summary.skipped, conn)?;
+
+                for rule in paper.draw_rules {
+                    let existing: Option<i32> = draw_rules::table
+                        .filter(draw_rules::uuid.eq(rule.uuid))
+                        .select(draw_rules::test_paper_id)
+                        .get_result(conn)
+                        .optional()?;
+                    if existing.map_or(false, |id| id != paper_id) {
+                        summary
+                            .skipped
+                            .push((rule.uuid, "The draw rule is of another test paper.".into()));
+                        continue;
+                    }
+                    let bank = QuestionBank::find_by_uuid(rule.question_bank, conn)?;
+                    let row = DrawRuleRow {
+                        uuid: rule.uuid,
+                        test_paper_id: paper_id,
+                        question_bank_id: bank.id,
+                        question_count: rule.question_count,
+                    };
+                    diesel::insert_into(draw_rules::table)
+                        .values(&row)
+                        .on_conflict(draw_rules::uuid)
+                        .do_update()
+                        .set(&row)
+                        .execute(conn)?;
+                }
+                for schedule in paper.schedules {
+                    let existing: Option<i32> = test_schedules::table
+                        .filter(test_schedules::uuid.eq(schedule.uuid))
+                        .select(test_schedules::test_paper_id)
+                        .get_result(conn)
+                        .optional()?;
+                    if existing.map_or(false, |id| id != paper_id) {
+                        summary
+                            .skipped
+                            .push((schedule.uuid, "The schedule is of another test paper.".into()));
+                        continue;
+                    }
+                    let row = ScheduleRow {
+                        uuid: schedule.uuid,
+                        test_paper_id: paper_id,
+                        time: schedule.time,
+                        duration: schedule.duration,
+                    };
+                    diesel::insert_into(test_schedules::table)
+                        .values(&row)
+                        .on_conflict(test_schedules::uuid)
+                        .do_update()
+                        .set((
+                            &row,
+                            test_schedules::lock_version.eq(test_schedules::lock_version + 1),
+                        ))
+                        .execute(conn)?;
+                }
+                summary.test_papers += 1;
+            }
+            Ok(summary)
+        })
+    }
+}
+
+fn backup_questions(owner: QuestionOwner, conn: &PgConnection) -> SResult<Vec<QuestionBackup>> {
+    let mut questions = Vec::new();
+    for question in TestQuestion::find_all(owner, conn)? {
+        let options = QuestionOption::find_all(question.id, conn)?
+            .into_iter()
+            .map(|option| OptionBackup {
+                uuid: option.uuid,
+                option: option.option,
+                is_correct: option.is_correct,
+                feedback: option.feedback,
+            }).collect();
+        questions.push(QuestionBackup {
+            uuid: question.uuid,
+            question: question.question,
+            topic: question.topic,
+            tags: question.tags,
+            difficulty: question.difficulty,
+            explanation: question.explanation,
+            options,
+        });
+    }
+    Ok(questions)
+}
+
+/// Restores a user, giving whether it was restored.
+fn restore_user(
+    user: UserBackup,
+    skipped: &mut Vec<(Uuid, String)>,
+    conn: &PgConnection,
+) -> SResult<bool> {
+    let by_email: Option<Uuid> = users::table
+        .filter(users::email.eq(&user.email))
+        .select(users::uuid)
+        .get_result(conn)
+        .optional()?;
+    if by_email.map_or(false, |uuid| uuid != user.uuid) {
+        skipped.push((user.uuid, "Another user has the email.".into()));
+        return Ok(false);
+    }
+
+    let row = UserRow {
+        uuid: user.uuid,
+        first_name: user.first_name,
+        last_name: user.last_name,
+        email: user.email,
+        gender: user.gender,
+        contact: user.contact,
+        type_: user.type_,
+        created_at: user.created_at,
+    };
+    let updated = diesel::update(users::table.filter(users::uuid.eq(user.uuid)))
+        .set(&row)
+        .execute(conn)?;
+    if updated == 0 {
+        let password = match user.password {
+            Some(password) => password,
+            None => unknown_password_hash(conn)?,
+        };
+        diesel::insert_into(users::table)
+            .values((&row, users::password.eq(password)))
+            .execute(conn)?;
+    } else if let Some(password) = user.password {
+        diesel::update(users::table.filter(users::uuid.eq(user.uuid)))
+            .set(users::password.eq(password))
+            .execute(conn)?;
+    }
+    Ok(true)
+}
+
+/// Hash of a random password which nobody knows. Its cost is low as it is
+/// never guessed anyway.
+fn unknown_password_hash(conn: &PgConnection) -> SResult<String> {
+    let secret: Uuid =
+        diesel::select(sql::<sql_types::Uuid>("uuid_generate_v4()")).get_result(conn)?;
+    Ok(bcrypt::hash(&secret.to_string(), 4)?)
+}
+
+/// Restores the questions of an owner, skipping the ones which have been
+/// drawn into an attempt as their answers refer to them, and the ones whose
+/// uuid is of another owner.
+fn restore_questions(
+    questions: Vec<QuestionBackup>,
+    owner: QuestionOwner,
+    skipped: &mut Vec<(Uuid, String)>,
+    conn: &PgConnection,
+) -> SResult<()> {
+    for (position, question) in (0..).zip(questions) {
+        let existing: Option<(Option<i32>, Option<i32>)> = test_questions::table
+            .filter(test_questions::uuid.eq(question.uuid))
+            .select((test_questions::test_paper_id, test_questions::question_bank_id))
+            .get_result(conn)
+            .optional()?;
+        if existing.map_or(false, |existing| {
+            existing != (owner.test_paper_id(), owner.question_bank_id())
+        }) {
+            skipped.push((
+                question.uuid,
+                "The question is of another test paper or question bank.".into(),
+            ));
+            continue;
+        }
+        if TestQuestion::any_attempted(Some(&[question.uuid][..]), owner, conn)? {
+            skipped.push((question.uuid, "The question has been attempted.".into()));
+            continue;
+        }
+        let row = QuestionRow {
+            uuid: question.uuid,
+            question: question.question,
+            test_paper_id: owner.test_paper_id(),
+            position,
+            question_bank_id: owner.question_bank_id(),
+            topic: question.topic,
+            tags: question.tags,
+            difficulty: question.difficulty,
+            explanation: question.explanation,
+        };
+        let question_id = diesel::insert_into(test_questions::table)
+            .values(&row)
+            .on_conflict(test_questions::uuid)
+            .do_update()
+            .set((
+                &row,
+                test_questions::lock_version.eq(test_questions::lock_version + 1),
+            ))
+            .returning(test_questions::id)
+            .get_result(conn)?;
+
+        for (position, option) in (0..).zip(question.options) {
+            let existing: Option<i32> = question_options::table
+                .filter(question_options::uuid.eq(option.uuid))
+                .select(question_options::test_question_id)
+                .get_result(conn)
+                .optional()?;
+            if existing.map_or(false, |id| id != question_id) {
+                skipped.push((option.uuid, "The option is of another question.".into()));
+                continue;
+            }
+            let row = OptionRow {
+                uuid: option.uuid,
+                option: option.option,
+                test_question_id: question_id,
+                is_correct: option.is_correct,
+                position,
+                feedback: option.feedback,
+            };
+            diesel::insert_into(question_options::table)
+                .values(&row)
+                .on_conflict(question_options::uuid)
+                .do_update()
+                .set(&row)
+                .execute(conn)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "users"]
+#[changeset_options(treat_none_as_null = "true")]
+struct UserRow {
+    uuid: Uuid,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    email: String,
+    gender: Option<Gender>,
+    contact: Option<String>,
+    type_: UserType,
+    created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "question_banks"]
+#[changeset_options(treat_none_as_null = "true")]
+struct BankRow {
+    uuid: Uuid,
+    name: String,
+    description: Option<String>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "test_papers"]
+#[changeset_options(treat_none_as_null = "true")]
+struct PaperRow {
+    uuid: Uuid,
+    name: String,
+    description: Option<String>,
+    type_: TestType,
+    shuffle: bool,
+    status: PaperStatus,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "test_questions"]
+#[changeset_options(treat_none_as_null = "true")]
+struct QuestionRow {
+    uuid: Uuid,
+    question: String,
+    test_paper_id: Option<i32>,
+    position: i32,
+    question_bank_id: Option<i32>,
+    topic: Option<String>,
+    tags: Vec<String>,
+    difficulty: Option<Difficulty>,
+    explanation: Option<String>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "question_options"]
+#[changeset_options(treat_none_as_null = "true")]
+struct OptionRow {
+    uuid: Uuid,
+    option: String,
+    test_question_id: i32,
+    is_correct: Option<bool>,
+    position: i32,
+    feedback: Option<String>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "draw_rules"]
+struct DrawRuleRow {
+    uuid: Uuid,
+    test_paper_id: i32,
+    question_bank_id: i32,
+    question_count: i32,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "test_schedules"]
+struct ScheduleRow {
+    uuid: Uuid,
+    test_paper_id: i32,
+    time: NaiveDateTime,
+    duration: i32,
+}
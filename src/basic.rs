@@ -21,7 +21,8 @@ pub fn basic_optional() -> impl Filter<Extract = (Option<BasicUser>,), Error = R
         .unify()
 }
 
-fn parse_authorization(auth: String) -> Result<BasicUser, Rejection> {
+/// Reads a `BasicUser` from the value of an `Authorization` header.
+pub fn parse_authorization(auth: String) -> Result<BasicUser, Rejection> {
     let parts = auth.split_whitespace().collect::<Vec<_>>();
     if parts.len() != 2 {
         Err(warp::reject::bad_request())?;
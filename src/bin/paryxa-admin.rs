@@ -0,0 +1,115 @@
+extern crate paryxa_server;
+
+use paryxa_server::{admin, config::Config, password_policy, pg_pool, PooledPg};
+use std::{
+    env,
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, Write},
+    process,
+};
+
+const USAGE: &str = "Usage: paryxa-admin COMMAND
+
+Commands:
+    migrate                 Runs the pending database migrations.
+    create-admin EMAIL      Creates an admin, or promotes the existing user.
+    promote EMAIL           Makes an existing user an admin.
+    reset-password EMAIL    Sets a new password for a user.
+    export [--password-hashes] [FILE]
+                            Writes a JSON backup of the users and the test papers to the
+                            file or stdout, with the password hashes when asked for.
+    import FILE             Restores a JSON backup of the users and the test papers.
+    attempts                Lists the attempts of the tests happening right now.
+
+Passwords are read from stdin.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["migrate"] => or_exit(admin::run_migrations(&connect())),
+        ["create-admin", email] => {
+            let password = read_password();
+            let user = or_exit(admin::create_admin(email, &password, &connect()));
+            println!("{} is an admin.", user.email);
+        }
+        ["promote", email] => {
+            let user = or_exit(admin::promote(email, &connect()));
+            println!("{} is an admin.", user.email);
+        }
+        ["reset-password", email] => {
+            let password = read_password();
+            let user = or_exit(admin::reset_password(email, &password, &connect()));
+            println!("The password of {} is reset.", user.email);
+        }
+        ["export"] => or_exit(admin::export_backup(io::stdout(), false, &connect())),
+        ["export", "--password-hashes"] => {
+            or_exit(admin::export_backup(io::stdout(), true, &connect()))
+        }
+        ["export", "--password-hashes", path] => {
+            let file = or_exit(File::create(path));
+            or_exit(admin::export_backup(file, true, &connect()));
+        }
+        ["export", path] => {
+            let file = or_exit(File::create(path));
+            or_exit(admin::export_backup(file, false, &connect()));
+        }
+        ["import", path] => {
+            let file = or_exit(File::open(path));
+            let summary = or_exit(admin::import_backup(file, &connect()));
+            println!(
+                "Restored {} users, {} question banks and {} test papers.",
+                summary.users, summary.question_banks, summary.test_papers
+            );
+            for (uuid, reason) in summary.skipped {
+                println!("Skipped {}: {}", uuid, reason);
+            }
+        }
+        ["attempts"] => {
+            for attempt in or_exit(admin::active_attempts(&connect())) {
+                println!(
+                    "{}\t{}\t{}\tstarted {}\tends {}",
+                    attempt.id,
+                    attempt.email,
+                    attempt.test_paper,
+                    attempt.start_time,
+                    attempt.end_time
+                );
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+/// Connects to the database of the configuration the server would use, and
+/// applies its password policy.
+fn connect() -> PooledPg {
+    let config = or_exit(Config::load());
+    or_exit(password_policy::configure(&config.password));
+    let pool = or_exit(pg_pool(&config.database));
+    or_exit(pool.get())
+}
+
+fn read_password() -> String {
+    eprint!("Password: ");
+    or_exit(io::stderr().flush());
+    let mut password = String::new();
+    or_exit(io::stdin().lock().read_line(&mut password));
+    let password = password.trim_end_matches(|c| c == '\n' || c == '\r');
+    if password.is_empty() {
+        eprintln!("The password cannot be empty.");
+        process::exit(1);
+    }
+    password.to_string()
+}
+
+fn or_exit<T, E: Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1)
+    })
+}
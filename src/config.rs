@@ -0,0 +1,405 @@
+//! Settings of the server, read from a TOML file and then overridden by the
+//! environment. The file is `paryxa.toml` unless `PARYXA_CONFIG` names
+//! another one, and is optional as long as `DATABASE_URL` is set.
+
+use dotenv;
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Display,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use toml;
+
+const DEFAULT_PATH: &str = "paryxa.toml";
+
+#[derive(Debug, Fail)]
+pub enum ConfigError {
+    #[fail(display = "Could not read the config file {:?}: {}", _0, _1)]
+    Read(PathBuf, String),
+    #[fail(display = "Invalid config file {:?}: {}", _0, _1)]
+    Parse(PathBuf, String),
+    #[fail(display = "Invalid value {:?} of `{}`: {}", _1, _0, _2)]
+    InvalidEnv(&'static str, String, String),
+    #[fail(display = "Invalid setting `{}`: {}", _0, _1)]
+    Invalid(&'static str, String),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub graphql: GraphqlConfig,
+    pub password: PasswordConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address to listen on.
+    pub bind: String,
+    /// Serves HTTPS when set.
+    pub tls: Option<TlsConfig>,
+    /// Log filter used unless `RUST_LOG` is set.
+    pub log: String,
+    /// Origins of the web apps allowed to make cross origin requests.
+    pub cors_origins: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            bind: "127.0.0.1:4000".into(),
+            tls: None,
+            log: "paryxa-server".into(),
+            cors_origins: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// Path of the PEM encoded certificate chain.
+    pub cert: PathBuf,
+    /// Path of the PEM encoded private key.
+    pub key: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    /// Maximum number of connections in the pool.
+    pub pool_size: u32,
+    /// Seconds to wait for a connection from the pool.
+    pub connection_timeout: u64,
+    /// Seconds after which an idle connection is closed, or 0 for never.
+    pub idle_timeout: Option<u64>,
+    /// Seconds after which a connection is closed and replaced, or 0 for
+    /// never.
+    pub max_lifetime: Option<u64>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> DatabaseConfig {
+        DatabaseConfig {
+            url: String::new(),
+            pool_size: 10,
+            connection_timeout: 30,
+            idle_timeout: Some(600),
+            max_lifetime: Some(1800),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Seconds for which a verified login is remembered. Verifying a
+    /// password is slow, so it is not done on every request.
+    pub cache_ttl: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> AuthConfig {
+        AuthConfig { cache_ttl: 86400 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GraphqlConfig {
+    /// Deepest nesting of fields in a query.
+    pub max_depth: usize,
+    /// Highest cost of a query, which is the sum of the costs of its fields,
+    /// each multiplied by the page sizes of the lists it is in.
+    pub max_complexity: u64,
+    /// Most aliased fields in a query.
+    pub max_aliases: usize,
+    /// Costs of the fields which load lists, by their names. Every other
+    /// field costs 1.
+    pub field_costs: HashMap<String, u64>,
+    /// JSON file of the persisted queries, keyed by their SHA-256 hashes.
+    pub persisted_queries: Option<PathBuf>,
+    /// Executes nothing but the persisted queries, and disables GraphiQL.
+    pub persisted_only: bool,
+}
+
+impl Default for GraphqlConfig {
+    fn default() -> GraphqlConfig {
+        let field_costs = [
+            ("users", 10),
+            ("testPapers", 10),
+            ("questionBanks", 10),
+            ("questions", 10),
+            ("answers", 10),
+            ("options", 5),
+            ("topicScores", 5),
+            ("drawRules", 5),
+            ("testSchedules", 5),
+            ("testSubscriptions", 5),
+            ("testRooms", 5),
+            ("searchUsers", 20),
+            ("searchQuestions", 20),
+        ];
+        GraphqlConfig {
+            max_depth: 15,
+            max_complexity: 5000,
+            max_aliases: 30,
+            field_costs: field_costs
+                .iter()
+                .map(|&(field, cost)| (field.to_string(), cost))
+                .collect(),
+            persisted_queries: None,
+            persisted_only: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordConfig {
+    /// Fewest characters of a new password.
+    pub min_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// File or directory of the SHA-1 hashes of breached passwords, which
+    /// are not allowed.
+    pub breached_list: Option<PathBuf>,
+}
+
+impl Default for PasswordConfig {
+    fn default() -> PasswordConfig {
+        PasswordConfig {
+            min_length: 8,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            breached_list: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads and validates the configuration.
+    pub fn load() -> Result<Config, ConfigError> {
+        dotenv::dotenv().ok();
+
+        let mut config = match env::var("PARYXA_CONFIG") {
+            Ok(path) => Config::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_PATH).exists() => {
+                Config::from_file(Path::new(DEFAULT_PATH))?
+            }
+            Err(_) => Config::default(),
+        };
+        config.override_from_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| ConfigError::Read(path.to_path_buf(), err.to_string()))?;
+        toml::from_str(&content)
+            .map_err(|err| ConfigError::Parse(path.to_path_buf(), err.to_string()))
+    }
+
+    fn override_from_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(bind) = env_var("PARYXA_BIND")? {
+            self.server.bind = bind;
+        }
+        if let Some(log) = env_var("PARYXA_LOG")? {
+            self.server.log = log;
+        }
+        if let Some(origins) = env_var::<String>("PARYXA_CORS_ORIGINS")? {
+            self.server.cors_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        match (env_var("PARYXA_TLS_CERT")?, env_var("PARYXA_TLS_KEY")?) {
+            (Some(cert), Some(key)) => self.server.tls = Some(TlsConfig { cert, key }),
+            (None, None) => (),
+            _ => {
+                return Err(ConfigError::Invalid(
+                    "PARYXA_TLS_CERT",
+                    "`PARYXA_TLS_CERT` and `PARYXA_TLS_KEY` must be set together.".into(),
+                ))
+            }
+        }
+        if let Some(url) = env_var("DATABASE_URL")? {
+            self.database.url = url;
+        }
+        if let Some(pool_size) = env_var("PARYXA_POOL_SIZE")? {
+            self.database.pool_size = pool_size;
+        }
+        if let Some(timeout) = env_var("PARYXA_POOL_TIMEOUT")? {
+            self.database.connection_timeout = timeout;
+        }
+        if let Some(timeout) = env_var("PARYXA_POOL_IDLE_TIMEOUT")? {
+            self.database.idle_timeout = Some(timeout);
+        }
+        if let Some(lifetime) = env_var("PARYXA_POOL_MAX_LIFETIME")? {
+            self.database.max_lifetime = Some(lifetime);
+        }
+        if let Some(ttl) = env_var("PARYXA_AUTH_CACHE_TTL")? {
+            self.auth.cache_ttl = ttl;
+        }
+        if let Some(max_depth) = env_var("PARYXA_MAX_DEPTH")? {
+            self.graphql.max_depth = max_depth;
+        }
+        if let Some(max_complexity) = env_var("PARYXA_MAX_COMPLEXITY")? {
+            self.graphql.max_complexity = max_complexity;
+        }
+        if let Some(max_aliases) = env_var("PARYXA_MAX_ALIASES")? {
+            self.graphql.max_aliases = max_aliases;
+        }
+        if let Some(path) = env_var("PARYXA_PERSISTED_QUERIES")? {
+            self.graphql.persisted_queries = Some(path);
+        }
+        if let Some(persisted_only) = env_var("PARYXA_PERSISTED_ONLY")? {
+            self.graphql.persisted_only = persisted_only;
+        }
+        if let Some(min_length) = env_var("PARYXA_PASSWORD_MIN_LENGTH")? {
+            self.password.min_length = min_length;
+        }
+        if let Some(path) = env_var("PARYXA_BREACHED_PASSWORDS")? {
+            self.password.breached_list = Some(path);
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.bind_address()?;
+        if let Some(ref tls) = self.server.tls {
+            for &(name, path) in &[("server.tls.cert", &tls.cert), ("server.tls.key", &tls.key)] {
+                if !path.is_file() {
+                    let message = format!("{:?} is not a file.", path);
+                    return Err(ConfigError::Invalid(name, message));
+                }
+            }
+        }
+        for origin in &self.server.cors_origins {
+            let host = origin
+                .trim_start_matches("http://")
+                .trim_start_matches("https://");
+            if host.len() == origin.len() || host.is_empty() || host.contains('/') {
+                let message = format!("{:?} is not an origin like `https://example.com`.", origin);
+                return Err(ConfigError::Invalid("server.cors_origins", message));
+            }
+        }
+
+        let database = &self.database;
+        if database.url.is_empty() {
+            let message = "Set it in the config file or as `DATABASE_URL`.".into();
+            return Err(ConfigError::Invalid("database.url", message));
+        }
+        if database.pool_size == 0 {
+            let message = "The pool needs at least one connection.".into();
+            return Err(ConfigError::Invalid("database.pool_size", message));
+        }
+        if database.connection_timeout == 0 {
+            let message = "The timeout must be at least a second.".into();
+            return Err(ConfigError::Invalid("database.connection_timeout", message));
+        }
+
+        let graphql = &self.graphql;
+        if graphql.max_depth == 0 {
+            let message = "Queries need at least one level.".into();
+            return Err(ConfigError::Invalid("graphql.max_depth", message));
+        }
+        if graphql.max_complexity == 0 {
+            let message = "Queries need to cost at least 1.".into();
+            return Err(ConfigError::Invalid("graphql.max_complexity", message));
+        }
+        if let Some((field, _)) = graphql.field_costs.iter().find(|&(_, &cost)| cost == 0) {
+            let message = format!("`{}` must cost at least 1.", field);
+            return Err(ConfigError::Invalid("graphql.field_costs", message));
+        }
+        match graphql.persisted_queries {
+            Some(ref path) if !path.is_file() => {
+                let message = format!("{:?} is not a file.", path);
+                return Err(ConfigError::Invalid("graphql.persisted_queries", message));
+            }
+            None if graphql.persisted_only => {
+                let message = "Set `graphql.persisted_queries` to the allowed queries.".into();
+                return Err(ConfigError::Invalid("graphql.persisted_only", message));
+            }
+            _ => (),
+        }
+
+        let password = &self.password;
+        if password.min_length == 0 {
+            let message = "Passwords need at least one character.".into();
+            return Err(ConfigError::Invalid("password.min_length", message));
+        }
+        if let Some(ref path) = password.breached_list {
+            if !path.exists() {
+                let message = format!("{:?} does not exist.", path);
+                return Err(ConfigError::Invalid("password.breached_list", message));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn bind_address(&self) -> Result<SocketAddr, ConfigError> {
+        self.server.bind.parse().map_err(|_| {
+            let message = format!(
+                "{:?} is not an address like `127.0.0.1:4000`.",
+                self.server.bind
+            );
+            ConfigError::Invalid("server.bind", message)
+        })
+    }
+}
+
+impl DatabaseConfig {
+    pub fn connection_timeout(&self) -> Duration {
+        Duration::from_secs(self.connection_timeout)
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+            .filter(|&seconds| seconds > 0)
+            .map(Duration::from_secs)
+    }
+
+    pub fn max_lifetime(&self) -> Option<Duration> {
+        self.max_lifetime
+            .filter(|&seconds| seconds > 0)
+            .map(Duration::from_secs)
+    }
+}
+
+impl AuthConfig {
+    pub fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl)
+    }
+}
+
+/// Reads and parses an environment variable if it is set.
+fn env_var<T>(name: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|err: T::Err| ConfigError::InvalidEnv(name, value.clone(), err.to_string())),
+        Err(_) => Ok(None),
+    }
+}
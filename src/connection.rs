@@ -0,0 +1,457 @@
+//! Relay style connections for the lists which can grow long. Every list is
+//! sorted with the id as the last key, and a cursor holds the sort key and
+//! the id of an item. A page is loaded as the rows beyond a cursor in the
+//! order of the list rather than at an offset, so that rows inserted or
+//! deleted between requests neither skip nor repeat items, and deep pages
+//! are as fast as the first one.
+
+use base64;
+use chrono::NaiveDateTime;
+use errors::{Error, SResult};
+use models::{
+    test_attempt::TestAttempt, test_paper::TestPaper, test_question::TestQuestion,
+    test_subscription::TestSubscription, user::User,
+};
+use serde_json;
+use Context;
+
+/// Most items in a page, which is also the size of a page when neither
+/// `first` nor `last` is given.
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// Position of an item in its sorted list.
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Cursor {
+    /// Sort key of the item, unless the list is sorted by the ids only.
+    key: Option<SortKey>,
+    id: i32,
+}
+
+/// Value of the key a list is sorted by.
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub enum SortKey {
+    Int(i32),
+    Text(String),
+    Time(NaiveDateTime),
+}
+
+impl Cursor {
+    pub fn new(key: Option<SortKey>, id: i32) -> Cursor {
+        Cursor { key, id }
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// The id of a cursor of a list sorted by the ids only.
+    pub fn id_only(&self) -> SResult<i32> {
+        match self.key {
+            None => Ok(self.id),
+            Some(_) => Err(wrong_sort()),
+        }
+    }
+
+    pub fn key_int(&self) -> SResult<i32> {
+        match self.key {
+            Some(SortKey::Int(key)) => Ok(key),
+            _ => Err(wrong_sort()),
+        }
+    }
+
+    pub fn key_text(&self) -> SResult<String> {
+        match self.key {
+            Some(SortKey::Text(ref key)) => Ok(key.clone()),
+            _ => Err(wrong_sort()),
+        }
+    }
+
+    pub fn key_time(&self) -> SResult<NaiveDateTime> {
+        match self.key {
+            Some(SortKey::Time(key)) => Ok(key),
+            _ => Err(wrong_sort()),
+        }
+    }
+
+    fn encode(&self) -> String {
+        base64::encode(&serde_json::to_string(self).unwrap())
+    }
+
+    fn decode(cursor: &str) -> SResult<Cursor> {
+        base64::decode(cursor)
+            .ok()
+            .and_then(|decoded| serde_json::from_slice(&decoded).ok())
+            .ok_or_else(|| Error::InvalidPage(format!("`{}` is not a valid cursor.", cursor)))
+    }
+}
+
+fn wrong_sort() -> Error {
+    Error::InvalidPage("The cursor is not from a list with this sort.".into())
+}
+
+/// Page of a list selected by the `first`, `after`, `last` and `before`
+/// arguments of a connection field.
+pub struct Page {
+    first: Option<i64>,
+    after: Option<Cursor>,
+    last: Option<i64>,
+    before: Option<Cursor>,
+}
+
+/// Rows of a sorted list to load for a page, which is done by the `seek!`
+/// macro.
+pub struct Seek {
+    /// Loads the rows after this cursor.
+    pub after: Option<Cursor>,
+    /// Loads the rows before this cursor.
+    pub before: Option<Cursor>,
+    /// Loads the rows from the end of the list, in reverse.
+    pub backwards: bool,
+    /// Most rows to load, which is one more than the page needs so that it
+    /// is known whether more follow.
+    pub limit: i64,
+}
+
+impl Page {
+    pub fn new(
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>,
+    ) -> SResult<Page> {
+        let mut first = page_size("first", first)?;
+        let last = page_size("last", last)?;
+        if first.is_none() && last.is_none() {
+            first = Some(MAX_PAGE_SIZE);
+        }
+        Ok(Page {
+            first,
+            after: cursor(after)?,
+            last,
+            before: cursor(before)?,
+        })
+    }
+
+    /// Pages through a list from the start, unless only `last` is given.
+    fn seek(&self) -> Seek {
+        let (backwards, count) = match (self.first, self.last) {
+            (Some(first), _) => (false, first),
+            (None, Some(last)) => (true, last),
+            (None, None) => (false, MAX_PAGE_SIZE),
+        };
+        Seek {
+            after: self.after.clone(),
+            before: self.before.clone(),
+            backwards,
+            limit: count + 1,
+        }
+    }
+}
+
+fn page_size(name: &str, count: Option<i32>) -> SResult<Option<i64>> {
+    match count {
+        Some(count) if count < 0 => Err(Error::InvalidPage(format!(
+            "`{}` cannot be negative.",
+            name
+        ))),
+        Some(count) => Ok(Some(i64::from(count).min(MAX_PAGE_SIZE))),
+        None => Ok(None),
+    }
+}
+
+fn cursor(cursor: Option<String>) -> SResult<Option<Cursor>> {
+    match cursor {
+        Some(cursor) => Cursor::decode(&cursor).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Filters and orders a boxed query to load the rows of a `Seek`, sorted by
+/// the id or by a key and then the id, ascending unless `descending` is
+/// true. The key of the cursors is read with the given method of `Cursor`.
+/// The function it is used in must return an `SResult`.
+macro_rules! seek {
+    ($query:expr, $seek:expr, $descending:expr, $id:expr) => {{
+        let seek: &$crate::connection::Seek = $seek;
+        let descending: bool = $descending;
+        let mut query = $query;
+        for &(cursor, is_after) in &[(seek.after.as_ref(), true), (seek.before.as_ref(), false)] {
+            if let Some(cursor) = cursor {
+                let id = cursor.id_only()?;
+                query = if is_after != descending {
+                    query.filter($id.gt(id))
+                } else {
+                    query.filter($id.lt(id))
+                };
+            }
+        }
+        let query = if seek.backwards != descending {
+            query.order($id.desc())
+        } else {
+            query.order($id.asc())
+        };
+        query.limit(seek.limit)
+    }};
+    ($query:expr, $seek:expr, $descending:expr, $id:expr, $key:expr, $value:ident) => {{
+        let seek: &$crate::connection::Seek = $seek;
+        let descending: bool = $descending;
+        let mut query = $query;
+        for &(cursor, is_after) in &[(seek.after.as_ref(), true), (seek.before.as_ref(), false)] {
+            if let Some(cursor) = cursor {
+                let (key, id) = (cursor.$value()?, cursor.id());
+                query = if is_after != descending {
+                    query.filter($key.gt(key.clone()).or($key.eq(key).and($id.gt(id))))
+                } else {
+                    query.filter($key.lt(key.clone()).or($key.eq(key).and($id.lt(id))))
+                };
+            }
+        }
+        let query = if seek.backwards != descending {
+            query.order(($key.desc(), $id.desc()))
+        } else {
+            query.order(($key.asc(), $id.asc()))
+        };
+        query.limit(seek.limit)
+    }};
+}
+
+pub struct Connection<T> {
+    edges: Vec<Edge<T>>,
+    has_previous_page: bool,
+    has_next_page: bool,
+    total_count: i64,
+}
+
+pub struct Edge<T> {
+    cursor: String,
+    node: T,
+}
+
+pub struct PageInfo {
+    has_previous_page: bool,
+    has_next_page: bool,
+    start_cursor: Option<String>,
+    end_cursor: Option<String>,
+}
+
+impl<T> Connection<T> {
+    /// Loads a page of a list of `total_count` items with the rows of a
+    /// `Seek`, giving every item its cursor. Whether there are items before
+    /// a page loaded forwards, or after one loaded backwards, is only known
+    /// from the cursor it starts from.
+    pub fn load<F, C>(page: &Page, total_count: i64, load: F, cursor: C) -> SResult<Connection<T>>
+    where
+        F: FnOnce(&Seek) -> SResult<Vec<T>>,
+        C: Fn(&T) -> Cursor,
+    {
+        let seek = page.seek();
+        let mut items = load(&seek)?;
+        let has_more = items.len() as i64 >= seek.limit;
+        items.truncate(seek.limit as usize - 1);
+        let (mut has_previous_page, has_next_page) = if seek.backwards {
+            items.reverse();
+            (has_more, page.before.is_some())
+        } else {
+            (page.after.is_some(), has_more)
+        };
+        if let (false, Some(last)) = (seek.backwards, page.last) {
+            let extra = items.len().saturating_sub(last as usize);
+            if extra > 0 {
+                items.drain(..extra);
+                has_previous_page = true;
+            }
+        }
+        Ok(Connection::new(items, cursor, has_previous_page, has_next_page, total_count))
+    }
+
+    /// Pages a list which is already loaded as a whole, in the order of the
+    /// cursors of its items.
+    pub fn from_vec<C>(page: &Page, mut items: Vec<T>, cursor: C) -> Connection<T>
+    where
+        C: Fn(&T) -> Cursor,
+    {
+        let total_count = items.len() as i64;
+        let mut start = match page.after {
+            Some(ref after) => items.iter().take_while(|item| cursor(item) <= *after).count(),
+            None => 0,
+        };
+        let mut end = match page.before {
+            Some(ref before) => items.iter().take_while(|item| cursor(item) < *before).count(),
+            None => items.len(),
+        }.max(start);
+        if let Some(first) = page.first {
+            end = end.min(start + first as usize);
+        }
+        if let Some(last) = page.last {
+            start = start.max(end.saturating_sub(last as usize));
+        }
+        items.truncate(end);
+        let items = items.split_off(start);
+        Connection::new(items, cursor, start > 0, end < total_count as usize, total_count)
+    }
+
+    fn new<C>(
+        items: Vec<T>,
+        cursor: C,
+        has_previous_page: bool,
+        has_next_page: bool,
+        total_count: i64,
+    ) -> Connection<T>
+    where
+        C: Fn(&T) -> Cursor,
+    {
+        let edges = items
+            .into_iter()
+            .map(|node| Edge {
+                cursor: cursor(&node).encode(),
+                node,
+            }).collect();
+        Connection {
+            edges,
+            has_previous_page,
+            has_next_page,
+            total_count,
+        }
+    }
+
+    fn page_info(&self) -> PageInfo {
+        PageInfo {
+            has_previous_page: self.has_previous_page,
+            has_next_page: self.has_next_page,
+            start_cursor: self.edges.first().map(|edge| edge.cursor.clone()),
+            end_cursor: self.edges.last().map(|edge| edge.cursor.clone()),
+        }
+    }
+}
+
+graphql_object!(PageInfo: () |&self| {
+    description: "Position of a page in its list."
+
+    field has_previous_page() -> bool
+        as "Specifies whether there are items before this page."
+    {
+        self.has_previous_page
+    }
+
+    field has_next_page() -> bool
+        as "Specifies whether there are items after this page."
+    {
+        self.has_next_page
+    }
+
+    field start_cursor() -> &Option<String>
+        as "Cursor of the first item of this page."
+    {
+        &self.start_cursor
+    }
+
+    field end_cursor() -> &Option<String>
+        as "Cursor of the last item of this page."
+    {
+        &self.end_cursor
+    }
+});
+
+/// Exposes the connection and the edge of a type of items to GraphQL.
+macro_rules! connection {
+    ($node:ty, $connection:tt, $edge:tt) => {
+        graphql_object!(Connection<$node>: Context as $connection |&self| {
+            description: "A page of a list."
+
+            field edges() -> &Vec<Edge<$node>>
+                as "Items of this page with their cursors."
+            {
+                &self.edges
+            }
+
+            field page_info() -> PageInfo
+                as "Position of this page in the list."
+            {
+                self.page_info()
+            }
+
+            field total_count() -> i32
+                as "Number of items in the whole list."
+            {
+                self.total_count as i32
+            }
+        });
+
+        graphql_object!(Edge<$node>: Context as $edge |&self| {
+            description: "An item of a page with its cursor."
+
+            field cursor() -> &str
+                as "Cursor to page from this item onwards."
+            {
+                &self.cursor
+            }
+
+            field node() -> &$node
+                as "The item."
+            {
+                &self.node
+            }
+        });
+    };
+}
+
+connection!(User, "UserConnection", "UserEdge");
+connection!(TestPaper, "TestPaperConnection", "TestPaperEdge");
+connection!(TestQuestion, "TestQuestionConnection", "TestQuestionEdge");
+connection!(TestAttempt, "TestAttemptConnection", "TestAttemptEdge");
+connection!(TestSubscription, "TestSubscriptionConnection", "TestSubscriptionEdge");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(connection: &Connection<i32>) -> Vec<i32> {
+        connection.edges.iter().map(|edge| edge.node).collect()
+    }
+
+    fn cursor_of(id: &i32) -> Cursor {
+        Cursor::new(Some(SortKey::Int(id % 3)), *id)
+    }
+
+    #[test]
+    fn pages_through_a_loaded_list() {
+        let mut items: Vec<i32> = (1..11).collect();
+        items.sort_by(|a, b| cursor_of(a).partial_cmp(&cursor_of(b)).unwrap());
+        let first = Connection::from_vec(
+            &Page::new(Some(4), None, None, None).unwrap(),
+            items.clone(),
+            cursor_of,
+        );
+        assert_eq!(ids(&first), vec![3, 6, 9, 1]);
+        let info = first.page_info();
+        assert!(!info.has_previous_page && info.has_next_page);
+
+        let second = Connection::from_vec(
+            &Page::new(Some(4), info.end_cursor, None, None).unwrap(),
+            items.clone(),
+            cursor_of,
+        );
+        assert_eq!(ids(&second), vec![4, 7, 10, 2]);
+
+        let before = second.page_info().start_cursor;
+        let previous = Connection::from_vec(
+            &Page::new(None, None, Some(2), before).unwrap(),
+            items,
+            cursor_of,
+        );
+        assert_eq!(ids(&previous), vec![9, 1]);
+        let info = previous.page_info();
+        assert!(info.has_previous_page && info.has_next_page);
+    }
+
+    #[test]
+    fn decodes_only_its_own_cursors() {
+        let cursor = Cursor::new(Some(SortKey::Text("name".into())), 7);
+        let decoded = Cursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded, cursor);
+        assert_eq!(decoded.key_text().unwrap(), "name");
+        assert!(decoded.key_time().is_err());
+        assert!(decoded.id_only().is_err());
+        assert!(Cursor::decode(&base64::encode("offset:3")).is_err());
+    }
+}
@@ -4,10 +4,18 @@
 #[postgres(type_name = "USER_TYPE")]
 pub struct User_type;
 
-#[derive(SqlType)]
+#[derive(SqlType, QueryId)]
 #[postgres(type_name = "GENDER_TYPE")]
 pub struct Gender_type;
 
-#[derive(SqlType)]
+#[derive(SqlType, QueryId)]
 #[postgres(type_name = "TEST_TYPE")]
 pub struct Test_type;
+
+#[derive(SqlType, QueryId)]
+#[postgres(type_name = "DIFFICULTY_TYPE")]
+pub struct Difficulty_type;
+
+#[derive(SqlType, QueryId)]
+#[postgres(type_name = "PAPER_STATUS")]
+pub struct Paper_status;
@@ -1,6 +1,14 @@
 use bcrypt::BcryptError;
+use csv;
 use diesel::result::{DatabaseErrorKind, Error as DieselError};
-use juniper::{FieldError, IntoFieldError};
+use diesel_migrations::RunMigrationsError;
+use juniper::{FieldError, IntoFieldError, Value};
+use password_policy::Weakness;
+use serde_json;
+use std::io;
+use uuid::Uuid;
+use validation::Violation;
+use zip::result::ZipError;
 
 #[derive(Debug, Fail)]
 pub enum Error {
@@ -8,12 +16,58 @@ pub enum Error {
     Diesel(#[cause] DieselError),
     #[fail(display = "BcryptError: {}", _0)]
     Bcrypt(#[cause] BcryptError),
+    #[fail(display = "ZipError: {}", _0)]
+    Zip(#[cause] ZipError),
+    #[fail(display = "CsvError: {}", _0)]
+    Csv(#[cause] csv::Error),
+    #[fail(display = "JsonError: {}", _0)]
+    Json(#[cause] serde_json::Error),
+    #[fail(display = "RunMigrationsError: {}", _0)]
+    Migration(#[cause] RunMigrationsError),
+    #[fail(display = "IoError: {}", _0)]
+    Io(#[cause] io::Error),
     #[fail(display = "Password for the user is incorrect.")]
     IncorrectPassword,
     #[fail(display = "An unauthorized request.")]
     Unauthorized,
     #[fail(display = "Cannot change the user type of the last admin.")]
     LastAdmin,
+    #[fail(display = "The given order does not list every item exactly once.")]
+    InvalidOrder,
+    #[fail(display = "A question bank does not have enough questions to draw from.")]
+    InsufficientQuestions,
+    #[fail(display = "The test paper has no questions or some have no correct option.")]
+    NotPublishable(Vec<Uuid>),
+    #[fail(display = "The test paper is not published.")]
+    NotPublished,
+    #[fail(display = "Only the latest version of a test paper can be edited.")]
+    NotLatestVersion,
+    #[fail(display = "The test paper has already been attempted.")]
+    AlreadyAttempted,
+    #[fail(display = "Line {}: {}", line, message)]
+    Import { line: usize, message: String },
+    #[fail(display = "Invalid QTI package: {}", _0)]
+    InvalidPackage(String),
+    #[fail(display = "Backups of version {} are not supported.", _0)]
+    UnsupportedBackup(u32),
+    #[fail(display = "Invalid page: {}", _0)]
+    InvalidPage(String),
+    #[fail(display = "The query is over the {} limit of {}.", limit, max)]
+    QueryTooComplex { limit: &'static str, max: u64 },
+    #[fail(display = "The query could not be read.")]
+    InvalidQuery,
+    #[fail(display = "The persisted query is not found.")]
+    PersistedQueryNotFound,
+    #[fail(display = "The query does not match the hash of the persisted query.")]
+    PersistedQueryMismatch,
+    #[fail(display = "Only persisted queries are allowed.")]
+    QueryNotAllowed,
+    #[fail(display = "Some fields of the input are invalid.")]
+    Validation(Vec<Violation>),
+    #[fail(display = "The password is too weak.")]
+    WeakPassword(Vec<Weakness>),
+    #[fail(display = "The item has been changed since it was loaded.")]
+    StaleUpdate,
 }
 
 impl From<DieselError> for Error {
@@ -28,6 +82,36 @@ impl From<BcryptError> for Error {
     }
 }
 
+impl From<ZipError> for Error {
+    fn from(err: ZipError) -> Error {
+        Error::Zip(err)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Error {
+        Error::Csv(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Json(err)
+    }
+}
+
+impl From<RunMigrationsError> for Error {
+    fn from(err: RunMigrationsError) -> Error {
+        Error::Migration(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
 impl IntoFieldError for Error {
     fn into_field_error(self) -> FieldError {
         match self {
@@ -74,6 +158,26 @@ impl IntoFieldError for Error {
                 error!("BcryptError: {:?}", err);
                 internal_server_error()
             }
+            Error::Zip(err) => {
+                error!("ZipError: {:?}", err);
+                internal_server_error()
+            }
+            Error::Csv(err) => {
+                error!("CsvError: {:?}", err);
+                internal_server_error()
+            }
+            Error::Json(err) => {
+                error!("JsonError: {:?}", err);
+                internal_server_error()
+            }
+            Error::Migration(err) => {
+                error!("RunMigrationsError: {:?}", err);
+                internal_server_error()
+            }
+            Error::Io(err) => {
+                error!("IoError: {:?}", err);
+                internal_server_error()
+            }
             Error::IncorrectPassword => FieldError::new(
                 "Given password was incorrect.",
                 graphql_value!({
@@ -92,6 +196,161 @@ impl IntoFieldError for Error {
                     "kind": "LAST_ADMIN"
                 }),
             ),
+            Error::InvalidOrder => FieldError::new(
+                "The given order does not list every item exactly once.",
+                graphql_value!({
+                    "kind": "INVALID_ORDER"
+                }),
+            ),
+            Error::InsufficientQuestions => FieldError::new(
+                "A question bank does not have enough questions to draw from.",
+                graphql_value!({
+                    "kind": "INSUFFICIENT_QUESTIONS"
+                }),
+            ),
+            Error::NotPublishable(questions) => {
+                let message = if questions.is_empty() {
+                    "The test paper has no questions."
+                } else {
+                    "Some questions of the test paper have no correct option."
+                };
+                let questions = Value::List(
+                    questions
+                        .iter()
+                        .map(|id| Value::String(id.to_string()))
+                        .collect(),
+                );
+                FieldError::new(
+                    message,
+                    graphql_value!({
+                        "kind": "NOT_PUBLISHABLE",
+                        "questions": questions
+                    }),
+                )
+            }
+            Error::NotPublished => FieldError::new(
+                "The test paper is not published.",
+                graphql_value!({
+                    "kind": "NOT_PUBLISHED"
+                }),
+            ),
+            Error::NotLatestVersion => FieldError::new(
+                "Only the latest version of a test paper can be edited.",
+                graphql_value!({
+                    "kind": "NOT_LATEST_VERSION"
+                }),
+            ),
+            Error::AlreadyAttempted => FieldError::new(
+                "The test paper has already been attempted. Archive it instead.",
+                graphql_value!({
+                    "kind": "ALREADY_ATTEMPTED"
+                }),
+            ),
+            Error::Import { line, message } => {
+                let line = line as i32;
+                FieldError::new(
+                    format!("Line {}: {}", line, message),
+                    graphql_value!({
+                        "kind": "IMPORT_ERROR",
+                        "line": line
+                    }),
+                )
+            }
+            Error::InvalidPackage(message) => FieldError::new(
+                message,
+                graphql_value!({
+                    "kind": "INVALID_PACKAGE"
+                }),
+            ),
+            Error::UnsupportedBackup(version) => FieldError::new(
+                format!("Backups of version {} are not supported.", version),
+                graphql_value!({
+                    "kind": "UNSUPPORTED_BACKUP"
+                }),
+            ),
+            Error::InvalidPage(message) => FieldError::new(
+                message,
+                graphql_value!({
+                    "kind": "INVALID_PAGE"
+                }),
+            ),
+            Error::QueryTooComplex { limit, max } => {
+                let max = max.min(i32::max_value() as u64) as i32;
+                FieldError::new(
+                    format!("The query is over the {} limit of {}.", limit, max),
+                    graphql_value!({
+                        "kind": "QUERY_TOO_COMPLEX",
+                        "limit": limit,
+                        "max": max
+                    }),
+                )
+            }
+            Error::InvalidQuery => FieldError::new(
+                "The query could not be read.",
+                graphql_value!({
+                    "kind": "INVALID_QUERY"
+                }),
+            ),
+            // Apollo clients retry with the full query on this message.
+            Error::PersistedQueryNotFound => FieldError::new(
+                "PersistedQueryNotFound",
+                graphql_value!({
+                    "kind": "PERSISTED_QUERY_NOT_FOUND"
+                }),
+            ),
+            Error::PersistedQueryMismatch => FieldError::new(
+                "The query does not match the hash of the persisted query.",
+                graphql_value!({
+                    "kind": "PERSISTED_QUERY_MISMATCH"
+                }),
+            ),
+            Error::QueryNotAllowed => FieldError::new(
+                "Only persisted queries are allowed.",
+                graphql_value!({
+                    "kind": "QUERY_NOT_ALLOWED"
+                }),
+            ),
+            Error::Validation(violations) => {
+                let errors = Value::List(violations.iter().map(Violation::to_value).collect());
+                FieldError::new(
+                    "Some fields of the input are invalid.",
+                    graphql_value!({
+                        "kind": "VALIDATION_ERROR",
+                        "errors": errors
+                    }),
+                )
+            }
+            Error::StaleUpdate => FieldError::new(
+                "The item has been changed since it was loaded. Reload it and try again.",
+                graphql_value!({
+                    "kind": "STALE_UPDATE"
+                }),
+            ),
+            Error::WeakPassword(weaknesses) => {
+                let messages: Vec<_> = weaknesses
+                    .iter()
+                    .map(|weakness| weakness.message())
+                    .collect();
+                let reasons = Value::List(
+                    weaknesses
+                        .iter()
+                        .map(|weakness| {
+                            let code = weakness.code();
+                            let message = weakness.message();
+                            graphql_value!({
+                                "code": code,
+                                "message": message
+                            })
+                        }).collect(),
+                );
+                FieldError::new(
+                    format!("The password is too weak. {}", messages.join(" ")),
+                    graphql_value!({
+                        "kind": "WEAK_PASSWORD",
+                        "reasons": reasons
+                    }),
+                )
+            }
         }
     }
 }
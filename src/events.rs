@@ -0,0 +1,116 @@
+//! Broadcast of what happens to the tests, which the live subscriptions
+//! listen to. Every subscriber gets every event and picks the ones it wants.
+
+use chrono::{NaiveDateTime, Utc};
+use futures::sync::mpsc::{self, Receiver, Sender};
+use models::{test_attempt::TestAttempt, test_schedule::TestSchedule, user::User};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// Seconds between two ticks, which is how often the remaining time of a
+/// test is sent.
+const TICK_INTERVAL: u64 = 1;
+
+/// Events a subscriber may fall behind by. A subscriber which is this far
+/// behind misses the ticks, and is dropped when it would miss anything else.
+const SUBSCRIBER_BUFFER: usize = 32;
+
+lazy_static! {
+    static ref SUBSCRIBERS: Mutex<Vec<Sender<Arc<Event>>>> = Mutex::new(Vec::new());
+}
+
+pub enum Event {
+    /// Time has passed, so schedules may have started or ended.
+    Tick,
+    /// A schedule was moved, or deleted when it has no time any more.
+    ScheduleChanged {
+        test_schedule_id: i32,
+        time: Option<(NaiveDateTime, NaiveDateTime)>,
+    },
+    Attempt(AttemptEvent),
+}
+
+#[derive(Clone)]
+pub struct AttemptEvent {
+    pub kind: AttemptEventKind,
+    pub test_schedule_id: i32,
+    pub test_attempt_id: Uuid,
+    pub user_id: Uuid,
+    pub time: NaiveDateTime,
+}
+
+/// What happened to a test attempt.
+#[derive(Clone, Copy, GraphQLEnum)]
+pub enum AttemptEventKind {
+    Started,
+    Finished,
+    Withdrawn,
+}
+
+impl Event {
+    pub fn schedule_changed(schedule: &TestSchedule) -> Event {
+        Event::ScheduleChanged {
+            test_schedule_id: schedule.id,
+            time: Some((schedule.time, schedule.end_time())),
+        }
+    }
+
+    pub fn schedule_deleted(schedule: &TestSchedule) -> Event {
+        Event::ScheduleChanged {
+            test_schedule_id: schedule.id,
+            time: None,
+        }
+    }
+
+    pub fn attempt(kind: AttemptEventKind, attempt: &TestAttempt, user: &User) -> Event {
+        Event::Attempt(AttemptEvent {
+            kind,
+            test_schedule_id: attempt.test_schedule_id,
+            test_attempt_id: attempt.uuid,
+            user_id: user.uuid,
+            time: Utc::now().naive_utc(),
+        })
+    }
+}
+
+/// Sends an event to every subscriber, forgetting the ones which are gone or
+/// too far behind to take anything but a tick.
+pub fn publish(event: Event) {
+    let event = Arc::new(event);
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    let mut kept = Vec::with_capacity(subscribers.len());
+    for mut subscriber in subscribers.drain(..) {
+        match subscriber.try_send(event.clone()) {
+            Ok(()) => kept.push(subscriber),
+            Err(ref err) if err.is_full() => {
+                if let Event::Tick = *event {
+                    kept.push(subscriber);
+                } else {
+                    debug!("Dropped a subscriber which is too far behind.");
+                }
+            }
+            Err(_) => (),
+        }
+    }
+    *subscribers = kept;
+}
+
+/// Every event published from now on, until the receiver is dropped or falls
+/// too far behind.
+pub fn subscribe() -> Receiver<Arc<Event>> {
+    let (sender, receiver) = mpsc::channel(SUBSCRIBER_BUFFER);
+    SUBSCRIBERS.lock().unwrap().push(sender);
+    receiver
+}
+
+/// Publishes the ticks on a thread of its own.
+pub fn start_ticker() {
+    thread::spawn(|| loop {
+        thread::sleep(Duration::from_secs(TICK_INTERVAL));
+        publish(Event::Tick);
+    });
+}
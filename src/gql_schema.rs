@@ -1,12 +1,23 @@
 use errors::SResult;
+use events::{self, AttemptEventKind, Event};
+use base64;
+use connection::{Connection, Page};
+use import::TestPaperImport;
 use juniper::RootNode;
 use models::{
-    test_paper::{TestPaper, TestPaperForm, TestPaperUpdate},
+    question_bank::{QuestionBank, QuestionBankForm, QuestionBankUpdate},
+    test_paper::{TestPaper, TestPaperFilter, TestPaperForm, TestPaperSort, TestPaperUpdate},
+    test_question::{QuestionOwner, TestQuestion},
     test_schedule::{TestSchedule, TestScheduleForm, TestScheduleUpdate},
-    user::{User, UserCredentialsUpdate, UserForm, UserInfoUpdate, UserTypeUpdate},
+    user::{
+        User, UserCredentialsUpdate, UserFilter, UserForm, UserInfoUpdate, UserSort,
+        UserTypeUpdate,
+    },
     test_attempt::{StartTest, TestAttempt, TestAttemptPatch},
     question_answer::{ProvideAnswer, QuestionAnswer}
 };
+use qti::{self, QtiImport, QtiImportResult};
+use search::{self, QuestionHit, UserHit};
 use uuid::Uuid;
 use Context;
 
@@ -21,12 +32,28 @@ graphql_object!(Query: Context | &self | {
         executor.context().auth_user()
     }
 
-    field users(&executor, query: Option<String>) -> SResult<Vec<User>> 
-        as "Gets all the users based on the given query." 
+    field users(
+        &executor,
+        query: Option<String>,
+        filter: Option<UserFilter>,
+        sort: Option<UserSort>,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>
+    ) -> SResult<Connection<User>> 
+        as "Gets a page of the users based on the given query, filter and sort." 
     {
         let ctx = executor.context();
         ctx.admin_only()?;
-        User::find_all(query, &ctx.conn)
+        let page = Page::new(first, after, last, before)?;
+        let total_count = User::count_all(query.clone(), filter.as_ref(), &ctx.conn)?;
+        Connection::load(
+            &page,
+            total_count,
+            |seek| User::find_page(query, filter.as_ref(), sort.as_ref(), seek, &ctx.conn),
+            |user| user.cursor(sort.as_ref()),
+        )
     }
 
     field user(&executor, id: Uuid) -> SResult<User> 
@@ -37,16 +64,93 @@ graphql_object!(Query: Context | &self | {
         User::find_by_uuid(id, &ctx.conn)
     }
 
-    field test_papers(&executor) -> SResult<Vec<TestPaper>> 
-        as "Gets all the test papers." 
+    field search_users(&executor, query: String, first: Option<i32>) -> SResult<Vec<UserHit>> 
+        as "Searches users by their names, emails and contacts, tolerating typos. The most relevant come first." 
     {
-        TestPaper::find_all(&executor.context().conn)
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        search::search_users(&query, first, &ctx.conn)
+    }
+
+    field search_questions(&executor, query: String, first: Option<i32>) -> SResult<Vec<QuestionHit>> 
+        as "Searches questions by their text and the text of their options, tolerating typos. The most relevant come first." 
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        search::search_questions(&query, first, &ctx.conn)
+    }
+
+    field test_papers(
+        &executor,
+        filter: Option<TestPaperFilter>,
+        sort: Option<TestPaperSort>,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>
+    ) -> SResult<Connection<TestPaper>> 
+        as "Gets a page of the test papers. Only admins get the unpublished ones." 
+    {
+        let ctx = executor.context();
+        let page = Page::new(first, after, last, before)?;
+        let published_only = !ctx.is_admin();
+        let filter = filter.as_ref();
+        let total_count = TestPaper::count_latest(published_only, filter, &ctx.conn)?;
+        Connection::load(
+            &page,
+            total_count,
+            |seek| {
+                let test_papers =
+                    TestPaper::find_page(published_only, filter, sort.as_ref(), seek, &ctx.conn)?;
+                ctx.loaders.register_test_papers(&test_papers);
+                Ok(test_papers)
+            },
+            |test_paper| test_paper.cursor(sort.as_ref()),
+        )
     }
 
     field test_paper(&executor, id: Uuid) -> SResult<TestPaper> 
-        as "Gets a test paper with the given id." 
+        as "Gets a test paper with the given id. Only admins get the unpublished ones." 
     {
-        TestPaper::find_by_uuid(id, &executor.context().conn)
+        let ctx = executor.context();
+        if ctx.is_admin() {
+            TestPaper::find_by_uuid(id, &ctx.conn)
+        } else {
+            TestPaper::find_published_by_uuid(id, &ctx.conn)
+        }
+    }
+
+    field test_paper_qti(&executor, id: Uuid) -> SResult<String> 
+        as "Exports a test paper with the given id as a base64 encoded QTI 2.1 package." 
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        let test_paper = TestPaper::find_by_uuid(id, &ctx.conn)?;
+        Ok(base64::encode(&qti::export(&test_paper, &ctx.conn)?))
+    }
+
+    field question_banks(&executor) -> SResult<Vec<QuestionBank>> 
+        as "Gets all the question banks." 
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        QuestionBank::find_all(&ctx.conn)
+    }
+
+    field question_bank(&executor, id: Uuid) -> SResult<QuestionBank> 
+        as "Gets a question bank with the given id." 
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        QuestionBank::find_by_uuid(id, &ctx.conn)
+    }
+
+    field test_attempt(&executor, id: Uuid) -> SResult<TestAttempt> 
+        as "Gets a test attempt with the given id. Used to review an attempt as the candidate saw it." 
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        TestAttempt::find_by_uuid(id, &ctx.conn)
     }
 });
 
@@ -117,6 +221,96 @@ graphql_object!(Mutation: Context | &self | {
         TestPaper::delete_by_uuid(id, &ctx.conn)
     }
 
+    field import_test_paper(&executor, test_paper: TestPaperImport) -> SResult<TestPaper> 
+        as "Creates a new test paper from questions written in the GIFT or the Aiken format."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        test_paper.parse()?.save(&ctx.conn)
+    }
+
+    field import_qti_package(&executor, test_paper: QtiImport) -> SResult<QtiImportResult> 
+        as "Creates a new test paper from a QTI 2.1 package, skipping the unsupported items."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        test_paper.save(&ctx.conn)
+    }
+
+    field clone_test_paper(&executor, id: Uuid, name: String, include_schedules: Option<bool>) -> SResult<TestPaper> 
+        as "Copies a test paper with all its questions and options into a new draft. When the schedules are included, the copy keeps the status of the original instead."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        TestPaper::clone_by_uuid(id, name, include_schedules.unwrap_or_default(), &ctx.conn)
+    }
+
+    field publish_test_paper(&executor, id: Uuid, lock_version: i32) -> SResult<TestPaper> 
+        as "Publishes a test paper which has questions, each with a correct option."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        TestPaper::publish(id, lock_version, &ctx.conn)
+    }
+
+    field archive_test_paper(&executor, id: Uuid, lock_version: i32) -> SResult<TestPaper> 
+        as "Archives a test paper so that it is no longer listed."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        TestPaper::archive(id, lock_version, &ctx.conn)
+    }
+
+    field reorder_questions(&executor, test_paper_id: Uuid, lock_version: i32, ids: Vec<Uuid>) -> SResult<TestPaper> 
+        as "Reorders the questions of a test paper. Every question must be listed once."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        TestPaper::reorder_questions(test_paper_id, lock_version, ids, &ctx.conn)
+    }
+
+    field create_question_bank(&executor, question_bank: QuestionBankForm) -> SResult<QuestionBank> 
+        as "Creates a new question bank."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        question_bank.save(&ctx.conn)
+    }
+
+    field update_question_bank(&executor, question_bank: QuestionBankUpdate) -> SResult<QuestionBank> 
+        as "Updates a question bank."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        question_bank.save(&ctx.conn)
+    }
+
+    field delete_question_bank(&executor, id: Uuid) -> SResult<QuestionBank> 
+        as "Deletes a question bank with the given id."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        QuestionBank::delete_by_uuid(id, &ctx.conn)
+    }
+
+    field reorder_bank_questions(&executor, question_bank_id: Uuid, ids: Vec<Uuid>) -> SResult<QuestionBank> 
+        as "Reorders the questions of a question bank. Every question must be listed once."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        let question_bank = QuestionBank::find_by_uuid(question_bank_id, &ctx.conn)?;
+        TestQuestion::reorder(ids, QuestionOwner::QuestionBank(question_bank.id), &ctx.conn)?;
+        Ok(question_bank)
+    }
+
+    field reorder_options(&executor, test_question_id: Uuid, lock_version: i32, ids: Vec<Uuid>) -> SResult<TestQuestion> 
+        as "Reorders the options of a question. Every option must be listed once."
+    {
+        let ctx = executor.context();
+        ctx.admin_only()?;
+        TestQuestion::reorder_options(test_question_id, lock_version, ids, &ctx.conn)
+    }
+
     field create_test_schedule(&executor, schedule: TestScheduleForm) -> SResult<TestSchedule> 
         as "Creates a new test schedule."
     {
@@ -130,7 +324,9 @@ graphql_object!(Mutation: Context | &self | {
     {
         let ctx = executor.context();
         ctx.admin_only()?;
-        schedule.save(&ctx.conn)
+        let schedule = schedule.save(&ctx.conn)?;
+        events::publish(Event::schedule_changed(&schedule));
+        Ok(schedule)
     }
 
     field delete_test_schedule(&executor, id: Uuid) -> SResult<TestSchedule> 
@@ -138,7 +334,9 @@ graphql_object!(Mutation: Context | &self | {
     {
         let ctx = executor.context();
         ctx.admin_only()?;
-        TestSchedule::delete_by_uuid(id, &ctx.conn)
+        let schedule = TestSchedule::delete_by_uuid(id, &ctx.conn)?;
+        events::publish(Event::schedule_deleted(&schedule));
+        Ok(schedule)
     }
 
     field start_test(&executor, test: StartTest) -> SResult<TestAttempt> 
@@ -146,7 +344,9 @@ graphql_object!(Mutation: Context | &self | {
     {
         let ctx = executor.context();
         let user = ctx.auth_user()?;
-        test.save(user.id, &ctx.conn)
+        let attempt = test.save(user.id, &ctx.conn)?;
+        events::publish(Event::attempt(AttemptEventKind::Started, &attempt, user));
+        Ok(attempt)
     }
 
     field leave_test(&executor, test_room_id: Uuid) -> SResult<TestAttempt> 
@@ -154,7 +354,9 @@ graphql_object!(Mutation: Context | &self | {
     {
         let ctx = executor.context();
         let user = ctx.auth_user()?;
-        TestAttemptPatch::leave().save(test_room_id, user.id, &ctx.conn)
+        let attempt = TestAttemptPatch::leave().save(test_room_id, user.id, &ctx.conn)?;
+        events::publish(Event::attempt(AttemptEventKind::Withdrawn, &attempt, user));
+        Ok(attempt)
     }
 
     field finish_test(&executor, test_room_id: Uuid) -> SResult<TestAttempt> 
@@ -162,7 +364,9 @@ graphql_object!(Mutation: Context | &self | {
     {
         let ctx = executor.context();
         let user = ctx.auth_user()?;
-        TestAttemptPatch::finish().save(test_room_id, user.id, &ctx.conn)
+        let attempt = TestAttemptPatch::finish().save(test_room_id, user.id, &ctx.conn)?;
+        events::publish(Event::attempt(AttemptEventKind::Finished, &attempt, user));
+        Ok(attempt)
     }
 
     field answer_the_question(&executor, answer: ProvideAnswer) -> SResult<QuestionAnswer> 
@@ -0,0 +1,230 @@
+//! GraphQL requests over HTTP, read the same way as `juniper_warp` reads
+//! them, but executed here so that persisted queries are looked up, and
+//! every operation is checked against the query limits and timed. The checks
+//! come before the context, so that rejected requests never take a database
+//! connection nor verify a password.
+
+use errors::Error;
+use gql_schema::Schema;
+use juniper::{http::GraphQLRequest, InputValue, IntoFieldError};
+use metrics;
+use persisted_queries::PersistedQueries;
+use query_limits::QueryLimits;
+use serde_json::{self, Value};
+use std::time::Instant;
+use warp::{reject::server_error, Rejection};
+use Context;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationRequest {
+    query: Option<String>,
+    operation_name: Option<String>,
+    variables: Option<InputValue>,
+    extensions: Option<Extensions>,
+}
+
+/// Extensions of a request, as Apollo clients send them.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Extensions {
+    persisted_query: Option<PersistedQuery>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersistedQuery {
+    sha256_hash: String,
+}
+
+impl Extensions {
+    /// Hash of the persisted query the request refers to.
+    pub fn persisted_hash(&self) -> Option<&str> {
+        self.persisted_query
+            .as_ref()
+            .map(|persisted_query| persisted_query.sha256_hash.as_str())
+    }
+}
+
+/// Body of a `POST` request, which may batch several operations.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum BatchRequest {
+    Single(OperationRequest),
+    Batch(Vec<OperationRequest>),
+}
+
+/// Query string of a `GET` request, where the variables and the extensions
+/// are JSON encoded.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetRequest {
+    query: Option<String>,
+    operation_name: Option<String>,
+    variables: Option<String>,
+    extensions: Option<String>,
+}
+
+impl GetRequest {
+    pub fn parse(self) -> Result<BatchRequest, serde_json::Error> {
+        let variables = match self.variables {
+            Some(variables) => Some(serde_json::from_str(&variables)?),
+            None => None,
+        };
+        let extensions = match self.extensions {
+            Some(extensions) => Some(serde_json::from_str(&extensions)?),
+            None => None,
+        };
+        Ok(BatchRequest::Single(OperationRequest {
+            query: self.query,
+            operation_name: self.operation_name,
+            variables,
+            extensions,
+        }))
+    }
+}
+
+/// An operation which has been looked up and checked, waiting for a context
+/// to be executed in, or the response of its rejection.
+pub enum PreparedOperation {
+    Ready {
+        request: GraphQLRequest,
+        name: Option<String>,
+        start: Instant,
+    },
+    Rejected(Value),
+}
+
+impl OperationRequest {
+    fn prepare(self, persisted: &PersistedQueries, limits: &QueryLimits) -> PreparedOperation {
+        let start = Instant::now();
+        let OperationRequest {
+            query,
+            operation_name,
+            variables,
+            extensions,
+        } = self;
+        let hash = extensions.as_ref().and_then(Extensions::persisted_hash);
+        let name = operation_name.as_ref().map(String::as_str);
+        let checked = persisted.resolve(query, hash).and_then(|query| {
+            limits.check(&query, name, variables.as_ref())?;
+            Ok(query)
+        });
+        match checked {
+            Ok(query) => PreparedOperation::Ready {
+                name: operation_name.clone(),
+                request: GraphQLRequest::new(query, operation_name, variables),
+                start,
+            },
+            Err(err) => {
+                metrics::observe_operation(name, false, start.elapsed());
+                PreparedOperation::Rejected(error_body(err))
+            }
+        }
+    }
+}
+
+impl PreparedOperation {
+    fn is_ready(&self) -> bool {
+        match *self {
+            PreparedOperation::Ready { .. } => true,
+            PreparedOperation::Rejected(_) => false,
+        }
+    }
+
+    /// Executes the operation, unless it has been rejected in which case
+    /// there is no context.
+    fn execute(
+        self,
+        schema: &Schema,
+        ctx: Option<&Context>,
+    ) -> Result<(Value, bool), serde_json::Error> {
+        match self {
+            PreparedOperation::Ready {
+                request,
+                name,
+                start,
+            } => {
+                let ctx = ctx.expect("A context is created for the ready operations.");
+                let response = request.execute(schema, ctx);
+                let is_ok = response.is_ok();
+                let name = name.as_ref().map(String::as_str);
+                metrics::observe_operation(name, is_ok, start.elapsed());
+                Ok((serde_json::to_value(&response)?, is_ok))
+            }
+            PreparedOperation::Rejected(response) => Ok((response, false)),
+        }
+    }
+}
+
+impl BatchRequest {
+    /// Looks the persisted queries up and checks the operations against the
+    /// query limits, before any connection or credentials are needed.
+    pub fn prepare(self, persisted: &PersistedQueries, limits: &QueryLimits) -> PreparedBatch {
+        match self {
+            BatchRequest::Single(request) => {
+                PreparedBatch::Single(request.prepare(persisted, limits))
+            }
+            BatchRequest::Batch(requests) => PreparedBatch::Batch(
+                requests
+                    .into_iter()
+                    .map(|request| request.prepare(persisted, limits))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// A request whose operations have been checked.
+pub enum PreparedBatch {
+    Single(PreparedOperation),
+    Batch(Vec<PreparedOperation>),
+}
+
+impl PreparedBatch {
+    /// Executes the operations one after the other, responding with the JSON
+    /// body and whether every operation has succeeded. The context is only
+    /// created when some operation has passed the checks.
+    pub fn execute<F>(self, schema: &Schema, context: F) -> Result<(Vec<u8>, bool), Rejection>
+    where
+        F: FnOnce() -> Result<Context, Rejection>,
+    {
+        let is_ready = match self {
+            PreparedBatch::Single(ref operation) => operation.is_ready(),
+            PreparedBatch::Batch(ref operations) => operations.iter().any(|op| op.is_ready()),
+        };
+        let ctx = if is_ready { Some(context()?) } else { None };
+        let ctx = ctx.as_ref();
+        let body = match self {
+            PreparedBatch::Single(operation) => operation
+                .execute(schema, ctx)
+                .and_then(|(response, is_ok)| Ok((serde_json::to_vec(&response)?, is_ok))),
+            PreparedBatch::Batch(operations) => {
+                let mut responses = Vec::with_capacity(operations.len());
+                let mut all_ok = true;
+                for operation in operations {
+                    let (response, is_ok) = operation
+                        .execute(schema, ctx)
+                        .map_err(|_| server_error())?;
+                    responses.push(response);
+                    all_ok &= is_ok;
+                }
+                serde_json::to_vec(&responses).map(|body| (body, all_ok))
+            }
+        };
+        body.map_err(|_| server_error())
+    }
+}
+
+/// Body of a response failing with an error, the same as the one of a
+/// GraphQL error.
+pub fn error_body(err: Error) -> Value {
+    let err = err.into_field_error();
+    json!({
+        "data": null,
+        "errors": [{
+            "message": err.message(),
+            "extensions": err.extensions(),
+        }]
+    })
+}
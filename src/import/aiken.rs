@@ -0,0 +1,202 @@
+//! The Aiken format lists a question, its lettered options and the letter of
+//! the correct option:
+//!
+//! ```text
+//! Which planet is the largest?
+//! A. Mars
+//! B. Jupiter
+//! C. Venus
+//! ANSWER: B
+//! ```
+
+use super::{blocks, import_error};
+use errors::SResult;
+use models::{question_option::QuestionOptionForm, test_question::TestQuestionForm};
+
+pub fn parse(content: &str) -> SResult<Vec<TestQuestionForm>> {
+    blocks(content, |_| false)
+        .into_iter()
+        .map(|lines| parse_question(&lines))
+        .collect()
+}
+
+fn parse_question(lines: &[(usize, &str)]) -> SResult<TestQuestionForm> {
+    let mut question: Vec<&str> = Vec::new();
+    let mut options: Vec<(char, QuestionOptionForm)> = Vec::new();
+    let mut answer = None;
+
+    for &(line_no, line) in lines {
+        let line = line.trim();
+        if answer.is_some() {
+            return import_error(line_no, "Expected a blank line after the answer.");
+        }
+
+        if let Some(letter) = parse_answer(line) {
+            answer = Some((line_no, letter));
+        } else if let Some((letter, text)) = parse_option(line) {
+            if question.is_empty() {
+                return import_error(line_no, "Expected a question before the options.");
+            }
+            if options.iter().any(|&(existing, _)| existing == letter) {
+                return import_error(line_no, format!("Option {} is listed twice.", letter));
+            }
+            options.push((
+                letter,
+                QuestionOptionForm {
+                    option: text.to_string(),
+                    is_correct: Some(false),
+                    feedback: None,
+                },
+            ));
+        } else if options.is_empty() {
+            question.push(line);
+        } else {
+            return import_error(line_no, "Expected an option like `A. text` or the answer.");
+        }
+    }
+
+    let (answer_line, letter) = match answer {
+        Some(answer) => answer,
+        None => {
+            let last = lines[lines.len() - 1].0;
+            return import_error(last, "Expected the answer like `ANSWER: A`.");
+        }
+    };
+    if options.len() < 2 {
+        return import_error(lines[0].0, "A question needs at least two options.");
+    }
+    match options.iter_mut().find(|option| option.0 == letter) {
+        Some(option) => option.1.is_correct = Some(true),
+        None => {
+            return import_error(answer_line, format!("There is no option {}.", letter));
+        }
+    }
+
+    Ok(TestQuestionForm {
+        question: question.join("\n"),
+        topic: None,
+        tags: None,
+        difficulty: None,
+        explanation: None,
+        options: options.into_iter().map(|(_, option)| option).collect(),
+    })
+}
+
+/// Parses an option line like `A. text` or `A) text`.
+fn parse_option(line: &str) -> Option<(char, &str)> {
+    let mut chars = line.chars();
+    let letter = chars.next().filter(char::is_ascii_uppercase)?;
+    match chars.next() {
+        Some('.') | Some(')') => (),
+        _ => return None,
+    }
+    let text = chars.as_str();
+    if text.starts_with(char::is_whitespace) && !text.trim().is_empty() {
+        Some((letter, text.trim()))
+    } else {
+        None
+    }
+}
+
+/// Parses the answer line like `ANSWER: A`.
+fn parse_answer(line: &str) -> Option<char> {
+    if !line.starts_with("ANSWER:") {
+        return None;
+    }
+    let letter = line["ANSWER:".len()..].trim();
+    let mut chars = letter.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), None) if letter.is_ascii_uppercase() => Some(letter),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errors::Error;
+
+    fn options(question: &TestQuestionForm) -> Vec<(&str, Option<bool>)> {
+        question
+            .options
+            .iter()
+            .map(|option| (option.option.as_str(), option.is_correct))
+            .collect()
+    }
+
+    fn error(content: &str) -> (usize, String) {
+        match parse(content) {
+            Err(Error::Import { line, message }) => (line, message),
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Expected an import error."),
+        }
+    }
+
+    #[test]
+    fn parses_questions() {
+        let questions = parse(
+            "Which planet is the largest?\n\
+             A. Mars\n\
+             B) Jupiter\n\
+             ANSWER: B\n\
+             \n\
+             What is\n\
+             two plus two?\n\
+             A. 3\n\
+             B. 4\n\
+             C. 5\n\
+             ANSWER: B\n",
+        ).unwrap();
+        assert_eq!(questions.len(), 2);
+        assert_eq!(questions[0].question, "Which planet is the largest?");
+        assert_eq!(
+            options(&questions[0]),
+            vec![("Mars", Some(false)), ("Jupiter", Some(true))]
+        );
+        assert_eq!(questions[1].question, "What is\ntwo plus two?");
+        assert_eq!(
+            options(&questions[1]),
+            vec![("3", Some(false)), ("4", Some(true)), ("5", Some(false))]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_questions() {
+        assert_eq!(
+            error("Q\nA. x\nB. y"),
+            (3, "Expected the answer like `ANSWER: A`.".into())
+        );
+        assert_eq!(
+            error("Q\nA. x\nB. y\nANSWER: C"),
+            (4, "There is no option C.".into())
+        );
+        assert_eq!(
+            error("Q\nA. x\nA. y\nANSWER: A"),
+            (3, "Option A is listed twice.".into())
+        );
+        assert_eq!(
+            error("A. x\nB. y\nANSWER: A"),
+            (1, "Expected a question before the options.".into())
+        );
+        assert_eq!(
+            error("Q\nA. x\nmore\nANSWER: A"),
+            (3, "Expected an option like `A. text` or the answer.".into())
+        );
+        assert_eq!(
+            error("Q\nA. x\nB. y\nANSWER: A\nmore"),
+            (5, "Expected a blank line after the answer.".into())
+        );
+        assert_eq!(
+            error("Q\nA. x\nANSWER: A"),
+            (1, "A question needs at least two options.".into())
+        );
+    }
+
+    #[test]
+    fn reports_the_line_in_the_content() {
+        assert_eq!(
+            error("Q\nA. x\nB. y\nANSWER: A\n\n\nR\nA. x\nB. y\nANSWER: D\n"),
+            (10, "There is no option D.".into())
+        );
+    }
+}
@@ -0,0 +1,545 @@
+//! The GIFT format of Moodle. Only the question types which map to options are
+//! supported, i.e. multiple choice, multiple answers and true/false:
+//!
+//! ```text
+//! // A comment.
+//! $CATEGORY: Astronomy
+//!
+//! ::Largest planet:: Which planet is the largest? {
+//!     ~Mars#Mars is smaller than the Earth.
+//!     =Jupiter
+//!     ~Venus
+//!     ####Jupiter is more than twice as massive as the other planets combined.
+//! }
+//!
+//! The Sun is a star. {TRUE}
+//! ```
+//!
+//! A category sets the topic of the questions following it.
+
+use super::{blocks, import_error};
+use errors::SResult;
+use models::{question_option::QuestionOptionForm, test_question::TestQuestionForm};
+
+const CATEGORY: &str = "$CATEGORY:";
+
+pub fn parse(content: &str) -> SResult<Vec<TestQuestionForm>> {
+    let mut questions = Vec::new();
+    let mut topic: Option<String> = None;
+    for lines in blocks(content, is_comment) {
+        let mut lines = &lines[..];
+        while let Some(category) = lines.first().and_then(|&(_, line)| parse_category(line)) {
+            topic = category;
+            lines = &lines[1..];
+        }
+        if lines.is_empty() {
+            continue;
+        }
+
+        let numbers: Vec<usize> = lines.iter().map(|&(number, _)| number).collect();
+        let text: Vec<&str> = lines.iter().map(|&(_, line)| line).collect();
+        let mut question = parse_question(&numbers, &text.join("\n"))?;
+        question.topic = topic.clone();
+        questions.push(question);
+    }
+    Ok(questions)
+}
+
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with("//")
+}
+
+/// Parses a category line to the topic it sets. The topic is the last part
+/// of a category path like `$course$/Science/Astronomy`.
+fn parse_category(line: &str) -> Option<Option<String>> {
+    let line = line.trim();
+    if !line.starts_with(CATEGORY) {
+        return None;
+    }
+    let topic = line[CATEGORY.len()..]
+        .rsplit('/')
+        .next()
+        .map(str::trim)
+        .filter(|topic| !topic.is_empty())
+        .map(str::to_string);
+    Some(topic)
+}
+
+/// Parses the text of a question, whose lines have the given numbers.
+fn parse_question(lines: &[usize], text: &str) -> SResult<TestQuestionForm> {
+    let start = lines[0];
+    let open = match find_unescaped(text, 0, '{') {
+        Some(open) => open,
+        None => return import_error(start, "Expected the answers within `{` and `}`."),
+    };
+    let close = match find_unescaped(text, open + 1, '}') {
+        Some(close) => close,
+        None => {
+            return import_error(line_at(lines, text, open), "The answers are not closed by `}`.")
+        }
+    };
+    if let Some(extra) = find_unescaped(text, close + 1, '{') {
+        return import_error(
+            line_at(lines, text, extra),
+            "A question can only have one set of answers.",
+        );
+    }
+
+    let head = unescape(strip_format(strip_title(text[..open].trim())));
+    let tail = unescape(text[close + 1..].trim());
+    if head.is_empty() {
+        return import_error(start, "Expected a question before the answers.");
+    }
+    // The answers may be in the middle of the text for a missing word.
+    let question = if tail.is_empty() {
+        head
+    } else {
+        format!("{} _____ {}", head, tail)
+    };
+
+    let answers_lines = &lines[line_index(text, open)..];
+    let (options, explanation) = parse_answers(answers_lines, &text[open + 1..close])?;
+    Ok(TestQuestionForm {
+        question,
+        topic: None,
+        tags: None,
+        difficulty: None,
+        explanation,
+        options,
+    })
+}
+
+/// Strips the title like `::Title::` from a question.
+fn strip_title(text: &str) -> &str {
+    if text.starts_with("::") {
+        if let Some(end) = text[2..].find("::") {
+            return text[end + 4..].trim_start();
+        }
+    }
+    text
+}
+
+/// Strips the text format like `[markdown]` from a question.
+fn strip_format(text: &str) -> &str {
+    for format in &["[html]", "[moodle]", "[plain]", "[markdown]"] {
+        if text.starts_with(format) {
+            return text[format.len()..].trim_start();
+        }
+    }
+    text
+}
+
+#[derive(PartialEq)]
+enum Part {
+    Leading,
+    Answer { correct: bool },
+    Feedback,
+    General,
+}
+
+/// Parses the answers within the braces to the options and the general
+/// feedback, which becomes the explanation. The lines of the answers have
+/// the given numbers.
+fn parse_answers(
+    lines: &[usize],
+    body: &str,
+) -> SResult<(Vec<QuestionOptionForm>, Option<String>)> {
+    let start = lines[0];
+    let parts = split_answers(lines, body);
+    let (leading_line, leading) = match parts.first() {
+        Some(&(Part::Leading, line, ref text)) => (line, text.clone()),
+        _ => (start, String::new()),
+    };
+    let mut explanation = None;
+    for &(ref part, line, ref text) in &parts {
+        if *part == Part::General {
+            if explanation.is_some() {
+                return import_error(line, "A question can only have one general feedback.");
+            }
+            explanation = Some(text.clone());
+        }
+    }
+
+    if !leading.is_empty() {
+        return match leading.to_uppercase().as_str() {
+            "T" | "TRUE" => true_false(true, &parts).map(|options| (options, explanation)),
+            "F" | "FALSE" => true_false(false, &parts).map(|options| (options, explanation)),
+            _ if leading.starts_with('#') => {
+                import_error(leading_line, "Numerical questions are not supported.")
+            }
+            _ => import_error(leading_line, "Expected an answer starting with `=` or `~`."),
+        };
+    }
+
+    let mut options: Vec<QuestionOptionForm> = Vec::new();
+    let mut has_wrong = false;
+    for (part, line, text) in parts {
+        match part {
+            Part::Leading | Part::General => (),
+            Part::Answer { correct } => {
+                if text.contains("->") {
+                    return import_error(line, "Matching questions are not supported.");
+                }
+                let (weight, text) = split_weight(line, &text)?;
+                if text.is_empty() {
+                    return import_error(line, "An answer cannot be empty.");
+                }
+                has_wrong |= !correct;
+                options.push(QuestionOptionForm {
+                    option: text.to_string(),
+                    is_correct: Some(weight.map_or(correct, |weight| weight > 0.0)),
+                    feedback: None,
+                });
+            }
+            Part::Feedback => match options.last_mut() {
+                Some(option) => option.feedback = Some(text),
+                None => return import_error(line, "Expected an answer before the feedback."),
+            },
+        }
+    }
+
+    if options.is_empty() {
+        return import_error(start, "Essay questions are not supported.");
+    }
+    if !has_wrong {
+        return import_error(start, "Short answer questions are not supported.");
+    }
+    if options.len() < 2 {
+        return import_error(start, "A question needs at least two options.");
+    }
+    if !options.iter().any(|option| option.is_correct == Some(true)) {
+        return import_error(start, "A question needs at least one correct answer.");
+    }
+    Ok((options, explanation))
+}
+
+/// Options of a true/false question. The first feedback is shown for the
+/// wrong option and the second one for the correct option.
+fn true_false(
+    statement: bool,
+    parts: &[(Part, usize, String)],
+) -> SResult<Vec<QuestionOptionForm>> {
+    let mut feedbacks = Vec::new();
+    for &(ref part, line, ref text) in parts {
+        match *part {
+            Part::Answer { .. } => {
+                return import_error(line, "A true/false question cannot have other answers.")
+            }
+            Part::Feedback => feedbacks.push(text.clone()),
+            Part::Leading | Part::General => (),
+        }
+    }
+    let mut feedbacks = feedbacks.into_iter();
+    let wrong_feedback = feedbacks.next();
+    let right_feedback = feedbacks.next();
+
+    Ok([true, false]
+        .iter()
+        .map(|&value| QuestionOptionForm {
+            option: if value { "True" } else { "False" }.to_string(),
+            is_correct: Some(value == statement),
+            feedback: if value == statement {
+                right_feedback.clone()
+            } else {
+                wrong_feedback.clone()
+            },
+        }).collect())
+}
+
+/// Splits the weight like `%50%` off the start of an answer.
+fn split_weight(line: usize, text: &str) -> SResult<(Option<f64>, &str)> {
+    if !text.starts_with('%') {
+        return Ok((None, text));
+    }
+    match text[1..].find('%') {
+        Some(end) => match text[1..end + 1].parse() {
+            Ok(weight) => Ok((Some(weight), text[end + 2..].trim())),
+            Err(_) => import_error(line, "Expected a weight like `%50%`."),
+        },
+        None => import_error(line, "Expected a weight like `%50%`."),
+    }
+}
+
+/// Splits the answers on the unescaped `=`, `~`, `#` and `####` markers. Every
+/// part is paired with the number of the line it starts at.
+fn split_answers(lines: &[usize], body: &str) -> Vec<(Part, usize, String)> {
+    let mut parts = Vec::new();
+    let mut part = Part::Leading;
+    let mut part_line = lines[0];
+    let mut line = 0;
+    let mut text = String::new();
+    let mut chars = body.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let next = match c {
+            '\\' => {
+                match chars.next() {
+                    Some('n') => text.push('\n'),
+                    Some(escaped) => text.push(escaped),
+                    None => text.push(c),
+                }
+                continue;
+            }
+            '=' => Part::Answer { correct: true },
+            '~' => Part::Answer { correct: false },
+            '#' if part != Part::Leading || !text.trim().is_empty() => {
+                let mut count = 1;
+                while chars.peek() == Some(&'#') {
+                    chars.next();
+                    count += 1;
+                }
+                if count >= 4 {
+                    Part::General
+                } else {
+                    Part::Feedback
+                }
+            }
+            _ => {
+                if c == '\n' {
+                    line += 1;
+                }
+                text.push(c);
+                continue;
+            }
+        };
+        push_part(&mut parts, part, part_line, &text);
+        part = next;
+        part_line = lines[line];
+        text.clear();
+    }
+    push_part(&mut parts, part, part_line, &text);
+    parts
+}
+
+fn push_part(parts: &mut Vec<(Part, usize, String)>, part: Part, line: usize, text: &str) {
+    let text = text.trim();
+    if part != Part::Leading || !text.is_empty() {
+        parts.push((part, line, text.to_string()));
+    }
+}
+
+/// Byte offset of the first unescaped character at or after `from`.
+fn find_unescaped(text: &str, from: usize, target: char) -> Option<usize> {
+    let mut escaped = false;
+    for (offset, c) in text[from..].char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == target {
+            return Some(from + offset);
+        }
+    }
+    None
+}
+
+/// Index of the line a byte offset is on.
+fn line_index(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count()
+}
+
+/// Number of the line a byte offset is on, given the numbers of the lines.
+fn line_at(lines: &[usize], text: &str, offset: usize) -> usize {
+    lines[line_index(text, offset)]
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some(escaped) => unescaped.push(escaped),
+                None => unescaped.push(c),
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use errors::Error;
+
+    fn options(question: &TestQuestionForm) -> Vec<(&str, Option<bool>)> {
+        question
+            .options
+            .iter()
+            .map(|option| (option.option.as_str(), option.is_correct))
+            .collect()
+    }
+
+    fn error(content: &str) -> (usize, String) {
+        match parse(content) {
+            Err(Error::Import { line, message }) => (line, message),
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Expected an import error."),
+        }
+    }
+
+    #[test]
+    fn parses_multiple_choice() {
+        let questions = parse(
+            "// A comment.\n\
+             $CATEGORY: $course$/Science/Astronomy\n\
+             \n\
+             ::Largest planet:: Which planet is the largest? {\n\
+             ~Mars#Mars is smaller than the Earth.\n\
+             =Jupiter\n\
+             ~Venus\n\
+             ####Jupiter is more than twice as massive.\n\
+             }\n",
+        ).unwrap();
+        assert_eq!(questions.len(), 1);
+        let question = &questions[0];
+        assert_eq!(question.question, "Which planet is the largest?");
+        assert_eq!(question.topic, Some("Astronomy".into()));
+        assert_eq!(
+            question.explanation,
+            Some("Jupiter is more than twice as massive.".into())
+        );
+        assert_eq!(
+            options(question),
+            vec![
+                ("Mars", Some(false)),
+                ("Jupiter", Some(true)),
+                ("Venus", Some(false)),
+            ]
+        );
+        assert_eq!(
+            question.options[0].feedback,
+            Some("Mars is smaller than the Earth.".into())
+        );
+    }
+
+    #[test]
+    fn parses_multiple_answers() {
+        let questions = parse("Which are planets? {~%50%Mars ~%50%Venus ~%-100%Pluto}").unwrap();
+        assert_eq!(
+            options(&questions[0]),
+            vec![
+                ("Mars", Some(true)),
+                ("Venus", Some(true)),
+                ("Pluto", Some(false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_true_false() {
+        let questions = parse(
+            "The Sun is a star. {TRUE}\n\
+             \n\
+             The Moon is a planet. {F#It is a planet.#It is a moon.}\n",
+        ).unwrap();
+        assert_eq!(questions.len(), 2);
+        assert_eq!(
+            options(&questions[0]),
+            vec![("True", Some(true)), ("False", Some(false))]
+        );
+        assert_eq!(
+            options(&questions[1]),
+            vec![("True", Some(false)), ("False", Some(true))]
+        );
+        assert_eq!(
+            questions[1].options[0].feedback,
+            Some("It is a planet.".into())
+        );
+        assert_eq!(
+            questions[1].options[1].feedback,
+            Some("It is a moon.".into())
+        );
+    }
+
+    #[test]
+    fn parses_missing_word() {
+        let questions = parse("The Sun is a {=star ~planet} of the solar system.").unwrap();
+        assert_eq!(
+            questions[0].question,
+            "The Sun is a _____ of the solar system."
+        );
+        assert_eq!(
+            options(&questions[0]),
+            vec![("star", Some(true)), ("planet", Some(false))]
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_types() {
+        assert_eq!(
+            error("What is two plus two? {#4}"),
+            (1, "Numerical questions are not supported.".into())
+        );
+        assert_eq!(
+            error("Match the planets.\n{\n=Mars -> red\n=Jupiter -> big\n}"),
+            (3, "Matching questions are not supported.".into())
+        );
+        assert_eq!(
+            error("Write about the Sun. {}"),
+            (1, "Essay questions are not supported.".into())
+        );
+        assert_eq!(
+            error("Which planet is the largest? {=Jupiter =jupiter}"),
+            (1, "Short answer questions are not supported.".into())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_questions() {
+        assert_eq!(
+            error("Which planet is the largest?"),
+            (1, "Expected the answers within `{` and `}`.".into())
+        );
+        assert_eq!(
+            error("Which planet\nis the largest? {\n=Jupiter\n~Mars"),
+            (2, "The answers are not closed by `}`.".into())
+        );
+        assert_eq!(
+            error("Q {=a ~b} and {=c ~d}"),
+            (1, "A question can only have one set of answers.".into())
+        );
+        assert_eq!(
+            error("{=a ~b}"),
+            (1, "Expected a question before the answers.".into())
+        );
+        assert_eq!(
+            error("Q {=a}"),
+            (1, "Short answer questions are not supported.".into())
+        );
+        assert_eq!(
+            error("Q {~a ~b}"),
+            (1, "A question needs at least one correct answer.".into())
+        );
+    }
+
+    #[test]
+    fn reports_the_line_in_the_content() {
+        assert_eq!(
+            error(
+                "// A comment.\n\
+                 The Sun is a star. {T}\n\
+                 \n\
+                 Which planet is the largest? {\n\
+                 =Jupiter\n\
+                 ~Pluto -> dwarf\n\
+                 }\n"
+            ),
+            (6, "Matching questions are not supported.".into())
+        );
+        assert_eq!(
+            error(
+                "Which planet is the largest? {\n\
+                 // Gas giants.\n\
+                 =Jupiter\n\
+                 // Dwarf planets.\n\
+                 ~Pluto -> dwarf\n\
+                 }\n"
+            ),
+            (5, "Matching questions are not supported.".into())
+        );
+    }
+}
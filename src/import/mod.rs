@@ -0,0 +1,86 @@
+//! Parsers for the plain text formats question authors write in.
+
+use errors::{Error, SResult};
+use models::{
+    test_paper::{TestPaperForm, TestType},
+    test_question::TestQuestionForm,
+};
+
+mod aiken;
+mod gift;
+
+/// Format of the text to import a test paper from.
+#[derive(Debug, Clone, Copy, GraphQLEnum)]
+pub enum ImportFormat {
+    Gift,
+    Aiken,
+}
+
+/// A type to import a test paper from text.
+#[derive(GraphQLInputObject)]
+pub struct TestPaperImport {
+    /// Name of the test paper.
+    name: String,
+    /// Description of the test paper.
+    description: Option<String>,
+    /// Type of the test paper.
+    type_: TestType,
+    /// Format of the content.
+    format: ImportFormat,
+    /// Questions written in the given format.
+    content: String,
+}
+
+impl TestPaperImport {
+    /// Parses the content into a form to create the test paper with.
+    pub fn parse(self) -> SResult<TestPaperForm> {
+        let questions = parse_questions(self.format, &self.content)?;
+        Ok(TestPaperForm {
+            name: self.name,
+            description: self.description,
+            type_: self.type_,
+            shuffle: None,
+            questions,
+            draw_rules: None,
+        })
+    }
+}
+
+fn parse_questions(format: ImportFormat, content: &str) -> SResult<Vec<TestQuestionForm>> {
+    match format {
+        ImportFormat::Gift => gift::parse(content),
+        ImportFormat::Aiken => aiken::parse(content),
+    }
+}
+
+/// Splits the content into blocks separated by blank lines. Comment lines
+/// are left out, so every line is paired with its line number, counting
+/// from 1.
+fn blocks<'a>(content: &'a str, is_comment: fn(&str) -> bool) -> Vec<Vec<(usize, &'a str)>> {
+    let mut blocks = Vec::new();
+    let mut current = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if is_comment(line) {
+            continue;
+        }
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                blocks.push(current);
+                current = Vec::new();
+            }
+            continue;
+        }
+        current.push((index + 1, line));
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
+
+fn import_error<T, S: Into<String>>(line: usize, message: S) -> SResult<T> {
+    Err(Error::Import {
+        line,
+        message: message.into(),
+    })
+}
@@ -2,6 +2,8 @@
 
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 extern crate chrono;
 extern crate uuid;
 #[macro_use]
@@ -9,55 +11,111 @@ extern crate failure;
 extern crate bcrypt;
 extern crate dotenv;
 extern crate futures;
+extern crate futures_cpupool;
 #[macro_use]
 extern crate juniper;
 extern crate juniper_warp;
 #[macro_use]
 extern crate log;
 extern crate base64;
+extern crate bytes;
+extern crate csv;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate sha1;
+extern crate sha2;
+extern crate toml;
 extern crate ttl_cache;
 extern crate warp;
 #[macro_use]
 extern crate lazy_static;
+extern crate xml;
+extern crate zip;
 
 use basic::BasicUser;
+use bytes::Buf;
+use config::{AuthConfig, DatabaseConfig, GraphqlConfig, ServerConfig};
 use diesel::{
     pg::PgConnection,
-    r2d2::{ConnectionManager, Pool, PooledConnection},
+    r2d2::{ConnectionManager, Pool, PoolError, PooledConnection},
+    RunQueryDsl,
 };
 use errors::{Error, SResult};
+use futures::{Future, Stream};
+use futures_cpupool::CpuPool;
 use gql_schema::create_schema;
-use models::user::{verify_user, User};
-use std::{env, sync::Mutex, time::Duration};
+use graphql_http::{BatchRequest, GetRequest};
+use loaders::Loaders;
+use models::{
+    test_attempt::TestAttempt,
+    test_paper::TestPaper,
+    user::{verify_user, User},
+};
+use persisted_queries::PersistedQueries;
+use query_limits::QueryLimits;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use subscriptions::Session;
 use ttl_cache::TtlCache;
+use uuid::Uuid;
 use warp::{
-    filters::BoxedFilter,
+    filters::{cors::Cors, BoxedFilter},
     http::{Response, StatusCode},
-    Filter, Rejection,
+    multipart::FormData,
+    ws::Ws2,
+    Filter, Rejection, Reply,
 };
 
+pub mod admin;
+mod backup;
 mod basic;
+pub mod config;
+#[macro_use]
+mod connection;
 mod db_types;
 mod errors;
+mod events;
 mod gql_schema;
+mod graphql_http;
+mod import;
+mod loaders;
+mod metrics;
 mod models;
+pub mod password_policy;
+pub mod persisted_queries;
+mod qti;
+mod query_limits;
+mod questions_csv;
 #[allow(unused_imports)]
 mod schema;
+mod search;
+mod shuffle;
+mod subscriptions;
+mod validation;
 
-type PgPool = Pool<ConnectionManager<PgConnection>>;
-type PooledPg = PooledConnection<ConnectionManager<PgConnection>>;
-
-fn pg_pool() -> PgPool {
-    dotenv::dotenv().unwrap();
+pub type PgPool = Pool<ConnectionManager<PgConnection>>;
+pub type PooledPg = PooledConnection<ConnectionManager<PgConnection>>;
 
-    let postgres_url = env::var("DATABASE_URL").expect("Could not find `DATABASE_URL` in the env.");
-    let manager = ConnectionManager::<PgConnection>::new(postgres_url);
-    Pool::new(manager).expect("Postgres connection pool could not be created")
+/// Creates the connection pool, failing if the database cannot be reached.
+pub fn pg_pool(config: &DatabaseConfig) -> Result<PgPool, PoolError> {
+    let manager = ConnectionManager::<PgConnection>::new(config.url.as_str());
+    Pool::builder()
+        .max_size(config.pool_size)
+        .connection_timeout(config.connection_timeout())
+        .idle_timeout(config.idle_timeout())
+        .max_lifetime(config.max_lifetime())
+        .build(manager)
 }
 
 pub struct Context {
     pub conn: PooledPg,
     pub user: Option<User>,
+    pub loaders: Loaders,
 }
 
 impl Context {
@@ -65,6 +123,10 @@ impl Context {
         self.user.as_ref().ok_or(Error::Unauthorized)
     }
 
+    fn is_admin(&self) -> bool {
+        self.user.as_ref().map_or(false, User::is_admin)
+    }
+
     fn admin_only(&self) -> SResult<&User> {
         self.auth_user().and_then(|user| {
             if user.is_admin() {
@@ -88,33 +150,341 @@ impl Context {
 
 impl juniper::Context for Context {}
 
-pub fn graphiql() -> impl Filter<Extract = (Response<Vec<u8>>,), Error = Rejection> + Clone {
+/// Serves GraphiQL at the root, unless only the persisted queries are
+/// allowed, which GraphiQL could not send.
+pub fn graphiql(
+    config: &GraphqlConfig,
+) -> impl Filter<Extract = (Response<Vec<u8>>,), Error = Rejection> + Clone {
+    let is_enabled = !config.persisted_only;
     warp::get2()
         .and(warp::index())
-        .and(juniper_warp::graphiql_handler("/graphql"))
+        .and_then(move || {
+            if is_enabled {
+                Ok(())
+            } else {
+                Err(warp::reject())
+            }
+        }).and(juniper_warp::graphiql_handler("/graphql"))
+        .map(|(), response| response)
 }
 
-fn graphql_context() -> BoxedFilter<(Context,)> {
-    pg_conn()
-        .and(basic::basic_optional())
-        .and_then(user_lookup)
-        .map(|(pooled, user)| Context { conn: pooled, user })
-        .boxed()
-}
+/// Serves GraphQL requests on `GET` and `POST`. Persisted queries are
+/// looked up by their hashes, operations are checked against the query
+/// limits and then executed on a thread pool as they block on the database.
+/// The connection is taken and the user verified only for the operations
+/// which pass the checks.
+pub fn graphql(
+    pool: PgPool,
+    auth: &AuthConfig,
+    config: &GraphqlConfig,
+    persisted: Arc<PersistedQueries>,
+) -> impl Filter<Extract = (Response<Vec<u8>>,), Error = Rejection> + Clone {
+    let schema = Arc::new(create_schema());
+    let limits = Arc::new(QueryLimits::new(config));
+    let cpu_pool = CpuPool::new_num_cpus();
+    let cache_ttl = auth.cache_ttl();
+    let get = warp::get2().and(warp::query::<GetRequest>().and_then(|request: GetRequest| {
+        request.parse().map_err(|_| warp::reject::bad_request())
+    }));
+    let post = warp::post2().and(warp::body::json::<BatchRequest>());
 
-pub fn graphql() -> impl Filter<Extract = (Response<Vec<u8>>,), Error = Rejection> + Clone {
-    let graphql_filter = juniper_warp::make_graphql_filter(create_schema(), graphql_context());
     warp::path("graphql")
-        .and(graphql_filter)
-        .recover(handle_error)
+        .and(get.or(post).unify())
+        .and(basic::basic_optional())
+        .and_then(move |request: BatchRequest, user: Option<BasicUser>| {
+            let schema = schema.clone();
+            let persisted = persisted.clone();
+            let limits = limits.clone();
+            let pool = pool.clone();
+            cpu_pool
+                .spawn_fn(move || {
+                    request
+                        .prepare(&persisted, &limits)
+                        .execute(&schema, || new_context(&pool, user, cache_ttl))
+                }).map(|(body, is_ok)| {
+                    let status = if is_ok {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::BAD_REQUEST
+                    };
+                    Response::builder()
+                        .status(status)
+                        .header("Content-Type", "application/json")
+                        .body(body)
+                        .unwrap()
+                })
+        }).recover(handle_error)
         .unify()
 }
 
-fn pg_conn() -> impl Filter<Extract = (PooledPg,), Error = Rejection> + Clone {
-    let pg_pool = pg_pool();
-    warp::any().and_then(move || match pg_pool.get() {
-        Ok(pooled) => Ok(pooled),
-        Err(_) => Err(warp::reject::server_error()),
+/// Serves the subscriptions over WebSockets at `/subscriptions`. The
+/// credentials are taken from the upgrade request, or else from the
+/// `connection_init` message as browsers cannot set headers on WebSockets.
+/// Subscriptions are looked up and checked like the other operations.
+pub fn subscriptions(
+    pool: PgPool,
+    auth: &AuthConfig,
+    config: &GraphqlConfig,
+    persisted: Arc<PersistedQueries>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let limits = Arc::new(QueryLimits::new(config));
+    let cpu_pool = CpuPool::new_num_cpus();
+    let cache_ttl = auth.cache_ttl();
+    events::start_ticker();
+    warp::path("subscriptions")
+        .and(warp::path::end())
+        .and(warp::ws2())
+        .and(basic::basic_optional())
+        .map(move |ws: Ws2, credentials: Option<BasicUser>| {
+            let session = Session::new(
+                pool.clone(),
+                persisted.clone(),
+                limits.clone(),
+                cache_ttl,
+                credentials,
+            );
+            let cpu_pool = cpu_pool.clone();
+            ws.on_upgrade(move |socket| session.run(socket, cpu_pool))
+        }).with(warp::reply::with::header(
+            "Sec-WebSocket-Protocol",
+            "graphql-ws",
+        ))
+}
+
+/// Longest wait in seconds for a connection when checking the database, so
+/// that probes get an answer before they time out.
+const PROBE_TIMEOUT: u64 = 2;
+
+/// Serves `/healthz`, which answers as long as the server runs, `/readyz`,
+/// which also checks the database, and `/metrics` for Prometheus. The
+/// database is checked on a thread pool, as waiting for a connection blocks.
+pub fn monitoring(
+    pool: PgPool,
+) -> impl Filter<Extract = (Response<Vec<u8>>,), Error = Rejection> + Clone {
+    let cpu_pool = CpuPool::new_num_cpus();
+    let healthz = warp::path("healthz")
+        .and(warp::path::end())
+        .map(|| text_response(StatusCode::OK, "ok"));
+    let ready_pool = pool.clone();
+    let ready_cpu_pool = cpu_pool.clone();
+    let readyz = warp::path("readyz")
+        .and(warp::path::end())
+        .and_then(move || {
+            let pool = ready_pool.clone();
+            ready_cpu_pool.spawn_fn(move || Ok::<_, Rejection>(readiness(&pool)))
+        });
+    let metrics = warp::path("metrics")
+        .and(warp::path::end())
+        .and_then(move || {
+            let pool = pool.clone();
+            cpu_pool.spawn_fn(move || Ok::<_, Rejection>(metrics_response(&pool)))
+        });
+    warp::get2().and(healthz.or(readyz).unify().or(metrics).unify())
+}
+
+fn readiness(pool: &PgPool) -> Response<Vec<u8>> {
+    let is_ready = pool
+        .get_timeout(Duration::from_secs(PROBE_TIMEOUT))
+        .map_err(|err| err.to_string())
+        .and_then(|conn| {
+            diesel::sql_query("SELECT 1")
+                .execute(&*conn)
+                .map_err(|err| err.to_string())
+        });
+    match is_ready {
+        Ok(_) => text_response(StatusCode::OK, "ok"),
+        Err(err) => {
+            warn!("The database is not ready: {}", err);
+            text_response(StatusCode::SERVICE_UNAVAILABLE, "database unavailable")
+        }
+    }
+}
+
+fn metrics_response(pool: &PgPool) -> Response<Vec<u8>> {
+    let active_attempts = pool
+        .get_timeout(Duration::from_secs(PROBE_TIMEOUT))
+        .map_err(|err| err.to_string())
+        .and_then(|conn| TestAttempt::count_active(&conn).map_err(|err| err.to_string()));
+    if let Err(ref err) = active_attempts {
+        warn!("Could not count the active attempts: {}", err);
+    }
+    let body = metrics::render(pool.state(), pool.max_size(), active_attempts.ok());
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(body.into_bytes())
+        .unwrap()
+}
+
+fn text_response(status: StatusCode, body: &str) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(body.as_bytes().to_vec())
+        .unwrap()
+}
+
+/// Largest CSV file of questions which can be uploaded.
+const CSV_MAX_LENGTH: u64 = 4 * 1024 * 1024;
+
+/// Downloads the questions of a test paper as a CSV file on `GET` and creates
+/// or updates them from the `file` field of a multipart form on `POST`. An
+/// upload gives the `lockVersion` of the test paper the file was made from
+/// in the query string, like the GraphQL updates do. As with the GraphQL
+/// requests, the user is verified and the file exported or imported on a
+/// thread pool.
+pub fn questions_csv(
+    pool: PgPool,
+    auth: &AuthConfig,
+) -> impl Filter<Extract = (Response<Vec<u8>>,), Error = Rejection> + Clone {
+    let cpu_pool = CpuPool::new_num_cpus();
+    let csv_path = warp::path("test-papers")
+        .and(warp::path::param::<Uuid>())
+        .and(warp::path("questions.csv"))
+        .and(warp::path::end());
+    let admin_context = admin_context(pool, auth, cpu_pool.clone());
+
+    let download_pool = cpu_pool.clone();
+    let download = warp::get2()
+        .and(csv_path.clone())
+        .and(admin_context.clone())
+        .and_then(move |id: Uuid, ctx: Context| {
+            download_pool.spawn_fn(move || Ok::<_, Rejection>(download_csv(id, &ctx)))
+        });
+    let upload = warp::post2()
+        .and(csv_path)
+        .and(admin_context)
+        .and(warp::query::<CsvUpload>())
+        .and(warp::multipart::form().max_length(CSV_MAX_LENGTH))
+        .and_then(move |id, ctx, upload, form| upload_csv(id, ctx, upload, form, &cpu_pool));
+    download.or(upload).unify().recover(handle_error).unify()
+}
+
+fn admin_context(pool: PgPool, auth: &AuthConfig, cpu_pool: CpuPool) -> BoxedFilter<(Context,)> {
+    let cache_ttl = auth.cache_ttl();
+    basic::basic_optional()
+        .and_then(move |user: Option<BasicUser>| {
+            let pool = pool.clone();
+            cpu_pool.spawn_fn(move || {
+                let ctx = new_context(&pool, user, cache_ttl)?;
+                if ctx.is_admin() {
+                    Ok(ctx)
+                } else {
+                    Err(warp::reject::forbidden())
+                }
+            })
+        }).boxed()
+}
+
+fn download_csv(id: Uuid, ctx: &Context) -> Response<Vec<u8>> {
+    let content = TestPaper::find_by_uuid(id, &ctx.conn)
+        .and_then(|paper| questions_csv::export(&paper, &ctx.conn));
+    match content {
+        Ok(content) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "text/csv; charset=utf-8")
+            .header(
+                "Content-Disposition",
+                format!("attachment; filename=\"questions-{}.csv\"", id),
+            ).body(content)
+            .unwrap(),
+        Err(err) => error_response(err),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CsvUpload {
+    lock_version: i32,
+}
+
+fn upload_csv(
+    id: Uuid,
+    ctx: Context,
+    upload: CsvUpload,
+    form: FormData,
+    cpu_pool: &CpuPool,
+) -> impl Future<Item = Response<Vec<u8>>, Error = Rejection> {
+    let cpu_pool = cpu_pool.clone();
+    form.filter(|part| part.name() == "file")
+        .into_future()
+        .map_err(|_| warp::reject::bad_request())
+        .and_then(|(part, _)| part.ok_or_else(warp::reject::bad_request))
+        .and_then(|part| {
+            part.fold(Vec::new(), |mut content, chunk| {
+                content.extend_from_slice(chunk.bytes());
+                Ok::<_, warp::Error>(content)
+            }).map_err(|_| warp::reject::bad_request())
+        }).and_then(move |content| {
+            cpu_pool.spawn_fn(move || Ok(import_csv(id, &ctx, &upload, &content)))
+        })
+}
+
+fn import_csv(id: Uuid, ctx: &Context, upload: &CsvUpload, content: &[u8]) -> Response<Vec<u8>> {
+    let imported = TestPaper::find_by_uuid(id, &ctx.conn).and_then(|paper| {
+        questions_csv::import(&paper, upload.lock_version, content, &ctx.conn)
+    });
+    match imported {
+        Ok(Ok(paper)) => json_response(json!({
+            "data": {
+                "testPaper": {
+                    "id": paper.uuid.to_string(),
+                    "version": paper.version,
+                }
+            }
+        })),
+        Ok(Err(rows)) => {
+            let errors: Vec<_> = rows
+                .into_iter()
+                .map(|row| {
+                    json!({
+                        "message": format!("Row {}: {}", row.row, row.message),
+                        "extensions": {
+                            "kind": "INVALID_ROW",
+                            "row": row.row,
+                        }
+                    })
+                }).collect();
+            json_response(json!({ "data": null, "errors": errors }))
+        }
+        Err(err) => error_response(err),
+    }
+}
+
+fn json_response(body: serde_json::Value) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(body.to_string().into_bytes())
+        .unwrap()
+}
+
+/// Responds with an error the same way the GraphQL endpoint does.
+fn error_response(err: Error) -> Response<Vec<u8>> {
+    json_response(graphql_http::error_body(err))
+}
+
+/// Allows the configured origins to call the API from a browser.
+pub fn cors(config: &ServerConfig) -> Cors {
+    warp::cors()
+        .allow_origins(config.cors_origins.iter().map(String::as_str))
+        .allow_methods(vec!["GET", "POST"])
+        .allow_headers(vec!["authorization", "content-type"])
+        .allow_credentials(true)
+}
+
+/// Takes a connection and looks the user of the credentials up.
+fn new_context(
+    pool: &PgPool,
+    user: Option<BasicUser>,
+    cache_ttl: Duration,
+) -> Result<Context, Rejection> {
+    let conn = pool.get().map_err(|_| warp::reject::server_error())?;
+    let (conn, user) = user_lookup(conn, user, cache_ttl)?;
+    Ok(Context {
+        conn,
+        user,
+        loaders: Loaders::default(),
     })
 }
 
@@ -125,27 +495,30 @@ lazy_static! {
 fn user_lookup(
     conn: PooledPg,
     user: Option<BasicUser>,
+    cache_ttl: Duration,
 ) -> Result<(PooledPg, Option<User>), Rejection> {
     if let Some(user) = user {
         // Check if the user is already in cache.
         {
             let cache = AUTH_CACHE.lock().unwrap();
             if let Some(&user_id) = cache.get(&user) {
+                metrics::auth_cache_hit();
                 let user = User::find(user_id, &conn).map_err(|_| warp::reject::forbidden())?;
                 return Ok((conn, Some(user)));
             }
         }
 
         // Else verify the user.
+        metrics::auth_cache_miss();
         let found_user = User::find_by_email(&user.username, &conn)
             .and_then(|found| verify_user(found, &user.password))
             .map_err(|_| warp::reject::forbidden())?;
 
-        // And remember it for the rest of the day as verification is a very
-        // slow process.
+        // And remember it for a while as verification is a very slow
+        // process.
         {
             let mut cache = AUTH_CACHE.lock().unwrap();
-            cache.insert(user, found_user.id, Duration::from_secs(86400));
+            cache.insert(user, found_user.id, cache_ttl);
         }
 
         Ok((conn, Some(found_user)))
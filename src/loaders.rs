@@ -0,0 +1,366 @@
+//! Batched loading for the GraphQL resolvers. Juniper resolves a list one
+//! item at a time, so the children of every item would be queried one by
+//! one. Instead, the items of a list are registered as it is resolved and
+//! the first lookup loads the children of all of them with one query.
+//!
+//! What is loaded is handed out once and then forgotten, so nothing stale is
+//! kept around when a mutation changes it later in the same request.
+
+use diesel::{result::Error as DieselError, PgConnection};
+use errors::{Error, SResult};
+use models::{
+    question_answer::QuestionAnswer, question_option::QuestionOption, test_paper::TestPaper,
+    test_question::TestQuestion,
+};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+/// Values keyed by the id they were looked up with.
+struct Loader<V> {
+    pending: RefCell<HashSet<i32>>,
+    loaded: RefCell<HashMap<i32, V>>,
+}
+
+impl<V> Default for Loader<V> {
+    fn default() -> Loader<V> {
+        Loader {
+            pending: RefCell::new(HashSet::new()),
+            loaded: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl<V> Loader<V> {
+    /// Marks ids to be loaded along with the next lookup.
+    fn register<I: IntoIterator<Item = i32>>(&self, ids: I) {
+        self.pending.borrow_mut().extend(ids);
+    }
+
+    /// Takes the value of an id, fetching it with every pending id unless it
+    /// has already been fetched.
+    fn load<F>(&self, id: i32, fetch: F) -> SResult<Option<V>>
+    where
+        F: FnOnce(&[i32]) -> SResult<HashMap<i32, V>>,
+    {
+        if let Some(value) = self.loaded.borrow_mut().remove(&id) {
+            return Ok(Some(value));
+        }
+
+        let mut ids: Vec<i32> = {
+            let mut pending = self.pending.borrow_mut();
+            pending.remove(&id);
+            pending.drain().collect()
+        };
+        ids.push(id);
+        let mut fetched = fetch(&ids)?;
+        let value = fetched.remove(&id);
+        self.loaded.borrow_mut().extend(fetched);
+        Ok(value)
+    }
+}
+
+/// Loaders of a single GraphQL request.
+#[derive(Default)]
+pub struct Loaders {
+    paper_questions: Loader<Vec<TestQuestion>>,
+    question_options: Loader<Vec<QuestionOption>>,
+    questions: Loader<TestQuestion>,
+    options: Loader<QuestionOption>,
+}
+
+impl Loaders {
+    pub fn register_test_papers(&self, papers: &[TestPaper]) {
+        self.paper_questions
+            .register(papers.iter().map(|paper| paper.id));
+    }
+
+    pub fn register_questions<'a, I>(&self, questions: I)
+    where
+        I: IntoIterator<Item = &'a TestQuestion>,
+    {
+        self.question_options
+            .register(questions.into_iter().map(|question| question.id));
+    }
+
+    pub fn register_answers(&self, answers: &[QuestionAnswer]) {
+        self.questions
+            .register(answers.iter().map(|answer| answer.test_question_id));
+        self.options
+            .register(answers.iter().map(|answer| answer.answered_option));
+    }
+
+    /// Questions of a test paper. The questions of every test paper loaded
+    /// together are registered for their options.
+    pub fn test_paper_questions(
+        &self,
+        test_paper_id: i32,
+        conn: &PgConnection,
+    ) -> SResult<Vec<TestQuestion>> {
+        let questions = self.paper_questions.load(test_paper_id, |ids| {
+            let questions = TestQuestion::find_all_for_test_papers(ids, conn)?;
+            self.register_questions(&questions);
+            Ok(group(ids, questions, |question| question.test_paper_id))
+        })?;
+        Ok(questions.unwrap_or_default())
+    }
+
+    pub fn question_options(
+        &self,
+        test_question_id: i32,
+        conn: &PgConnection,
+    ) -> SResult<Vec<QuestionOption>> {
+        let options = self.question_options.load(test_question_id, |ids| {
+            let options = QuestionOption::find_all_for_questions(ids, conn)?;
+            Ok(group(ids, options, |option| Some(option.test_question_id)))
+        })?;
+        Ok(options.unwrap_or_default())
+    }
+
+    pub fn question(&self, id: i32, conn: &PgConnection) -> SResult<TestQuestion> {
+        self.questions
+            .load(id, |ids| {
+                Ok(TestQuestion::find_many(ids, conn)?
+                    .into_iter()
+                    .map(|question| (question.id, question))
+                    .collect())
+            })?.ok_or(Error::Diesel(DieselError::NotFound))
+    }
+
+    pub fn option(&self, id: i32, conn: &PgConnection) -> SResult<QuestionOption> {
+        self.options
+            .load(id, |ids| {
+                Ok(QuestionOption::find_many(ids, conn)?
+                    .into_iter()
+                    .map(|option| (option.id, option))
+                    .collect())
+            })?.ok_or(Error::Diesel(DieselError::NotFound))
+    }
+}
+
+/// Groups children by their parent, keeping their order. Every parent gets
+/// an entry, so that one without children is not fetched again.
+fn group<T, F>(parent_ids: &[i32], children: Vec<T>, parent_id: F) -> HashMap<i32, Vec<T>>
+where
+    F: Fn(&T) -> Option<i32>,
+{
+    let mut groups: HashMap<i32, Vec<T>> =
+        parent_ids.iter().map(|&id| (id, Vec::new())).collect();
+    for child in children {
+        if let Some(group) = parent_id(&child).and_then(|id| groups.get_mut(&id)) {
+            group.push(child);
+        }
+    }
+    groups
+}
+
+/// The number of statements a request runs is counted with the
+/// `pg_stat_statements` extension of a test database, so these tests are
+/// ignored unless asked for:
+///
+/// ```text
+/// TEST_DATABASE_URL=postgres://localhost/paryxa_test cargo test -- --ignored
+/// ```
+///
+/// Everything is created in a transaction which is never committed.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use admin;
+    use chrono::Utc;
+    use diesel::{
+        r2d2::{ConnectionManager, Pool},
+        sql_query,
+        sql_types::BigInt,
+        Connection, RunQueryDsl,
+    };
+    use gql_schema::{create_schema, Schema};
+    use juniper::{self, Variables};
+    use models::user::User;
+    use serde_json::{self, Value};
+    use std::{env, sync::Mutex};
+    use {Context, PooledPg};
+
+    lazy_static! {
+        /// The statistics are shared by the whole database, so only one test
+        /// may run against it at a time.
+        static ref DATABASE: Mutex<()> = Mutex::new(());
+    }
+
+    #[derive(QueryableByName)]
+    struct Statements {
+        #[sql_type = "BigInt"]
+        count: i64,
+    }
+
+    struct TestDb {
+        schema: Schema,
+        ctx: Context,
+    }
+
+    impl TestDb {
+        fn new() -> TestDb {
+            let url = env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set.");
+            let pool = Pool::builder()
+                .max_size(1)
+                .build(ConnectionManager::new(url))
+                .unwrap();
+            let conn: PooledPg = pool.get().unwrap();
+            admin::run_migrations(&conn).unwrap();
+            conn.begin_test_transaction().unwrap();
+            let user = User::create_admin("loaders@example.com".into(), "password", &conn);
+            TestDb {
+                schema: create_schema(),
+                ctx: Context {
+                    conn,
+                    user: Some(user.unwrap()),
+                    loaders: Loaders::default(),
+                },
+            }
+        }
+
+        /// Executes a request with loaders of its own, like the server does.
+        fn execute(&mut self, query: &str) -> Value {
+            self.ctx.loaders = Loaders::default();
+            let (value, errors) =
+                juniper::execute(query, None, &self.schema, &Variables::new(), &self.ctx)
+                    .unwrap();
+            assert!(errors.is_empty(), "{:?}", errors);
+            serde_json::to_value(&value).unwrap()
+        }
+
+        /// Number of statements which executing a request runs.
+        fn count_statements(&mut self, query: &str) -> i64 {
+            sql_query("SELECT pg_stat_statements_reset()")
+                .execute(&*self.ctx.conn)
+                .unwrap();
+            self.execute(query);
+            let statements: Statements = sql_query(
+                "SELECT coalesce(sum(calls), 0)::BIGINT AS count
+                 FROM pg_stat_statements
+                 WHERE dbid = (SELECT oid FROM pg_database WHERE datname = current_database())
+                 AND query NOT LIKE '%pg_stat_statements%'",
+            ).get_result(&*self.ctx.conn)
+            .unwrap();
+            statements.count
+        }
+
+        /// Creates a test paper whose first option of every question is the
+        /// correct one.
+        fn create_test_paper(&mut self, questions: usize, options: usize) -> Value {
+            let questions: Vec<String> = (0..questions)
+                .map(|question| {
+                    let options: Vec<String> = (0..options)
+                        .map(|option| {
+                            format!(
+                                r#"{{ option: "Option {}", isCorrect: {} }}"#,
+                                option,
+                                option == 0
+                            )
+                        }).collect();
+                    format!(
+                        r#"{{ question: "Question {}", options: [{}] }}"#,
+                        question,
+                        options.join(", ")
+                    )
+                }).collect();
+            let created = self.execute(&format!(
+                r#"mutation {{
+                    createTestPaper(testPaper: {{
+                        name: "Paper", type: SCHEDULED, questions: [{}]
+                    }}) {{
+                        id
+                        lockVersion
+                        questions {{ edges {{ node {{ id options {{ id }} }} }} }}
+                    }}
+                }}"#,
+                questions.join(", ")
+            ));
+            created["createTestPaper"].clone()
+        }
+
+        /// Takes a test paper and answers every question with its first
+        /// option, giving the id of the attempt.
+        fn answer_all(&mut self, paper: &Value) -> String {
+            let paper_id = paper["id"].as_str().unwrap();
+            self.execute(&format!(
+                r#"mutation {{ publishTestPaper(id: "{}", lockVersion: {}) {{ id }} }}"#,
+                paper_id,
+                paper["lockVersion"]
+            ));
+            let schedule = self.execute(&format!(
+                r#"mutation {{
+                    createTestSchedule(schedule: {{
+                        testPaperId: "{}", time: {}.0, duration: 3600
+                    }}) {{ id }}
+                }}"#,
+                paper_id,
+                Utc::now().timestamp()
+            ));
+            let attempt = self.execute(&format!(
+                r#"mutation {{
+                    startTest(test: {{ testPaperId: "{}", testScheduleId: "{}" }}) {{ id }}
+                }}"#,
+                paper_id,
+                schedule["createTestSchedule"]["id"].as_str().unwrap()
+            ));
+            let attempt_id = attempt["startTest"]["id"].as_str().unwrap().to_string();
+            for edge in paper["questions"]["edges"].as_array().unwrap() {
+                let question = &edge["node"];
+                self.execute(&format!(
+                    r#"mutation {{
+                        answerTheQuestion(answer: {{
+                            testAttemptId: "{}", testQuestionId: "{}", answeredOption: "{}"
+                        }}) {{ answeredOption {{ id }} }}
+                    }}"#,
+                    attempt_id,
+                    question["id"].as_str().unwrap(),
+                    question["options"][0]["id"].as_str().unwrap()
+                ));
+            }
+            attempt_id
+        }
+    }
+
+    const PAPERS_QUERY: &str = "{
+        testPapers { edges { node { questions { edges { node { options { id } } } } } } }
+    }";
+
+    fn answers_query(attempt_id: &str) -> String {
+        format!(
+            r#"{{
+                testAttempt(id: "{}") {{ answers {{ question {{ id }} answeredOption {{ id }} }} }}
+            }}"#,
+            attempt_id
+        )
+    }
+
+    #[test]
+    #[ignore]
+    fn loads_papers_questions_and_options_with_constant_statements() {
+        let _database = DATABASE.lock().unwrap();
+        let mut db = TestDb::new();
+        db.create_test_paper(1, 2);
+        let few = db.count_statements(PAPERS_QUERY);
+        for _ in 0..3 {
+            db.create_test_paper(4, 3);
+        }
+        let many = db.count_statements(PAPERS_QUERY);
+        assert_eq!(few, many);
+    }
+
+    #[test]
+    #[ignore]
+    fn loads_answers_with_constant_statements() {
+        let _database = DATABASE.lock().unwrap();
+        let mut db = TestDb::new();
+        let paper = db.create_test_paper(2, 2);
+        let few_answers = db.answer_all(&paper);
+        let paper = db.create_test_paper(6, 3);
+        let many_answers = db.answer_all(&paper);
+        let few = db.count_statements(&answers_query(&few_answers));
+        let many = db.count_statements(&answers_query(&many_answers));
+        assert_eq!(few, many);
+    }
+}
@@ -2,18 +2,62 @@ extern crate env_logger;
 extern crate paryxa_server;
 extern crate warp;
 
-use paryxa_server::{graphiql, graphql};
-use std::env;
+use paryxa_server::{
+    config::Config, cors, graphiql, graphql, monitoring, password_policy,
+    persisted_queries::PersistedQueries, pg_pool, questions_csv, subscriptions,
+};
+use std::{env, fmt::Display, process, sync::Arc};
 use warp::Filter;
 
 const LOG: &str = "paryxa-server";
 
 fn main() {
+    let config = or_exit("Could not load the configuration", Config::load());
     if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", LOG);
+        env::set_var("RUST_LOG", &config.server.log);
     }
     let log = warp::log(LOG);
 
     env_logger::init();
-    warp::serve(graphiql().or(graphql()).with(log)).run(([127, 0, 0, 1], 4000));
+    let pool = or_exit(
+        "Could not connect to the database",
+        pg_pool(&config.database),
+    );
+    let address = or_exit("Could not load the configuration", config.bind_address());
+    or_exit(
+        "Could not load the breached passwords",
+        password_policy::configure(&config.password),
+    );
+    let persisted = Arc::new(or_exit(
+        "Could not load the persisted queries",
+        PersistedQueries::load(&config.graphql),
+    ));
+    let routes = graphiql(&config.graphql)
+        .or(graphql(
+            pool.clone(),
+            &config.auth,
+            &config.graphql,
+            persisted.clone(),
+        )).or(subscriptions(
+            pool.clone(),
+            &config.auth,
+            &config.graphql,
+            persisted,
+        )).or(questions_csv(pool.clone(), &config.auth))
+        .or(monitoring(pool))
+        .with(cors(&config.server))
+        .with(log);
+
+    let server = warp::serve(routes);
+    match config.server.tls {
+        Some(tls) => server.tls(tls.cert, tls.key).run(address),
+        None => server.run(address),
+    }
+}
+
+fn or_exit<T, E: Display>(context: &str, result: Result<T, E>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("{}: {}", context, err);
+        process::exit(1)
+    })
 }
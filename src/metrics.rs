@@ -0,0 +1,200 @@
+//! Metrics of the server in the Prometheus text format, served at `/metrics`.
+//! Requests and the auth cache are counted as they happen, while the pool
+//! and the attempts are looked up on every scrape.
+
+use diesel::r2d2::State;
+use std::{collections::HashMap, fmt::Write, sync::Mutex, time::Duration};
+
+/// Upper bounds in seconds of the buckets of the request duration histogram.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Operation names are chosen by the clients, so only this many of them are
+/// tracked and the rest are counted as `other`.
+const MAX_OPERATIONS: usize = 200;
+
+lazy_static! {
+    static ref METRICS: Mutex<Metrics> = Mutex::new(Metrics::default());
+}
+
+#[derive(Default)]
+struct Metrics {
+    operations: HashMap<String, Operation>,
+    auth_cache_hits: u64,
+    auth_cache_misses: u64,
+}
+
+#[derive(Default)]
+struct Operation {
+    ok: u64,
+    errors: u64,
+    /// Requests per bucket, not cumulative.
+    buckets: [u64; 11],
+    seconds: f64,
+}
+
+/// Records a GraphQL operation which has been executed.
+pub fn observe_operation(name: Option<&str>, is_ok: bool, elapsed: Duration) {
+    let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+    let mut metrics = METRICS.lock().unwrap();
+    let name = name.unwrap_or("anonymous");
+    let is_tracked = metrics.operations.contains_key(name);
+    let name = if is_tracked || metrics.operations.len() < MAX_OPERATIONS {
+        name
+    } else {
+        "other"
+    };
+
+    let operation = metrics.operations.entry(name.to_string()).or_default();
+    if is_ok {
+        operation.ok += 1;
+    } else {
+        operation.errors += 1;
+    }
+    if let Some(bucket) = BUCKETS.iter().position(|&bound| seconds <= bound) {
+        operation.buckets[bucket] += 1;
+    }
+    operation.seconds += seconds;
+}
+
+pub fn auth_cache_hit() {
+    METRICS.lock().unwrap().auth_cache_hits += 1;
+}
+
+pub fn auth_cache_miss() {
+    METRICS.lock().unwrap().auth_cache_misses += 1;
+}
+
+/// Renders every metric. The active attempts are left out when they could
+/// not be counted.
+pub fn render(pool: State, pool_size: u32, active_attempts: Option<usize>) -> String {
+    let metrics = METRICS.lock().unwrap();
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "paryxa_graphql_requests_total",
+        "counter",
+        "GraphQL operations executed.",
+    );
+    let mut names: Vec<&String> = metrics.operations.keys().collect();
+    names.sort();
+    for &name in &names {
+        let operation = &metrics.operations[name];
+        let name = escape(name);
+        for &(status, count) in &[("ok", operation.ok), ("error", operation.errors)] {
+            writeln!(
+                out,
+                "paryxa_graphql_requests_total{{operation=\"{}\",status=\"{}\"}} {}",
+                name, status, count
+            ).unwrap();
+        }
+    }
+
+    header(
+        &mut out,
+        "paryxa_graphql_request_duration_seconds",
+        "histogram",
+        "Time taken to execute GraphQL operations.",
+    );
+    for &name in &names {
+        let operation = &metrics.operations[name];
+        let name = escape(name);
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(&operation.buckets) {
+            cumulative += count;
+            writeln!(
+                out,
+                "paryxa_graphql_request_duration_seconds_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                name, bound, cumulative
+            ).unwrap();
+        }
+        let count = operation.ok + operation.errors;
+        writeln!(
+            out,
+            "paryxa_graphql_request_duration_seconds_bucket{{operation=\"{}\",le=\"+Inf\"}} {}",
+            name, count
+        ).unwrap();
+        writeln!(
+            out,
+            "paryxa_graphql_request_duration_seconds_sum{{operation=\"{}\"}} {}",
+            name, operation.seconds
+        ).unwrap();
+        writeln!(
+            out,
+            "paryxa_graphql_request_duration_seconds_count{{operation=\"{}\"}} {}",
+            name, count
+        ).unwrap();
+    }
+
+    gauge(
+        &mut out,
+        "paryxa_db_pool_connections",
+        "Open connections to Postgres.",
+        pool.connections,
+    );
+    gauge(
+        &mut out,
+        "paryxa_db_pool_idle_connections",
+        "Open connections to Postgres which are not in use.",
+        pool.idle_connections,
+    );
+    gauge(
+        &mut out,
+        "paryxa_db_pool_max_connections",
+        "Most connections the pool opens to Postgres.",
+        pool_size,
+    );
+
+    header(
+        &mut out,
+        "paryxa_auth_cache_hits_total",
+        "counter",
+        "Logins found in the auth cache.",
+    );
+    writeln!(
+        out,
+        "paryxa_auth_cache_hits_total {}",
+        metrics.auth_cache_hits
+    ).unwrap();
+    header(
+        &mut out,
+        "paryxa_auth_cache_misses_total",
+        "counter",
+        "Logins verified because they were not in the auth cache.",
+    );
+    writeln!(
+        out,
+        "paryxa_auth_cache_misses_total {}",
+        metrics.auth_cache_misses
+    ).unwrap();
+
+    if let Some(active_attempts) = active_attempts {
+        gauge(
+            &mut out,
+            "paryxa_active_attempts",
+            "Unfinished attempts of the tests happening right now.",
+            active_attempts,
+        );
+    }
+    out
+}
+
+fn header(out: &mut String, name: &str, type_: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, type_).unwrap();
+}
+
+fn gauge<V: ::std::fmt::Display>(out: &mut String, name: &str, help: &str, value: V) {
+    header(out, name, "gauge", help);
+    writeln!(out, "{} {}", name, value).unwrap();
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
@@ -0,0 +1,188 @@
+use diesel::{self, dsl, prelude::*};
+use errors::{Error, SResult};
+use loaders::Loaders;
+use models::{
+    draw_rule::DrawRule,
+    question_option::QuestionOption,
+    test_attempt::TestAttempt,
+    test_question::{Difficulty, QuestionOwner, TestQuestion},
+};
+use schema::attempt_questions;
+use shuffle::{derive_seed, new_seed, shuffle};
+use std::collections::HashSet;
+use uuid::Uuid;
+use Context;
+
+/// A question as presented to the candidate of a test attempt.
+pub struct AttemptQuestion {
+    pub question: TestQuestion,
+    /// Seed of the attempt when it was shuffled.
+    pub shuffle_seed: Option<i64>,
+    /// Whether explanations and feedback are revealed.
+    pub reviewable: bool,
+}
+
+impl AttemptQuestion {
+    /// Options of the question in the order the candidate sees them.
+    pub fn options(
+        &self,
+        loaders: &Loaders,
+        conn: &PgConnection,
+    ) -> SResult<Vec<AttemptOption>> {
+        let mut options = loaders.question_options(self.question.id, conn)?;
+        if let Some(seed) = self.shuffle_seed {
+            shuffle(&mut options, derive_seed(seed, self.question.id));
+        }
+        Ok(options
+            .into_iter()
+            .map(|option| AttemptOption {
+                option,
+                reviewable: self.reviewable,
+            }).collect())
+    }
+
+    /// Whether the questions of an attempt were frozen when it was started.
+    /// Attempts started before question banks existed are not.
+    pub fn is_frozen(test_attempt_id: i32, conn: &PgConnection) -> SResult<bool> {
+        Ok(diesel::select(dsl::exists(
+            attempt_questions::table.filter(attempt_questions::test_attempt_id.eq(test_attempt_id)),
+        )).get_result(conn)?)
+    }
+
+    /// Freezes the questions of a newly started attempt. These are all the
+    /// questions of the test paper followed by the random draws from its
+    /// question banks.
+    pub fn freeze(attempt: &TestAttempt, conn: &PgConnection) -> SResult<()> {
+        let mut question_ids =
+            TestQuestion::find_all_ids(QuestionOwner::TestPaper(attempt.test_paper_id), conn)?;
+        let mut drawn: HashSet<i32> = HashSet::new();
+        let draw_seed = new_seed(attempt.id);
+
+        for rule in DrawRule::find_all_for_test_paper(attempt.test_paper_id, conn)? {
+            // Two rules may draw from the same bank, so never draw a question
+            // twice.
+            let mut pool: Vec<i32> =
+                TestQuestion::find_all_ids(QuestionOwner::QuestionBank(rule.question_bank_id), conn)?
+                    .into_iter()
+                    .filter(|id| !drawn.contains(id))
+                    .collect();
+            let count = rule.question_count as usize;
+            if pool.len() < count {
+                Err(Error::InsufficientQuestions)?;
+            }
+
+            shuffle(&mut pool, derive_seed(draw_seed, rule.id));
+            pool.truncate(count);
+            drawn.extend(&pool);
+            question_ids.extend(pool);
+        }
+
+        let new_questions: Vec<_> = (0..)
+            .zip(question_ids)
+            .map(|(position, test_question_id)| NewAttemptQuestion {
+                test_attempt_id: attempt.id,
+                test_question_id,
+                position,
+            }).collect();
+        diesel::insert_into(attempt_questions::table)
+            .values(new_questions)
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+graphql_object!(AttemptQuestion: Context | &self | {
+    description: "A question of a test attempt in the order it was presented."
+
+    field id() -> Uuid
+        as "Id of a question."
+    {
+        self.question.uuid
+    }
+
+    field question() -> &str
+        as "The actual question."
+    {
+        &self.question.question
+    }
+
+    field topic() -> &Option<String>
+        as "Topic of a question."
+    {
+        &self.question.topic
+    }
+
+    field difficulty() -> &Option<Difficulty>
+        as "Difficulty level of a question."
+    {
+        &self.question.difficulty
+    }
+
+    field explanation() -> Option<&str>
+        as "Explanation of the answer. Only revealed once the attempt is reviewable."
+    {
+        if self.reviewable {
+            self.question.explanation.as_ref().map(String::as_str)
+        } else {
+            None
+        }
+    }
+
+    field options(&executor) -> SResult<Vec<AttemptOption>>
+        as "Options of a question in the order they were presented."
+    {
+        let ctx = executor.context();
+        self.options(&ctx.loaders, &ctx.conn)
+    }
+});
+
+/// An option of a question as presented to the candidate of a test attempt.
+pub struct AttemptOption {
+    pub option: QuestionOption,
+    /// Whether correctness and feedback are revealed.
+    pub reviewable: bool,
+}
+
+graphql_object!(AttemptOption: () | &self | {
+    description: "An option of a question of a test attempt."
+
+    field id() -> Uuid
+        as "Id of an option."
+    {
+        self.option.uuid
+    }
+
+    field option() -> &str
+        as "The actual option."
+    {
+        &self.option.option
+    }
+
+    field is_correct() -> Option<bool>
+        as "Specifies whether an option is correct. Only revealed once the attempt is reviewable."
+    {
+        if self.reviewable {
+            self.option.is_correct
+        } else {
+            None
+        }
+    }
+
+    field feedback() -> Option<&str>
+        as "Feedback for choosing this option. Only revealed once the attempt is reviewable."
+    {
+        if self.reviewable {
+            self.option.feedback.as_ref().map(String::as_str)
+        } else {
+            None
+        }
+    }
+});
+
+#[derive(Insertable)]
+#[table_name = "attempt_questions"]
+struct NewAttemptQuestion {
+    test_attempt_id: i32,
+    test_question_id: i32,
+    position: i32,
+}
@@ -0,0 +1,129 @@
+use diesel::{self, prelude::*};
+use errors::SResult;
+use models::question_bank::QuestionBank;
+use schema::draw_rules;
+use uuid::Uuid;
+use validation::{Validate, Validator};
+use Context;
+
+/// A rule to draw a number of random questions from a question bank for
+/// every attempt of a test paper.
+#[derive(Identifiable, Queryable)]
+pub struct DrawRule {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub test_paper_id: i32,
+    pub question_bank_id: i32,
+    pub question_count: i32,
+}
+
+impl DrawRule {
+    pub fn find_all_for_test_paper(
+        test_paper_id: i32,
+        conn: &PgConnection,
+    ) -> SResult<Vec<DrawRule>> {
+        Ok(draw_rules::table
+            .filter(draw_rules::test_paper_id.eq(test_paper_id))
+            .order(draw_rules::id)
+            .load(conn)?)
+    }
+
+    /// Copies all the draw rules of a test paper to another test paper.
+    pub fn copy_all(from_paper_id: i32, to_paper_id: i32, conn: &PgConnection) -> SResult<()> {
+        let new_rules: Vec<_> = DrawRule::find_all_for_test_paper(from_paper_id, conn)?
+            .into_iter()
+            .map(|rule| NewDrawRule {
+                test_paper_id: to_paper_id,
+                question_bank_id: rule.question_bank_id,
+                question_count: rule.question_count,
+            }).collect();
+        diesel::insert_into(draw_rules::table)
+            .values(new_rules)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn delete_all_for_test_paper(test_paper_id: i32, conn: &PgConnection) -> SResult<()> {
+        diesel::delete(draw_rules::table.filter(draw_rules::test_paper_id.eq(test_paper_id)))
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+graphql_object!(DrawRule: Context |&self| {
+    description: "A rule to draw random questions from a question bank."
+
+    field id() -> Uuid
+        as "Id of a draw rule."
+    {
+        self.uuid
+    }
+
+    field question_bank(&executor) -> SResult<QuestionBank>
+        as "Question bank to draw the questions from."
+    {
+        QuestionBank::find(self.question_bank_id, &executor.context().conn)
+    }
+
+    field question_count() -> i32
+        as "Number of questions drawn for every attempt."
+    {
+        self.question_count
+    }
+});
+
+#[derive(Insertable)]
+#[table_name = "draw_rules"]
+struct NewDrawRule {
+    test_paper_id: i32,
+    question_bank_id: i32,
+    question_count: i32,
+}
+
+/// A type to create a draw rule for a test paper.
+#[derive(GraphQLInputObject)]
+pub struct DrawRuleForm {
+    /// Id of a question bank.
+    question_bank_id: Uuid,
+    /// Number of questions to draw for every attempt.
+    question_count: i32,
+}
+
+impl Validate for DrawRuleForm {
+    fn validate(&self, validator: &mut Validator) {
+        validator.positive("questionCount", self.question_count);
+    }
+}
+
+impl DrawRuleForm {
+    pub fn save_multiple(
+        vec: Vec<DrawRuleForm>,
+        test_paper_id: i32,
+        conn: &PgConnection,
+    ) -> SResult<()> {
+        let mut new_rules = Vec::with_capacity(vec.len());
+        for form in vec {
+            let bank = QuestionBank::find_by_uuid(form.question_bank_id, conn)?;
+            new_rules.push(NewDrawRule {
+                test_paper_id,
+                question_bank_id: bank.id,
+                question_count: form.question_count,
+            });
+        }
+
+        diesel::insert_into(draw_rules::table)
+            .values(new_rules)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Replaces all the draw rules of a test paper.
+    pub fn replace_all(
+        vec: Vec<DrawRuleForm>,
+        test_paper_id: i32,
+        conn: &PgConnection,
+    ) -> SResult<()> {
+        DrawRule::delete_all_for_test_paper(test_paper_id, conn)?;
+        DrawRuleForm::save_multiple(vec, test_paper_id, conn)
+    }
+}
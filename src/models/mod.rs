@@ -1,10 +1,18 @@
+use errors::{Error, SResult};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+pub mod attempt_question;
+pub mod draw_rule;
 pub mod question_answer;
+pub mod question_bank;
 pub mod question_option;
 pub mod test_paper;
 pub mod test_question;
 pub mod test_attempt;
 pub mod test_schedule;
 pub mod test_subscription;
+pub mod topic_score;
 pub mod user;
 
 /// Merge values of `Option<T>` and `Option<bool>` into a patch value
@@ -24,3 +32,47 @@ impl<T> JoinPatch<T> for Option<T> {
         }
     }
 }
+
+/// Checks that `order` lists each of the `existing` ids exactly once.
+fn validate_order(existing: &[Uuid], order: &[Uuid]) -> SResult<()> {
+    let existing: HashSet<_> = existing.iter().collect();
+    let given: HashSet<_> = order.iter().collect();
+    if given.len() == order.len() && given == existing {
+        Ok(())
+    } else {
+        Err(Error::InvalidOrder)
+    }
+}
+
+/// Direction in which a list is sorted.
+#[derive(Clone, Copy, GraphQLEnum)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+/// Whether a list is sorted in the descending order, as it is ascending
+/// unless a direction is given.
+fn is_descending(direction: Option<SortDirection>) -> bool {
+    match direction {
+        Some(SortDirection::Desc) => true,
+        Some(SortDirection::Asc) | None => false,
+    }
+}
+
+/// Escapes the wildcards of a `LIKE` pattern.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Ids of copied rows keyed by the ids of their originals.
+pub type CopiedIds = HashMap<Uuid, Uuid>;
+
+/// Points an id of an original row to its copy, if it was copied.
+fn remap(id: &mut Uuid, copies: &CopiedIds) {
+    if let Some(copy) = copies.get(id) {
+        *id = *copy;
+    }
+}
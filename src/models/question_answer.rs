@@ -2,16 +2,16 @@ use diesel::{self, prelude::*};
 use models::{
     question_option::QuestionOption, test_attempt::TestAttempt, test_question::TestQuestion,
 };
-use schema::question_answers;
+use schema::{question_answers, question_options};
 use uuid::Uuid;
 use {errors::SResult, Context};
 
 #[derive(Identifiable, Queryable)]
 pub struct QuestionAnswer {
-    id: i32,
-    test_attempt_id: i32,
-    test_question_id: i32,
-    answered_option: i32,
+    pub id: i32,
+    pub test_attempt_id: i32,
+    pub test_question_id: i32,
+    pub answered_option: i32,
 }
 
 impl QuestionAnswer {
@@ -34,6 +34,21 @@ impl QuestionAnswer {
             .filter(question_answers::test_attempt_id.eq(test_attempt_id))
             .load(conn)?)
     }
+
+    /// Ids of the questions answered with a correct option in an attempt.
+    pub fn find_correct_question_ids(
+        test_attempt_id: i32,
+        conn: &PgConnection,
+    ) -> SResult<Vec<i32>> {
+        Ok(question_answers::table
+            .inner_join(question_options::table)
+            .filter(
+                question_answers::test_attempt_id
+                    .eq(test_attempt_id)
+                    .and(question_options::is_correct.eq(true)),
+            ).select(question_answers::test_question_id)
+            .load(conn)?)
+    }
 }
 
 graphql_object!(QuestionAnswer: Context | &self | {
@@ -42,13 +57,15 @@ graphql_object!(QuestionAnswer: Context | &self | {
     field question(&executor) -> SResult<TestQuestion> 
         as "Question to which this answer answers."
     {
-        TestQuestion::find(self.test_question_id, &executor.context().conn)
+        let ctx = executor.context();
+        ctx.loaders.question(self.test_question_id, &ctx.conn)
     }
 
     field answered_option(&executor) -> SResult<QuestionOption> 
         as "The selected option which is an answer."
     {
-        QuestionOption::find(self.answered_option, &executor.context().conn)
+        let ctx = executor.context();
+        ctx.loaders.option(self.answered_option, &ctx.conn)
     }
 });
 
@@ -96,11 +113,7 @@ pub struct ProvideAnswer {
 impl ProvideAnswer {
     pub fn save(self, user_id: i32, conn: &PgConnection) -> SResult<QuestionAnswer> {
         let attempt = TestAttempt::find_by_uuid_for_user(self.test_attempt_id, user_id, conn)?;
-        let question = TestQuestion::find_by_uuid_for_test_paper(
-            self.test_question_id,
-            attempt.test_paper_id,
-            conn,
-        )?;
+        let question = attempt.question(self.test_question_id, conn)?;
         let option = QuestionOption::find_by_uuid_for_test_question(
             self.answered_option,
             question.id,
@@ -0,0 +1,229 @@
+use diesel::{self, prelude::*};
+use errors::{Error, SResult};
+use models::test_question::{
+    QuestionFilter, QuestionOwner, TestQuestion, TestQuestionForm, TestQuestionsUpdate,
+};
+use schema::question_banks;
+use uuid::Uuid;
+use validation::{validate, Validate, Validator};
+use Context;
+
+#[derive(Identifiable, Queryable)]
+pub struct QuestionBank {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+impl QuestionBank {
+    pub fn find_all(conn: &PgConnection) -> SResult<Vec<QuestionBank>> {
+        Ok(question_banks::table.load(conn)?)
+    }
+
+    pub fn find(id: i32, conn: &PgConnection) -> SResult<QuestionBank> {
+        Ok(question_banks::table.find(id).get_result(conn)?)
+    }
+
+    pub fn find_by_uuid(uuid: Uuid, conn: &PgConnection) -> SResult<QuestionBank> {
+        Ok(question_banks::table
+            .filter(question_banks::uuid.eq(uuid))
+            .get_result(conn)?)
+    }
+
+    /// Deletes a question bank, unless any of its questions has been drawn
+    /// into a test attempt, as the answers would be deleted along with them.
+    pub fn delete_by_uuid(uuid: Uuid, conn: &PgConnection) -> SResult<QuestionBank> {
+        conn.transaction(|| {
+            let bank = QuestionBank::find_by_uuid(uuid, conn)?;
+            let owner = QuestionOwner::QuestionBank(bank.id);
+            if TestQuestion::any_attempted(None, owner, conn)? {
+                Err(Error::AlreadyAttempted)?;
+            }
+            Ok(diesel::delete(&bank).get_result(conn)?)
+        })
+    }
+}
+
+graphql_object!(QuestionBank: Context |&self| {
+    description: "A type representing a bank of questions to draw from."
+
+    field id() -> Uuid
+        as "Id of a question bank."
+    {
+        self.uuid
+    }
+
+    field name() -> &str
+        as "Name of a question bank."
+    {
+        &self.name
+    }
+
+    field description() -> &Option<String>
+        as "Description of a question bank."
+    {
+        &self.description
+    }
+
+    field total_questions(&executor) -> SResult<i32>
+        as "Number of questions in a question bank."
+    {
+        TestQuestion::count_questions(QuestionOwner::QuestionBank(self.id), &executor.context().conn)
+    }
+
+    field questions(&executor, filter: Option<QuestionFilter>) -> SResult<Vec<TestQuestion>>
+        as "Questions of a question bank."
+    {
+        let owner = QuestionOwner::QuestionBank(self.id);
+        let ctx = executor.context();
+        let questions = match filter {
+            Some(filter) => TestQuestion::find_filtered(owner, filter, &ctx.conn)?,
+            None => TestQuestion::find_all(owner, &ctx.conn)?,
+        };
+        ctx.loaders.register_questions(&questions);
+        Ok(questions)
+    }
+
+    field question(&executor, id: Uuid) -> SResult<TestQuestion>
+        as "Question of a question bank with the given id."
+    {
+        TestQuestion::find_by_uuid_for_owner(
+            id,
+            QuestionOwner::QuestionBank(self.id),
+            &executor.context().conn,
+        )
+    }
+});
+
+#[derive(Insertable)]
+#[table_name = "question_banks"]
+struct NewQuestionBank {
+    name: String,
+    description: Option<String>,
+}
+
+impl NewQuestionBank {
+    fn save(self, conn: &PgConnection) -> SResult<QuestionBank> {
+        Ok(diesel::insert_into(question_banks::table)
+            .values(self)
+            .get_result(conn)?)
+    }
+}
+
+#[derive(AsChangeset)]
+#[table_name = "question_banks"]
+struct QuestionBankPatch {
+    name: Option<String>,
+    description: Option<Option<String>>,
+}
+
+impl QuestionBankPatch {
+    fn save(self, uuid: Uuid, conn: &PgConnection) -> SResult<QuestionBank> {
+        Ok(
+            diesel::update(question_banks::table.filter(question_banks::uuid.eq(uuid)))
+                .set(self)
+                .get_result(conn)?,
+        )
+    }
+
+    fn is_none(&self) -> bool {
+        match self {
+            QuestionBankPatch {
+                name: None,
+                description: None,
+            } => true,
+            _ => false,
+        }
+    }
+
+    fn save_or_find(self, uuid: Uuid, conn: &PgConnection) -> SResult<QuestionBank> {
+        if self.is_none() {
+            QuestionBank::find_by_uuid(uuid, conn)
+        } else {
+            self.save(uuid, conn)
+        }
+    }
+}
+
+/// A type to create new question bank.
+#[derive(GraphQLInputObject)]
+pub struct QuestionBankForm {
+    /// Name of a question bank.
+    name: String,
+    /// Description of a question bank.
+    description: Option<String>,
+    /// Questions in this question bank.
+    questions: Vec<TestQuestionForm>,
+}
+
+impl Validate for QuestionBankForm {
+    fn validate(&self, validator: &mut Validator) {
+        validator.not_blank("name", &self.name);
+        validator.each("questions", &self.questions);
+    }
+}
+
+impl QuestionBankForm {
+    pub fn save(self, conn: &PgConnection) -> SResult<QuestionBank> {
+        validate(&self)?;
+        conn.transaction(|| {
+            let new_bank = NewQuestionBank {
+                name: self.name,
+                description: self.description,
+            };
+            let saved_bank = new_bank.save(conn)?;
+            TestQuestionForm::save_multiple(
+                self.questions,
+                QuestionOwner::QuestionBank(saved_bank.id),
+                conn,
+            )?;
+            Ok(saved_bank)
+        })
+    }
+}
+
+/// A type to update question bank.
+#[derive(GraphQLInputObject)]
+pub struct QuestionBankUpdate {
+    /// Id of a question bank.
+    id: Uuid,
+    /// New name of a question bank.
+    name: Option<String>,
+    /// New description of a question bank.
+    description: Option<Option<String>>,
+    /// Value to update questions of this question bank.
+    questions: TestQuestionsUpdate,
+}
+
+impl Validate for QuestionBankUpdate {
+    fn validate(&self, validator: &mut Validator) {
+        if let Some(ref name) = self.name {
+            validator.not_blank("name", name);
+        }
+        validator.nested("questions", &self.questions);
+    }
+}
+
+impl QuestionBankUpdate {
+    pub fn save(self, conn: &PgConnection) -> SResult<QuestionBank> {
+        validate(&self)?;
+        conn.transaction(|| {
+            let bank_patch = QuestionBankPatch {
+                name: self.name,
+                description: self.description,
+            };
+            let saved = bank_patch.save_or_find(self.id, conn)?;
+            // Drawn questions are what the candidates answered, and banks
+            // have no versions to move the edits to.
+            let owner = QuestionOwner::QuestionBank(saved.id);
+            let changed = self.questions.changed_ids();
+            if !changed.is_empty() && TestQuestion::any_attempted(Some(&changed[..]), owner, conn)?
+            {
+                Err(Error::AlreadyAttempted)?;
+            }
+            self.questions.save(owner, conn)?;
+            Ok(saved)
+        })
+    }
+}
@@ -1,7 +1,10 @@
-use diesel::{self, prelude::*};
+use super::{remap, validate_order, CopiedIds};
+use diesel::{self, dsl, prelude::*};
 use errors::SResult;
 use schema::question_options;
 use uuid::Uuid;
+use validation::{Validate, Validator};
+use Context;
 
 #[derive(Identifiable, Queryable)]
 pub struct QuestionOption {
@@ -10,16 +13,32 @@ pub struct QuestionOption {
     pub option: String,
     pub test_question_id: i32,
     pub is_correct: Option<bool>,
+    pub position: i32,
+    pub feedback: Option<String>,
 }
 
 impl QuestionOption {
-    pub fn find(id: i32, conn: &PgConnection) -> SResult<QuestionOption> {
-        Ok(question_options::table.find(id).get_result(conn)?)
-    }
-
     pub fn find_all(test_question_id: i32, conn: &PgConnection) -> SResult<Vec<QuestionOption>> {
         Ok(question_options::table
             .filter(question_options::test_question_id.eq(test_question_id))
+            .order((question_options::position, question_options::id))
+            .load(conn)?)
+    }
+
+    /// Options of many questions, in the order of each question.
+    pub fn find_all_for_questions(
+        test_question_ids: &[i32],
+        conn: &PgConnection,
+    ) -> SResult<Vec<QuestionOption>> {
+        Ok(question_options::table
+            .filter(question_options::test_question_id.eq_any(test_question_ids))
+            .order((question_options::position, question_options::id))
+            .load(conn)?)
+    }
+
+    pub fn find_many(ids: &[i32], conn: &PgConnection) -> SResult<Vec<QuestionOption>> {
+        Ok(question_options::table
+            .filter(question_options::id.eq_any(ids))
             .load(conn)?)
     }
 
@@ -36,6 +55,62 @@ impl QuestionOption {
             ).get_result(conn)?)
     }
 
+    /// Position after the last option of a question.
+    fn next_position(test_question_id: i32, conn: &PgConnection) -> SResult<i32> {
+        let last: Option<i32> = question_options::table
+            .filter(question_options::test_question_id.eq(test_question_id))
+            .select(dsl::max(question_options::position))
+            .get_result(conn)?;
+        Ok(last.map_or(0, |last| last + 1))
+    }
+
+    /// Reorders all the options of a question as per the given ids.
+    pub fn reorder(order: Vec<Uuid>, test_question_id: i32, conn: &PgConnection) -> SResult<()> {
+        conn.transaction(|| {
+            let existing: Vec<Uuid> = question_options::table
+                .filter(question_options::test_question_id.eq(test_question_id))
+                .select(question_options::uuid)
+                .load(conn)?;
+            validate_order(&existing, &order)?;
+
+            for (position, uuid) in order.into_iter().enumerate() {
+                diesel::update(
+                    question_options::table.filter(
+                        question_options::uuid
+                            .eq(uuid)
+                            .and(question_options::test_question_id.eq(test_question_id)),
+                    ),
+                ).set(question_options::position.eq(position as i32))
+                .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Copies all the options of a question to another question.
+    pub fn copy_all(
+        from_question_id: i32,
+        to_question_id: i32,
+        copies: &mut CopiedIds,
+        conn: &PgConnection,
+    ) -> SResult<()> {
+        for option in QuestionOption::find_all(from_question_id, conn)? {
+            let new_option = NewQuestionOption {
+                option: option.option,
+                test_question_id: to_question_id,
+                is_correct: option.is_correct,
+                position: option.position,
+                feedback: option.feedback,
+            };
+            let uuid: Uuid = diesel::insert_into(question_options::table)
+                .values(new_option)
+                .returning(question_options::uuid)
+                .get_result(conn)?;
+            copies.insert(option.uuid, uuid);
+        }
+        Ok(())
+    }
+
     fn delete_multiple(vec: Vec<Uuid>, test_question_id: i32, conn: &PgConnection) -> SResult<()> {
         let delete_count = diesel::delete(
             question_options::table.filter(
@@ -52,7 +127,7 @@ impl QuestionOption {
     }
 }
 
-graphql_object!(QuestionOption: () | &self | {
+graphql_object!(QuestionOption: Context | &self | {
     description: "A type representing an option."
 
     field id() -> Uuid 
@@ -67,10 +142,18 @@ graphql_object!(QuestionOption: () | &self | {
         &self.option
     }
 
-    field is_correct() -> Option<bool> 
-        as "Specifies whether an option is correct."
+    field is_correct(&executor) -> SResult<Option<bool>> 
+        as "Specifies whether an option is correct. Candidates see it through a reviewable attempt."
     {
-        self.is_correct
+        executor.context().admin_only()?;
+        Ok(self.is_correct)
+    }
+
+    field feedback(&executor) -> SResult<&Option<String>> 
+        as "Feedback shown when this option is chosen. Candidates see it through a reviewable attempt."
+    {
+        executor.context().admin_only()?;
+        Ok(&self.feedback)
     }
 });
 
@@ -80,6 +163,8 @@ struct NewQuestionOption {
     option: String,
     test_question_id: i32,
     is_correct: Option<bool>,
+    position: i32,
+    feedback: Option<String>,
 }
 
 impl NewQuestionOption {
@@ -96,6 +181,7 @@ impl NewQuestionOption {
 struct QuestionOptionPatch {
     option: Option<String>,
     is_correct: Option<Option<bool>>,
+    feedback: Option<Option<String>>,
 }
 
 impl QuestionOptionPatch {
@@ -116,9 +202,17 @@ impl QuestionOptionPatch {
 #[derive(GraphQLInputObject)]
 pub struct QuestionOptionForm {
     /// Option text.
-    option: String,
+    pub option: String,
     /// Specifies whether this option is correct.
-    is_correct: Option<bool>,
+    pub is_correct: Option<bool>,
+    /// Feedback shown when this option is chosen.
+    pub feedback: Option<String>,
+}
+
+impl Validate for QuestionOptionForm {
+    fn validate(&self, validator: &mut Validator) {
+        validator.not_blank("option", &self.option);
+    }
 }
 
 impl QuestionOptionForm {
@@ -127,12 +221,15 @@ impl QuestionOptionForm {
         test_question_id: i32,
         conn: &PgConnection,
     ) -> SResult<()> {
-        let new_options: Vec<_> = vec
-            .into_iter()
-            .map(|form| NewQuestionOption {
+        let first_position = QuestionOption::next_position(test_question_id, conn)?;
+        let new_options: Vec<_> = (first_position..)
+            .zip(vec)
+            .map(|(position, form)| NewQuestionOption {
                 option: form.option,
                 test_question_id,
                 is_correct: form.is_correct,
+                position,
+                feedback: form.feedback,
             }).collect();
 
         NewQuestionOption::save_multiple(new_options, conn)
@@ -141,13 +238,23 @@ impl QuestionOptionForm {
 
 /// A type to update an option for a question.
 #[derive(GraphQLInputObject)]
-struct QuestionOptionUpdate {
+pub struct QuestionOptionUpdate {
     /// Id of an option.
-    id: Uuid,
+    pub id: Uuid,
     /// New option text.
-    option: Option<String>,
+    pub option: Option<String>,
     /// Specifies whether an option is correct.
-    is_correct: Option<Option<bool>>,
+    pub is_correct: Option<Option<bool>>,
+    /// New feedback for an option.
+    pub feedback: Option<Option<String>>,
+}
+
+impl Validate for QuestionOptionUpdate {
+    fn validate(&self, validator: &mut Validator) {
+        if let Some(ref option) = self.option {
+            validator.not_blank("option", option);
+        }
+    }
 }
 
 impl QuestionOptionUpdate {
@@ -160,6 +267,7 @@ impl QuestionOptionUpdate {
             let opt_patch = QuestionOptionPatch {
                 option: opt.option,
                 is_correct: opt.is_correct,
+                feedback: opt.feedback,
             };
             opt_patch.save(opt.id, test_question_id, conn)?;
         }
@@ -171,14 +279,31 @@ impl QuestionOptionUpdate {
 #[derive(GraphQLInputObject)]
 pub struct QuestionOptionsUpdate {
     /// List of new options.
-    new: Vec<QuestionOptionForm>,
+    pub new: Vec<QuestionOptionForm>,
     /// List of updates for options.
-    update: Vec<QuestionOptionUpdate>,
+    pub update: Vec<QuestionOptionUpdate>,
     /// List of ids to delete options.
-    remove: Vec<Uuid>,
+    pub remove: Vec<Uuid>,
+}
+
+impl Validate for QuestionOptionsUpdate {
+    fn validate(&self, validator: &mut Validator) {
+        validator.each("new", &self.new);
+        validator.each("update", &self.update);
+    }
 }
 
 impl QuestionOptionsUpdate {
+    /// Points the updated and removed options to their copies.
+    pub fn remap(&mut self, copies: &CopiedIds) {
+        for opt in &mut self.update {
+            remap(&mut opt.id, copies);
+        }
+        for id in &mut self.remove {
+            remap(id, copies);
+        }
+    }
+
     pub fn save(self, test_question_id: i32, conn: &PgConnection) -> SResult<()> {
         QuestionOptionForm::save_multiple(self.new, test_question_id, conn)?;
         QuestionOptionUpdate::save_multiple(self.update, test_question_id, conn)?;
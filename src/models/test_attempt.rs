@@ -1,9 +1,17 @@
 use chrono::NaiveDateTime;
 use chrono::Utc;
+use connection::{Cursor, Seek};
 use diesel::{self, prelude::*};
-use errors::SResult;
-use models::{question_answer::QuestionAnswer, test_paper::TestPaper, test_schedule::TestSchedule};
-use schema::test_attempts;
+use errors::{Error, SResult};
+use models::{
+    attempt_question::AttemptQuestion, question_answer::QuestionAnswer, test_paper::TestPaper,
+    test_question::{QuestionOwner, TestQuestion},
+    test_schedule::TestSchedule,
+    topic_score::TopicScore,
+};
+use schema::{test_attempts, test_schedules};
+use shuffle::{new_seed, shuffle};
+use std::collections::HashSet;
 use uuid::Uuid;
 use Context;
 
@@ -17,9 +25,16 @@ pub struct TestAttempt {
     pub start_time: NaiveDateTime,
     pub finish_time: Option<NaiveDateTime>,
     pub has_withdrawn: Option<bool>,
+    pub shuffle_seed: Option<i64>,
 }
 
 impl TestAttempt {
+    pub fn find_by_uuid(uuid: Uuid, conn: &PgConnection) -> SResult<TestAttempt> {
+        Ok(test_attempts::table
+            .filter(test_attempts::uuid.eq(uuid))
+            .get_result(conn)?)
+    }
+
     pub fn find_by_uuid_for_user(
         uuid: Uuid,
         user_id: i32,
@@ -33,11 +48,104 @@ impl TestAttempt {
             ).get_result(conn)?)
     }
 
-    pub fn find_for_user(user_id: i32, conn: &PgConnection) -> SResult<Vec<TestAttempt>> {
+    /// Attempts which are neither finished nor withdrawn.
+    pub fn find_all_unfinished(conn: &PgConnection) -> SResult<Vec<TestAttempt>> {
         Ok(test_attempts::table
-            .filter(test_attempts::user_id.eq(user_id))
+            .filter(
+                test_attempts::finish_time
+                    .is_null()
+                    .and(test_attempts::has_withdrawn.is_distinct_from(true)),
+            ).order(test_attempts::start_time)
             .load(conn)?)
     }
+
+    /// Number of unfinished attempts of the tests which are happening right
+    /// now.
+    pub fn count_active(conn: &PgConnection) -> SResult<usize> {
+        let schedule_ids: Vec<i32> = test_attempts::table
+            .select(test_attempts::test_schedule_id)
+            .filter(
+                test_attempts::finish_time
+                    .is_null()
+                    .and(test_attempts::has_withdrawn.is_distinct_from(true)),
+            ).load(conn)?;
+        let happening: HashSet<i32> = test_schedules::table
+            .filter(test_schedules::id.eq_any(&schedule_ids))
+            .load::<TestSchedule>(conn)?
+            .into_iter()
+            .filter(TestSchedule::is_happening)
+            .map(|schedule| schedule.id)
+            .collect();
+        Ok(schedule_ids
+            .iter()
+            .filter(|id| happening.contains(id))
+            .count())
+    }
+
+    pub fn count_for_user(user_id: i32, conn: &PgConnection) -> SResult<i64> {
+        Ok(test_attempts::table
+            .filter(test_attempts::user_id.eq(user_id))
+            .count()
+            .get_result(conn)?)
+    }
+
+    /// A page of the attempts of a user in the order they were started.
+    pub fn find_page_for_user(
+        user_id: i32,
+        seek: &Seek,
+        conn: &PgConnection,
+    ) -> SResult<Vec<TestAttempt>> {
+        let attempts = test_attempts::table
+            .filter(test_attempts::user_id.eq(user_id))
+            .into_boxed();
+        Ok(seek!(attempts, seek, false, test_attempts::id).load(conn)?)
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        Cursor::new(None, self.id)
+    }
+
+    /// An attempt can be reviewed once it is finished and its schedule is
+    /// over, so that nothing is revealed while others are still taking it.
+    pub fn is_reviewable(&self, conn: &PgConnection) -> SResult<bool> {
+        if self.finish_time.is_none() {
+            return Ok(false);
+        }
+        let schedule = TestSchedule::find(self.test_schedule_id, conn)?;
+        Ok(schedule.has_ended())
+    }
+
+    /// Questions of the attempt in the order the candidate sees them.
+    pub fn questions(
+        &self,
+        reviewable: bool,
+        conn: &PgConnection,
+    ) -> SResult<Vec<AttemptQuestion>> {
+        let mut questions = if AttemptQuestion::is_frozen(self.id, conn)? {
+            TestQuestion::find_all_for_attempt(self.id, conn)?
+        } else {
+            TestQuestion::find_all(QuestionOwner::TestPaper(self.test_paper_id), conn)?
+        };
+        if let Some(seed) = self.shuffle_seed {
+            shuffle(&mut questions, seed);
+        }
+        Ok(questions
+            .into_iter()
+            .map(|question| AttemptQuestion {
+                question,
+                shuffle_seed: self.shuffle_seed,
+                reviewable,
+            }).collect())
+    }
+
+    /// A question of the attempt with the given id.
+    pub fn question(&self, uuid: Uuid, conn: &PgConnection) -> SResult<TestQuestion> {
+        if AttemptQuestion::is_frozen(self.id, conn)? {
+            TestQuestion::find_by_uuid_for_attempt(uuid, self.id, conn)
+        } else {
+            TestQuestion::find_by_uuid_for_test_paper(uuid, self.test_paper_id, conn)
+        }
+    }
 }
 
 graphql_object!(TestAttempt: Context | &self | {
@@ -50,13 +158,49 @@ graphql_object!(TestAttempt: Context | &self | {
     }
 
     field test_paper(&executor) -> SResult<TestPaper> 
-        as "Test paper being attempted."
+        as "Version of the test paper being attempted."
     {
         TestPaper::find(self.test_paper_id, &executor.context().conn)
     }
 
+    field questions(&executor) -> SResult<Vec<AttemptQuestion>> 
+        as "Questions of the test paper in the order they were presented in this attempt."
+    {
+        let ctx = executor.context();
+        let questions = self.questions(self.is_reviewable(&ctx.conn)?, &ctx.conn)?;
+        ctx.loaders.register_questions(questions.iter().map(|question| &question.question));
+        Ok(questions)
+    }
+
+    field is_reviewable(&executor) -> SResult<bool> 
+        as "Specifies whether explanations and feedback of this attempt are revealed."
+    {
+        self.is_reviewable(&executor.context().conn)
+    }
+
+    field is_shuffled() -> bool 
+        as "Specifies whether questions and options were shuffled for this attempt."
+    {
+        self.shuffle_seed.is_some()
+    }
+
+    field topic_scores(&executor) -> SResult<Vec<TopicScore>> 
+        as "Score of this attempt broken down by the topics of its questions. Empty for candidates until the attempt is reviewable."
+    {
+        // The scores would tell a candidate whether each answer is correct.
+        let ctx = executor.context();
+        if ctx.is_admin() || self.is_reviewable(&ctx.conn)? {
+            TopicScore::find_all_for_attempt(self, &ctx.conn)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
     field answers(&executor) -> SResult<Vec<QuestionAnswer>> {
-        QuestionAnswer::find_all(self.id, &executor.context().conn)
+        let ctx = executor.context();
+        let answers = QuestionAnswer::find_all(self.id, &ctx.conn)?;
+        ctx.loaders.register_answers(&answers);
+        Ok(answers)
     }
 
     field test_schedule(&executor) -> SResult<TestSchedule> 
@@ -93,6 +237,7 @@ struct NewTestAttempt {
     start_time: NaiveDateTime,
     finish_time: Option<NaiveDateTime>,
     has_withdrawn: Option<bool>,
+    shuffle_seed: Option<i64>,
 }
 
 impl NewTestAttempt {
@@ -153,16 +298,30 @@ pub struct StartTest {
 
 impl StartTest {
     pub fn save(self, user_id: i32, conn: &PgConnection) -> SResult<TestAttempt> {
-        let test_paper = TestPaper::find_by_uuid(self.test_paper_id, conn)?;
-        let test_schedule = TestSchedule::find_by_uuid(self.test_schedule_id, conn)?;
-        let new_test = NewTestAttempt {
-            user_id,
-            test_paper_id: test_paper.id,
-            test_schedule_id: test_schedule.id,
-            start_time: Utc::now().naive_utc(),
-            finish_time: None,
-            has_withdrawn: None,
-        };
-        new_test.save(conn)
+        conn.transaction(|| {
+            // Attempts are always taken against the latest version.
+            let test_paper =
+                TestPaper::find_by_uuid(self.test_paper_id, conn)?.latest_version(conn)?;
+            if !test_paper.is_published() {
+                Err(Error::NotPublished)?;
+            }
+            let test_schedule = TestSchedule::find_by_uuid(self.test_schedule_id, conn)?;
+            let new_test = NewTestAttempt {
+                user_id,
+                test_paper_id: test_paper.id,
+                test_schedule_id: test_schedule.id,
+                start_time: Utc::now().naive_utc(),
+                finish_time: None,
+                has_withdrawn: None,
+                shuffle_seed: if test_paper.shuffle {
+                    Some(new_seed(user_id))
+                } else {
+                    None
+                },
+            };
+            let saved = new_test.save(conn)?;
+            AttemptQuestion::freeze(&saved, conn)?;
+            Ok(saved)
+        })
     }
 }
@@ -1,19 +1,28 @@
+use chrono::NaiveDateTime;
+use connection::{Connection, Cursor, Page, Seek, SortKey};
 use db_types::*;
 use diesel::{
     self,
     deserialize::{self, FromSql},
+    dsl,
     pg::Pg,
     prelude::*,
     serialize::{self, IsNull, Output, ToSql},
 };
-use errors::SResult;
+use errors::{Error, SResult};
 use models::{
-    test_question::{TestQuestion, TestQuestionForm, TestQuestionsUpdate},
+    draw_rule::{DrawRule, DrawRuleForm},
+    test_question::{
+        QuestionFilter, QuestionOwner, TestQuestion, TestQuestionForm, TestQuestionsUpdate,
+    },
     test_schedule::TestSchedule,
+    test_subscription::TestSubscription,
+    is_descending, CopiedIds, SortDirection,
 };
-use schema::test_papers;
+use schema::{test_attempts, test_papers, test_questions};
 use std::io::Write;
 use uuid::Uuid;
+use validation::{validate, Validate, Validator};
 use Context;
 
 #[derive(Identifiable, Queryable)]
@@ -23,11 +32,112 @@ pub struct TestPaper {
     pub name: String,
     pub description: Option<String>,
     pub type_: TestType,
+    pub shuffle: bool,
+    pub status: PaperStatus,
+    pub version: i32,
+    pub previous_version_id: Option<i32>,
+    pub is_latest: bool,
+    pub created_at: NaiveDateTime,
+    pub lock_version: i32,
 }
 
 impl TestPaper {
+    /// Latest versions of all the test papers.
     pub fn find_all(conn: &PgConnection) -> SResult<Vec<TestPaper>> {
-        Ok(test_papers::table.load(conn)?)
+        Ok(TestPaper::latest(false, None)
+            .order(test_papers::id)
+            .load(conn)?)
+    }
+
+    /// Latest versions of the test papers matching the filter, or of the
+    /// published ones only.
+    fn latest(
+        published_only: bool,
+        filter: Option<&TestPaperFilter>,
+    ) -> test_papers::BoxedQuery<'static, Pg> {
+        let mut query = test_papers::table
+            .filter(test_papers::is_latest.eq(true))
+            .into_boxed();
+        if published_only {
+            query = query.filter(test_papers::status.eq(PaperStatus::Published));
+        }
+        if let Some(filter) = filter {
+            if let Some(type_) = filter.type_ {
+                query = query.filter(test_papers::type_.eq(type_));
+            }
+            if let Some(status) = filter.status {
+                query = query.filter(test_papers::status.eq(status));
+            }
+            if let Some(ref tags) = filter.tags {
+                let tagged = test_questions::table
+                    .select(test_questions::test_paper_id)
+                    .filter(test_questions::tags.overlaps_with(tags.clone()));
+                query = query.filter(test_papers::id.nullable().eq_any(tagged));
+            }
+            if let Some(created_after) = filter.created_after {
+                query = query.filter(test_papers::created_at.ge(created_after));
+            }
+            if let Some(created_before) = filter.created_before {
+                query = query.filter(test_papers::created_at.lt(created_before));
+            }
+        }
+        query
+    }
+
+    pub fn count_latest(
+        published_only: bool,
+        filter: Option<&TestPaperFilter>,
+        conn: &PgConnection,
+    ) -> SResult<i64> {
+        Ok(TestPaper::latest(published_only, filter)
+            .count()
+            .get_result(conn)?)
+    }
+
+    /// A page of the latest versions of the test papers, sorted by their ids
+    /// unless a sort is given. The id is always the last key so that the
+    /// cursors are unique.
+    pub fn find_page(
+        published_only: bool,
+        filter: Option<&TestPaperFilter>,
+        sort: Option<&TestPaperSort>,
+        seek: &Seek,
+        conn: &PgConnection,
+    ) -> SResult<Vec<TestPaper>> {
+        let papers = TestPaper::latest(published_only, filter);
+        let sort = match sort {
+            Some(sort) => sort,
+            None => return Ok(seek!(papers, seek, false, test_papers::id).load(conn)?),
+        };
+        let descending = is_descending(sort.direction);
+        let papers = match sort.field {
+            TestPaperSortField::CreatedAt => seek!(
+                papers,
+                seek,
+                descending,
+                test_papers::id,
+                test_papers::created_at,
+                key_time
+            ),
+            TestPaperSortField::Name => seek!(
+                papers,
+                seek,
+                descending,
+                test_papers::id,
+                test_papers::name,
+                key_text
+            ),
+        };
+        Ok(papers.load(conn)?)
+    }
+
+    /// Position of a test paper in a list with the sort.
+    pub fn cursor(&self, sort: Option<&TestPaperSort>) -> Cursor {
+        let key = sort.map(|sort| match sort.field {
+            TestPaperSortField::CreatedAt => SortKey::Time(self.created_at),
+            TestPaperSortField::Name => SortKey::Text(self.name.clone()),
+        });
+        Cursor::new(key, self.id)
     }
 
     pub fn find(id: i32, conn: &PgConnection) -> SResult<TestPaper> {
@@ -40,9 +150,236 @@ impl TestPaper {
             .get_result(conn)?)
     }
 
+    pub fn find_published_by_uuid(uuid: Uuid, conn: &PgConnection) -> SResult<TestPaper> {
+        Ok(test_papers::table
+            .filter(
+                test_papers::uuid
+                    .eq(uuid)
+                    .and(test_papers::status.eq(PaperStatus::Published)),
+            ).get_result(conn)?)
+    }
+
+    fn find_next_version(&self, conn: &PgConnection) -> SResult<Option<TestPaper>> {
+        Ok(test_papers::table
+            .filter(test_papers::previous_version_id.eq(self.id))
+            .get_result(conn)
+            .optional()?)
+    }
+
+    /// Follows the newer versions of a test paper up to the latest one.
+    pub fn latest_version(self, conn: &PgConnection) -> SResult<TestPaper> {
+        let mut paper = self;
+        while !paper.is_latest {
+            match paper.find_next_version(conn)? {
+                Some(next) => paper = next,
+                None => break,
+            }
+        }
+        Ok(paper)
+    }
+
+    /// A test paper with the given id, refusing to delete one of which any
+    /// version has been attempted. Deleting it would delete the answers, or
+    /// the schedules which moved along from the attempted versions.
     pub fn delete_by_uuid(uuid: Uuid, conn: &PgConnection) -> SResult<TestPaper> {
+        conn.transaction(|| {
+            let paper = TestPaper::find_by_uuid(uuid, conn)?;
+            if paper.any_version_attempted(conn)? {
+                Err(Error::AlreadyAttempted)?;
+            }
+            Ok(diesel::delete(&paper).get_result(conn)?)
+        })
+    }
+
+    /// Whether this or any of the previous versions of a test paper has been
+    /// attempted.
+    fn any_version_attempted(&self, conn: &PgConnection) -> SResult<bool> {
+        if self.has_attempts(conn)? {
+            return Ok(true);
+        }
+        let mut previous_version_id = self.previous_version_id;
+        while let Some(id) = previous_version_id {
+            let paper = TestPaper::find(id, conn)?;
+            if paper.has_attempts(conn)? {
+                return Ok(true);
+            }
+            previous_version_id = paper.previous_version_id;
+        }
+        Ok(false)
+    }
+
+    /// Creates an independent copy of a test paper with all its questions,
+    /// options and draw rules. The copy starts as a draft of its own, unless
+    /// the schedules are copied too, in which case it keeps the status of
+    /// the original so that only published papers stay scheduled.
+    pub fn clone_by_uuid(
+        uuid: Uuid,
+        name: String,
+        include_schedules: bool,
+        conn: &PgConnection,
+    ) -> SResult<TestPaper> {
+        conn.transaction(|| {
+            let paper = TestPaper::find_by_uuid(uuid, conn)?;
+            let new_paper = NewTestPaper {
+                name,
+                description: paper.description,
+                type_: paper.type_,
+                shuffle: paper.shuffle,
+                status: if include_schedules {
+                    paper.status
+                } else {
+                    PaperStatus::Draft
+                },
+                version: 1,
+                previous_version_id: None,
+            };
+            let saved = new_paper.save(conn)?;
+
+            TestQuestion::copy_all(
+                QuestionOwner::TestPaper(paper.id),
+                QuestionOwner::TestPaper(saved.id),
+                &mut CopiedIds::new(),
+                conn,
+            )?;
+            DrawRule::copy_all(paper.id, saved.id, conn)?;
+            if include_schedules {
+                TestSchedule::copy_all(paper.id, saved.id, conn)?;
+            }
+            Ok(saved)
+        })
+    }
+
+    pub fn has_attempts(&self, conn: &PgConnection) -> SResult<bool> {
+        Ok(diesel::select(dsl::exists(
+            test_attempts::table.filter(test_attempts::test_paper_id.eq(self.id)),
+        )).get_result(conn)?)
+    }
+
+    /// Fails unless the test paper can be changed in place, which is when it
+    /// is the latest version and has not been attempted.
+    pub fn check_editable(&self, conn: &PgConnection) -> SResult<()> {
+        if !self.is_latest {
+            Err(Error::NotLatestVersion)?;
+        }
+        if self.has_attempts(conn)? {
+            Err(Error::AlreadyAttempted)?;
+        }
+        Ok(())
+    }
+
+    /// Bumps the lock version of a test paper before it is updated, failing
+    /// if it was updated since the given version was loaded.
+    fn lock(uuid: Uuid, lock_version: i32, conn: &PgConnection) -> SResult<TestPaper> {
+        let locked = diesel::update(
+            test_papers::table.filter(
+                test_papers::uuid
+                    .eq(uuid)
+                    .and(test_papers::lock_version.eq(lock_version)),
+            ),
+        ).set(test_papers::lock_version.eq(test_papers::lock_version + 1))
+        .get_result(conn)
+        .optional()?;
+        match locked {
+            Some(paper) => Ok(paper),
+            None => {
+                TestPaper::find_by_uuid(uuid, conn)?;
+                Err(Error::StaleUpdate)
+            }
+        }
+    }
+
+    /// Creates the next version of a test paper. Attempts stay pinned to this
+    /// version while further edits go to the new one, so the schedules and
+    /// subscriptions move along to it.
+    fn new_version(&self, copies: &mut CopiedIds, conn: &PgConnection) -> SResult<TestPaper> {
+        let new_paper = NewTestPaper {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            type_: self.type_,
+            shuffle: self.shuffle,
+            status: self.status,
+            version: self.version + 1,
+            previous_version_id: Some(self.id),
+        };
+        let saved = new_paper.save(conn)?;
+        diesel::update(self)
+            .set(test_papers::is_latest.eq(false))
+            .execute(conn)?;
+
+        TestQuestion::copy_all(
+            QuestionOwner::TestPaper(self.id),
+            QuestionOwner::TestPaper(saved.id),
+            copies,
+            conn,
+        )?;
+        DrawRule::copy_all(self.id, saved.id, conn)?;
+        TestSchedule::move_all(self.id, saved.id, conn)?;
+        TestSubscription::move_all(self.id, saved.id, conn)?;
+        Ok(saved)
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.status == PaperStatus::Published
+    }
+
+    /// Publishes a test paper after checking that every question can be
+    /// answered correctly and that every draw rule can be fulfilled.
+    pub fn publish(uuid: Uuid, lock_version: i32, conn: &PgConnection) -> SResult<TestPaper> {
+        conn.transaction(|| {
+            let paper = TestPaper::lock(uuid, lock_version, conn)?;
+            let unanswerable =
+                TestQuestion::find_unanswerable(QuestionOwner::TestPaper(paper.id), conn)?;
+            if !unanswerable.is_empty() {
+                Err(Error::NotPublishable(unanswerable))?;
+            }
+
+            let mut question_count =
+                TestQuestion::count_questions(QuestionOwner::TestPaper(paper.id), conn)?;
+            for rule in DrawRule::find_all_for_test_paper(paper.id, conn)? {
+                question_count += rule.question_count;
+                let bank = QuestionOwner::QuestionBank(rule.question_bank_id);
+                if TestQuestion::count_questions(bank, conn)? < rule.question_count {
+                    Err(Error::InsufficientQuestions)?;
+                }
+                let unanswerable = TestQuestion::find_unanswerable(bank, conn)?;
+                if !unanswerable.is_empty() {
+                    Err(Error::NotPublishable(unanswerable))?;
+                }
+            }
+            if question_count == 0 {
+                Err(Error::NotPublishable(Vec::new()))?;
+            }
+
+            TestPaper::set_status(uuid, PaperStatus::Published, conn)
+        })
+    }
+
+    pub fn archive(uuid: Uuid, lock_version: i32, conn: &PgConnection) -> SResult<TestPaper> {
+        conn.transaction(|| {
+            TestPaper::lock(uuid, lock_version, conn)?;
+            TestPaper::set_status(uuid, PaperStatus::Archived, conn)
+        })
+    }
+
+    /// Reorders the questions of a test paper as per the given ids.
+    pub fn reorder_questions(
+        uuid: Uuid,
+        lock_version: i32,
+        order: Vec<Uuid>,
+        conn: &PgConnection,
+    ) -> SResult<TestPaper> {
+        conn.transaction(|| {
+            let paper = TestPaper::lock(uuid, lock_version, conn)?;
+            paper.check_editable(conn)?;
+            TestQuestion::reorder(order, QuestionOwner::TestPaper(paper.id), conn)?;
+            Ok(paper)
+        })
+    }
+
+    fn set_status(uuid: Uuid, status: PaperStatus, conn: &PgConnection) -> SResult<TestPaper> {
         Ok(
-            diesel::delete(test_papers::table.filter(test_papers::uuid.eq(uuid)))
+            diesel::update(test_papers::table.filter(test_papers::uuid.eq(uuid)))
+                .set(test_papers::status.eq(status))
                 .get_result(conn)?,
         )
     }
@@ -75,14 +412,91 @@ graphql_object!(TestPaper: Context |&self| {
         &self.type_
     }
 
-    field total_questions(&executor) -> SResult<i32> {
-        TestQuestion::count_questions_for_paper(self.id, &executor.context().conn)
+    field status() -> &PaperStatus 
+        as "Status of a test paper in its lifecycle."
+    {
+        &self.status
+    }
+
+    field shuffle() -> bool 
+        as "Specifies whether questions and options are shuffled for every attempt."
+    {
+        self.shuffle
     }
 
-    field questions(&executor) -> SResult<Vec<TestQuestion>> 
-        as "Questions of a test paper."
+    field lock_version() -> i32 
+        as "Counter of the updates of a test paper, which an update must send back."
     {
-        TestQuestion::find_all(self.id, &executor.context().conn)
+        self.lock_version
+    }
+
+    field version() -> i32 
+        as "Version of a test paper. A new version is created when an attempted paper is edited."
+    {
+        self.version
+    }
+
+    field is_latest() -> bool 
+        as "Specifies whether this is the latest version of a test paper."
+    {
+        self.is_latest
+    }
+
+    field created_at() -> &NaiveDateTime
+        as "Time when this version of a test paper was created."
+    {
+        &self.created_at
+    }
+
+    field previous_version(&executor) -> SResult<Option<TestPaper>> 
+        as "The version this test paper was created from."
+    {
+        match self.previous_version_id {
+            Some(id) => TestPaper::find(id, &executor.context().conn).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    field next_version(&executor) -> SResult<Option<TestPaper>> 
+        as "The version created from this test paper."
+    {
+        self.find_next_version(&executor.context().conn)
+    }
+
+    field total_questions(&executor) -> SResult<i32> 
+        as "Number of questions in every attempt of a test paper including the drawn ones."
+    {
+        let conn = &executor.context().conn;
+        let fixed = TestQuestion::count_questions(QuestionOwner::TestPaper(self.id), conn)?;
+        let drawn: i32 = DrawRule::find_all_for_test_paper(self.id, conn)?
+            .iter()
+            .map(|rule| rule.question_count)
+            .sum();
+        Ok(fixed + drawn)
+    }
+
+    field questions(
+        &executor,
+        filter: Option<QuestionFilter>,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>
+    ) -> SResult<Connection<TestQuestion>> 
+        as "Questions of a test paper which are a part of every attempt."
+    {
+        let ctx = executor.context();
+        let page = Page::new(first, after, last, before)?;
+        let questions = match filter {
+            Some(filter) => {
+                let owner = QuestionOwner::TestPaper(self.id);
+                let questions = TestQuestion::find_filtered(owner, filter, &ctx.conn)?;
+                ctx.loaders.register_questions(&questions);
+                questions
+            }
+            None => ctx.loaders.test_paper_questions(self.id, &ctx.conn)?,
+        };
+        Ok(Connection::from_vec(&page, questions, TestQuestion::cursor))
     }
 
     field question(&executor, id: Uuid) -> SResult<TestQuestion> 
@@ -91,6 +505,12 @@ graphql_object!(TestPaper: Context |&self| {
         TestQuestion::find_by_uuid_for_test_paper(id, self.id, &executor.context().conn)
     }
 
+    field draw_rules(&executor) -> SResult<Vec<DrawRule>> 
+        as "Rules to draw random questions from question banks for every attempt."
+    {
+        DrawRule::find_all_for_test_paper(self.id, &executor.context().conn)
+    }
+
     field test_schedules(&executor) -> SResult<Vec<TestSchedule>> 
         as "Schedules of a test paper."
     {
@@ -98,8 +518,39 @@ graphql_object!(TestPaper: Context |&self| {
     }
 });
 
+/// Filter for a list of test papers.
+#[derive(GraphQLInputObject)]
+pub struct TestPaperFilter {
+    /// Type of a test paper.
+    type_: Option<TestType>,
+    /// Status of a test paper.
+    status: Option<PaperStatus>,
+    /// Matches test papers with a question having any of these tags.
+    tags: Option<Vec<String>>,
+    /// Matches test papers created at or after this time.
+    created_after: Option<NaiveDateTime>,
+    /// Matches test papers created before this time.
+    created_before: Option<NaiveDateTime>,
+}
+
+/// Field by which a list of test papers is sorted.
+#[derive(Clone, Copy, GraphQLEnum)]
+pub enum TestPaperSortField {
+    CreatedAt,
+    Name,
+}
+
+/// Order of a list of test papers.
+#[derive(GraphQLInputObject)]
+pub struct TestPaperSort {
+    /// Field by which the test papers are sorted.
+    field: TestPaperSortField,
+    /// Direction of the sort, ascending unless given.
+    direction: Option<SortDirection>,
+}
+
 /// Type of a test.
-#[derive(Debug, FromSqlRow, AsExpression, GraphQLEnum)]
+#[derive(Debug, Clone, Copy, FromSqlRow, AsExpression, GraphQLEnum, Serialize, Deserialize)]
 #[sql_type = "Test_type"]
 pub enum TestType {
     Scheduled,
@@ -127,12 +578,51 @@ impl ToSql<Test_type, Pg> for TestType {
     }
 }
 
+/// Status of a test paper. Only published papers are listed to the
+/// candidates and can be scheduled.
+#[derive(
+    Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression, GraphQLEnum, Serialize, Deserialize,
+)]
+#[sql_type = "Paper_status"]
+pub enum PaperStatus {
+    Draft,
+    Published,
+    Archived,
+}
+
+impl FromSql<Paper_status, Pg> for PaperStatus {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let bytes = not_none!(bytes);
+        match bytes {
+            b"Draft" => Ok(PaperStatus::Draft),
+            b"Published" => Ok(PaperStatus::Published),
+            b"Archived" => Ok(PaperStatus::Archived),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl ToSql<Paper_status, Pg> for PaperStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match self {
+            PaperStatus::Draft => out.write_all(b"Draft")?,
+            PaperStatus::Published => out.write_all(b"Published")?,
+            PaperStatus::Archived => out.write_all(b"Archived")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
 #[derive(Insertable)]
 #[table_name = "test_papers"]
 struct NewTestPaper {
     name: String,
     description: Option<String>,
     type_: TestType,
+    shuffle: bool,
+    status: PaperStatus,
+    version: i32,
+    previous_version_id: Option<i32>,
 }
 
 impl NewTestPaper {
@@ -149,6 +639,7 @@ struct TestPaperPatch {
     name: Option<String>,
     description: Option<Option<String>>,
     type_: Option<TestType>,
+    shuffle: Option<bool>,
 }
 
 impl TestPaperPatch {
@@ -166,6 +657,7 @@ impl TestPaperPatch {
                 name: None,
                 description: None,
                 type_: None,
+                shuffle: None,
             } => true,
             _ => false,
         }
@@ -184,25 +676,51 @@ impl TestPaperPatch {
 #[derive(GraphQLInputObject)]
 pub struct TestPaperForm {
     /// Name of a test paper.
-    name: String,
+    pub name: String,
     /// Description of a test paper. 
-    description: Option<String>,
+    pub description: Option<String>,
     /// Type of a test paper.
-    type_: TestType,
+    pub type_: TestType,
+    /// Whether to shuffle questions and options for every attempt.
+    pub shuffle: Option<bool>,
     /// Questions in this test paper.
-    questions: Vec<TestQuestionForm>,
+    pub questions: Vec<TestQuestionForm>,
+    /// Rules to draw random questions from question banks.
+    pub draw_rules: Option<Vec<DrawRuleForm>>,
+}
+
+impl Validate for TestPaperForm {
+    fn validate(&self, validator: &mut Validator) {
+        validator.not_blank("name", &self.name);
+        validator.each("questions", &self.questions);
+        if let Some(ref draw_rules) = self.draw_rules {
+            validator.each("drawRules", draw_rules);
+        }
+    }
 }
 
 impl TestPaperForm {
     pub fn save(self, conn: &PgConnection) -> SResult<TestPaper> {
+        validate(&self)?;
         conn.transaction(|| {
             let new_paper = NewTestPaper {
                 name: self.name,
                 description: self.description,
                 type_: self.type_,
+                shuffle: self.shuffle.unwrap_or_default(),
+                status: PaperStatus::Draft,
+                version: 1,
+                previous_version_id: None,
             };
             let saved_paper = new_paper.save(conn)?;
-            TestQuestionForm::save_multiple(self.questions, saved_paper.id, conn)?;
+            TestQuestionForm::save_multiple(
+                self.questions,
+                QuestionOwner::TestPaper(saved_paper.id),
+                conn,
+            )?;
+            if let Some(draw_rules) = self.draw_rules {
+                DrawRuleForm::save_multiple(draw_rules, saved_paper.id, conn)?;
+            }
             Ok(saved_paper)
         })
     }
@@ -213,26 +731,81 @@ impl TestPaperForm {
 pub struct TestPaperUpdate {
     /// Id of a test paper.
     id: Uuid,
+    /// Lock version of the test paper being updated.
+    lock_version: i32,
     /// New name of a test paper.
     name: Option<String>,
     /// New description of a test paper.
     description: Option<Option<String>>,
     /// New type of a test paper.
     type_: Option<TestType>,
+    /// Whether to shuffle questions and options for every attempt.
+    shuffle: Option<bool>,
     /// Value to update questions of this test.
     questions: TestQuestionsUpdate,
+    /// New rules to draw random questions. Replaces all the older rules.
+    draw_rules: Option<Vec<DrawRuleForm>>,
+}
+
+impl Validate for TestPaperUpdate {
+    fn validate(&self, validator: &mut Validator) {
+        if let Some(ref name) = self.name {
+            validator.not_blank("name", name);
+        }
+        validator.nested("questions", &self.questions);
+        if let Some(ref draw_rules) = self.draw_rules {
+            validator.each("drawRules", draw_rules);
+        }
+    }
 }
 
 impl TestPaperUpdate {
+    /// An update which only changes the questions of a test paper.
+    pub fn questions(
+        id: Uuid,
+        lock_version: i32,
+        questions: TestQuestionsUpdate,
+    ) -> TestPaperUpdate {
+        TestPaperUpdate {
+            id,
+            lock_version,
+            name: None,
+            description: None,
+            type_: None,
+            shuffle: None,
+            questions,
+            draw_rules: None,
+        }
+    }
+
     pub fn save(self, conn: &PgConnection) -> SResult<TestPaper> {
+        validate(&self)?;
         conn.transaction(|| {
+            let mut paper = TestPaper::lock(self.id, self.lock_version, conn)?;
+            if !paper.is_latest {
+                Err(Error::NotLatestVersion)?;
+            }
+
+            // Never change what the candidates have already answered. Edit a
+            // new version instead, pointing the update to the copied rows.
+            let mut questions = self.questions;
+            if paper.has_attempts(conn)? {
+                let mut copies = CopiedIds::new();
+                paper = paper.new_version(&mut copies, conn)?;
+                questions.remap(&copies);
+            }
+
             let paper_patch = TestPaperPatch {
                 name: self.name,
                 description: self.description,
                 type_: self.type_,
+                shuffle: self.shuffle,
             };
-            let saved = paper_patch.save_or_find(self.id, conn)?;
-            self.questions.save(saved.id, conn)?;
+            let saved = paper_patch.save_or_find(paper.uuid, conn)?;
+            questions.save(QuestionOwner::TestPaper(saved.id), conn)?;
+            if let Some(draw_rules) = self.draw_rules {
+                DrawRuleForm::replace_all(draw_rules, saved.id, conn)?;
+            }
             Ok(saved)
         })
     }
@@ -1,8 +1,26 @@
-use diesel::{self, prelude::*, dsl};
-use errors::SResult;
-use models::question_option::{QuestionOption, QuestionOptionForm, QuestionOptionsUpdate};
-use schema::test_questions;
+use super::{remap, validate_order, CopiedIds};
+use connection::{Cursor, SortKey};
+use db_types::*;
+use diesel::{
+    self,
+    deserialize::{self, FromSql},
+    dsl,
+    expression::BoxableExpression,
+    pg::Pg,
+    prelude::*,
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::Bool,
+};
+use errors::{Error, SResult};
+use models::{
+    question_option::{QuestionOption, QuestionOptionForm, QuestionOptionsUpdate},
+    test_paper::TestPaper,
+};
+use schema::{attempt_questions, question_options, test_questions};
+use std::collections::HashSet;
+use std::io::Write;
 use uuid::Uuid;
+use validation::{Validate, Validator};
 use Context;
 
 #[derive(Identifiable, Queryable)]
@@ -10,20 +28,146 @@ pub struct TestQuestion {
     pub id: i32,
     pub uuid: Uuid,
     pub question: String,
-    pub test_paper_id: i32,
+    pub test_paper_id: Option<i32>,
+    pub position: i32,
+    pub question_bank_id: Option<i32>,
+    pub topic: Option<String>,
+    pub tags: Vec<String>,
+    pub difficulty: Option<Difficulty>,
+    pub explanation: Option<String>,
+    pub lock_version: i32,
+}
+
+/// What a question belongs to. A question is either a part of a test paper
+/// or of a question bank from which it is drawn.
+#[derive(Clone, Copy)]
+pub enum QuestionOwner {
+    TestPaper(i32),
+    QuestionBank(i32),
+}
+
+type OwnerFilter = Box<BoxableExpression<test_questions::table, Pg, SqlType = Bool>>;
+
+impl QuestionOwner {
+    fn filter(self) -> OwnerFilter {
+        match self {
+            QuestionOwner::TestPaper(id) => Box::new(test_questions::test_paper_id.eq(id)),
+            QuestionOwner::QuestionBank(id) => Box::new(test_questions::question_bank_id.eq(id)),
+        }
+    }
+
+    pub fn test_paper_id(self) -> Option<i32> {
+        match self {
+            QuestionOwner::TestPaper(id) => Some(id),
+            QuestionOwner::QuestionBank(_) => None,
+        }
+    }
+
+    pub fn question_bank_id(self) -> Option<i32> {
+        match self {
+            QuestionOwner::TestPaper(_) => None,
+            QuestionOwner::QuestionBank(id) => Some(id),
+        }
+    }
 }
 
 impl TestQuestion {
-    pub fn find(id: i32, conn: &PgConnection) -> SResult<TestQuestion> {
-        Ok(test_questions::table.find(id).get_result(conn)?)
+    pub fn find_by_uuid(uuid: Uuid, conn: &PgConnection) -> SResult<TestQuestion> {
+        Ok(test_questions::table
+            .filter(test_questions::uuid.eq(uuid))
+            .get_result(conn)?)
+    }
+
+    pub fn find_all(owner: QuestionOwner, conn: &PgConnection) -> SResult<Vec<TestQuestion>> {
+        Ok(test_questions::table
+            .filter(owner.filter())
+            .order((test_questions::position, test_questions::id))
+            .load(conn)?)
     }
 
-    pub fn find_all(test_paper_id: i32, conn: &PgConnection) -> SResult<Vec<TestQuestion>> {
+    /// Questions of many test papers, in the order of each test paper.
+    pub fn find_all_for_test_papers(
+        test_paper_ids: &[i32],
+        conn: &PgConnection,
+    ) -> SResult<Vec<TestQuestion>> {
+        Ok(test_questions::table
+            .filter(test_questions::test_paper_id.eq_any(test_paper_ids))
+            .order((test_questions::position, test_questions::id))
+            .load(conn)?)
+    }
+
+    pub fn find_many(ids: &[i32], conn: &PgConnection) -> SResult<Vec<TestQuestion>> {
         Ok(test_questions::table
-            .filter(test_questions::test_paper_id.eq(test_paper_id))
+            .filter(test_questions::id.eq_any(ids))
+            .load(conn)?)
+    }
+
+    pub fn find_filtered(
+        owner: QuestionOwner,
+        filter: QuestionFilter,
+        conn: &PgConnection,
+    ) -> SResult<Vec<TestQuestion>> {
+        let mut query = test_questions::table.filter(owner.filter()).into_boxed();
+        if let Some(topic) = filter.topic {
+            query = query.filter(test_questions::topic.eq(topic));
+        }
+        if let Some(tags) = filter.tags {
+            query = query.filter(test_questions::tags.overlaps_with(tags));
+        }
+        if let Some(difficulty) = filter.difficulty {
+            query = query.filter(test_questions::difficulty.eq(difficulty));
+        }
+        Ok(query
+            .order((test_questions::position, test_questions::id))
+            .load(conn)?)
+    }
+
+    pub fn find_all_ids(owner: QuestionOwner, conn: &PgConnection) -> SResult<Vec<i32>> {
+        Ok(test_questions::table
+            .filter(owner.filter())
+            .order((test_questions::position, test_questions::id))
+            .select(test_questions::id)
+            .load(conn)?)
+    }
+
+    /// Questions frozen for a test attempt in the order they were drawn.
+    pub fn find_all_for_attempt(
+        test_attempt_id: i32,
+        conn: &PgConnection,
+    ) -> SResult<Vec<TestQuestion>> {
+        Ok(attempt_questions::table
+            .inner_join(test_questions::table)
+            .filter(attempt_questions::test_attempt_id.eq(test_attempt_id))
+            .order(attempt_questions::position)
+            .select(test_questions::all_columns)
             .load(conn)?)
     }
 
+    pub fn find_by_uuid_for_attempt(
+        uuid: Uuid,
+        test_attempt_id: i32,
+        conn: &PgConnection,
+    ) -> SResult<TestQuestion> {
+        Ok(attempt_questions::table
+            .inner_join(test_questions::table)
+            .filter(
+                attempt_questions::test_attempt_id
+                    .eq(test_attempt_id)
+                    .and(test_questions::uuid.eq(uuid)),
+            ).select(test_questions::all_columns)
+            .get_result(conn)?)
+    }
+
+    pub fn find_by_uuid_for_owner(
+        uuid: Uuid,
+        owner: QuestionOwner,
+        conn: &PgConnection,
+    ) -> SResult<TestQuestion> {
+        Ok(test_questions::table
+            .filter(owner.filter().and(test_questions::uuid.eq(uuid)))
+            .get_result(conn)?)
+    }
+
     pub fn find_by_uuid_for_test_paper(
         uuid: Uuid,
         test_paper_id: i32,
@@ -37,21 +181,195 @@ impl TestQuestion {
             ).get_result(conn)?)
     }
 
-    pub fn count_questions_for_paper(test_paper_id: i32, conn: &PgConnection) -> SResult<i32> {
+    /// Whether any of the questions of the owner has been drawn into a test
+    /// attempt, or any of the given ones when ids are given.
+    pub fn any_attempted(
+        uuids: Option<&[Uuid]>,
+        owner: QuestionOwner,
+        conn: &PgConnection,
+    ) -> SResult<bool> {
+        let mut questions = test_questions::table
+            .filter(owner.filter())
+            .select(test_questions::id)
+            .into_boxed();
+        if let Some(uuids) = uuids {
+            questions = questions.filter(test_questions::uuid.eq_any(uuids.to_vec()));
+        }
+        Ok(diesel::select(dsl::exists(
+            attempt_questions::table.filter(attempt_questions::test_question_id.eq_any(questions)),
+        )).get_result(conn)?)
+    }
+
+    /// Position of a question in the list of the questions of its owner.
+    pub fn cursor(&self) -> Cursor {
+        Cursor::new(Some(SortKey::Int(self.position)), self.id)
+    }
+
+    /// Fails unless the question can be changed in place. A question of a
+    /// test paper follows the paper, while a question of a question bank must
+    /// not have been drawn into an attempt.
+    pub fn check_editable(&self, conn: &PgConnection) -> SResult<()> {
+        match (self.test_paper_id, self.question_bank_id) {
+            (Some(test_paper_id), _) => TestPaper::find(test_paper_id, conn)?.check_editable(conn),
+            (None, Some(question_bank_id)) => {
+                let owner = QuestionOwner::QuestionBank(question_bank_id);
+                if TestQuestion::any_attempted(Some(&[self.uuid][..]), owner, conn)? {
+                    Err(Error::AlreadyAttempted)?;
+                }
+                Ok(())
+            }
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Ids of the questions which have no options or no correct option.
+    pub fn find_unanswerable(owner: QuestionOwner, conn: &PgConnection) -> SResult<Vec<Uuid>> {
+        let questions: Vec<(i32, Uuid)> = test_questions::table
+            .filter(owner.filter())
+            .order((test_questions::position, test_questions::id))
+            .select((test_questions::id, test_questions::uuid))
+            .load(conn)?;
+        let ids: Vec<i32> = questions.iter().map(|&(id, _)| id).collect();
+        let answerable: HashSet<i32> = question_options::table
+            .filter(
+                question_options::test_question_id
+                    .eq_any(&ids)
+                    .and(question_options::is_correct.eq(true)),
+            ).select(question_options::test_question_id)
+            .load::<i32>(conn)?
+            .into_iter()
+            .collect();
+
+        Ok(questions
+            .into_iter()
+            .filter(|(id, _)| !answerable.contains(id))
+            .map(|(_, uuid)| uuid)
+            .collect())
+    }
+
+    pub fn count_questions(owner: QuestionOwner, conn: &PgConnection) -> SResult<i32> {
         let count: i64 = test_questions::table
-            .filter(test_questions::test_paper_id.eq(test_paper_id))
+            .filter(owner.filter())
             .select(dsl::count_star())
             .get_result(conn)?;
         Ok(count as i32)
     }
 
-    fn delete_multiple(vec: Vec<Uuid>, test_paper_id: i32, conn: &PgConnection) -> SResult<()> {
-        let delete_count = diesel::delete(
+    /// Position after the last question of a test paper or a question bank.
+    fn next_position(owner: QuestionOwner, conn: &PgConnection) -> SResult<i32> {
+        let last: Option<i32> = test_questions::table
+            .filter(owner.filter())
+            .select(dsl::max(test_questions::position))
+            .get_result(conn)?;
+        Ok(last.map_or(0, |last| last + 1))
+    }
+
+    /// Reorders all the questions of a test paper or a question bank as per
+    /// the given ids.
+    pub fn reorder(order: Vec<Uuid>, owner: QuestionOwner, conn: &PgConnection) -> SResult<()> {
+        conn.transaction(|| {
+            let existing: Vec<Uuid> = test_questions::table
+                .filter(owner.filter())
+                .select(test_questions::uuid)
+                .load(conn)?;
+            validate_order(&existing, &order)?;
+
+            for (position, uuid) in order.into_iter().enumerate() {
+                diesel::update(
+                    test_questions::table.filter(owner.filter().and(test_questions::uuid.eq(uuid))),
+                ).set(test_questions::position.eq(position as i32))
+                .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Reorders the options of a question as per the given ids, bumping its
+    /// lock version. Fails if it was updated since the given version was
+    /// loaded.
+    pub fn reorder_options(
+        uuid: Uuid,
+        lock_version: i32,
+        order: Vec<Uuid>,
+        conn: &PgConnection,
+    ) -> SResult<TestQuestion> {
+        conn.transaction(|| {
+            let question = TestQuestion::find_by_uuid(uuid, conn)?;
+            question.check_editable(conn)?;
+            let locked: Option<TestQuestion> = diesel::update(
+                test_questions::table.filter(
+                    test_questions::id
+                        .eq(question.id)
+                        .and(test_questions::lock_version.eq(lock_version)),
+                ),
+            ).set(test_questions::lock_version.eq(test_questions::lock_version + 1))
+            .get_result(conn)
+            .optional()?;
+            let question = locked.ok_or(Error::StaleUpdate)?;
+            QuestionOption::reorder(order, question.id, conn)?;
+            Ok(question)
+        })
+    }
+
+    /// Copies all the questions along with their options from one owner to
+    /// another.
+    pub fn copy_all(
+        from: QuestionOwner,
+        to: QuestionOwner,
+        copies: &mut CopiedIds,
+        conn: &PgConnection,
+    ) -> SResult<()> {
+        for quest in TestQuestion::find_all(from, conn)? {
+            let new_quest = NewTestQuestion {
+                question: quest.question,
+                test_paper_id: to.test_paper_id(),
+                position: quest.position,
+                question_bank_id: to.question_bank_id(),
+                topic: quest.topic,
+                tags: quest.tags,
+                difficulty: quest.difficulty,
+                explanation: quest.explanation,
+                lock_version: quest.lock_version,
+            };
+            let (new_id, new_uuid): (i32, Uuid) = diesel::insert_into(test_questions::table)
+                .values(new_quest)
+                .returning((test_questions::id, test_questions::uuid))
+                .get_result(conn)?;
+            copies.insert(quest.uuid, new_uuid);
+            QuestionOption::copy_all(quest.id, new_id, copies, conn)?;
+        }
+        Ok(())
+    }
+
+    /// Bumps the lock version of a question before it is updated, failing if
+    /// it was updated since the given version was loaded.
+    fn lock(
+        uuid: Uuid,
+        owner: QuestionOwner,
+        lock_version: i32,
+        conn: &PgConnection,
+    ) -> SResult<()> {
+        let locked: Option<i32> = diesel::update(
             test_questions::table.filter(
-                test_questions::uuid
-                    .eq_any(&vec)
-                    .and(test_questions::test_paper_id.eq(test_paper_id)),
+                owner
+                    .filter()
+                    .and(test_questions::uuid.eq(uuid))
+                    .and(test_questions::lock_version.eq(lock_version)),
             ),
+        ).set(test_questions::lock_version.eq(test_questions::lock_version + 1))
+        .returning(test_questions::id)
+        .get_result(conn)
+        .optional()?;
+        if locked.is_none() {
+            TestQuestion::find_by_uuid_for_owner(uuid, owner, conn)?;
+            Err(Error::StaleUpdate)?;
+        }
+        Ok(())
+    }
+
+    fn delete_multiple(vec: Vec<Uuid>, owner: QuestionOwner, conn: &PgConnection) -> SResult<()> {
+        let delete_count = diesel::delete(
+            test_questions::table.filter(owner.filter().and(test_questions::uuid.eq_any(&vec))),
         ).execute(conn)?;
 
         if delete_count != vec.len() {
@@ -76,10 +394,42 @@ graphql_object!(TestQuestion: Context |&self| {
         &self.question
     }
 
+    field topic() -> &Option<String> 
+        as "Topic of a question."
+    {
+        &self.topic
+    }
+
+    field tags() -> &Vec<String> 
+        as "Tags of a question."
+    {
+        &self.tags
+    }
+
+    field difficulty() -> &Option<Difficulty> 
+        as "Difficulty level of a question."
+    {
+        &self.difficulty
+    }
+
+    field explanation(&executor) -> SResult<&Option<String>> 
+        as "Explanation of the answer. Candidates see it through a reviewable attempt."
+    {
+        executor.context().admin_only()?;
+        Ok(&self.explanation)
+    }
+
+    field lock_version() -> i32 
+        as "Counter of the updates of a question, which an update must send back."
+    {
+        self.lock_version
+    }
+
     field options(&executor) -> SResult<Vec<QuestionOption>> 
         as "Options of a question."
     {
-        QuestionOption::find_all(self.id, &executor.context().conn)
+        let ctx = executor.context();
+        ctx.loaders.question_options(self.id, &ctx.conn)
     }
 
     field option(&executor, id: Uuid) -> SResult<QuestionOption> 
@@ -89,11 +439,63 @@ graphql_object!(TestQuestion: Context |&self| {
     }
 });
 
+/// Difficulty level of a question.
+#[derive(
+    Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression, GraphQLEnum, Serialize, Deserialize,
+)]
+#[sql_type = "Difficulty_type"]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl FromSql<Difficulty_type, Pg> for Difficulty {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let bytes = not_none!(bytes);
+        match bytes {
+            b"Easy" => Ok(Difficulty::Easy),
+            b"Medium" => Ok(Difficulty::Medium),
+            b"Hard" => Ok(Difficulty::Hard),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl ToSql<Difficulty_type, Pg> for Difficulty {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match self {
+            Difficulty::Easy => out.write_all(b"Easy")?,
+            Difficulty::Medium => out.write_all(b"Medium")?,
+            Difficulty::Hard => out.write_all(b"Hard")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+/// A type to filter questions by their metadata.
+#[derive(GraphQLInputObject)]
+pub struct QuestionFilter {
+    /// Topic of a question.
+    topic: Option<String>,
+    /// Matches questions with any of these tags.
+    tags: Option<Vec<String>>,
+    /// Difficulty level of a question.
+    difficulty: Option<Difficulty>,
+}
+
 #[derive(Insertable)]
 #[table_name = "test_questions"]
 struct NewTestQuestion {
     question: String,
-    test_paper_id: i32,
+    test_paper_id: Option<i32>,
+    position: i32,
+    question_bank_id: Option<i32>,
+    topic: Option<String>,
+    tags: Vec<String>,
+    difficulty: Option<Difficulty>,
+    explanation: Option<String>,
+    lock_version: i32,
 }
 
 impl NewTestQuestion {
@@ -109,27 +511,40 @@ impl NewTestQuestion {
 #[table_name = "test_questions"]
 struct TestQuestionPatch {
     question: Option<String>,
+    topic: Option<Option<String>>,
+    tags: Option<Vec<String>>,
+    difficulty: Option<Option<Difficulty>>,
+    explanation: Option<Option<String>>,
 }
 
 impl TestQuestionPatch {
-    fn save(self, uuid: Uuid, test_paper_id: i32, conn: &PgConnection) -> SResult<i32> {
+    fn save(self, uuid: Uuid, owner: QuestionOwner, conn: &PgConnection) -> SResult<i32> {
         let id = diesel::update(
-            test_questions::table.filter(
-                test_questions::uuid
-                    .eq(uuid)
-                    .and(test_questions::test_paper_id.eq(test_paper_id)),
-            ),
+            test_questions::table.filter(owner.filter().and(test_questions::uuid.eq(uuid))),
         ).set(self)
         .returning(test_questions::id)
         .get_result(conn)?;
         Ok(id)
     }
 
-    fn save_or_find(self, uuid: Uuid, test_paper_id: i32, conn: &PgConnection) -> SResult<i32> {
-        if self.question.is_some() {
-            self.save(uuid, test_paper_id, conn)
+    fn is_none(&self) -> bool {
+        match self {
+            TestQuestionPatch {
+                question: None,
+                topic: None,
+                tags: None,
+                difficulty: None,
+                explanation: None,
+            } => true,
+            _ => false,
+        }
+    }
+
+    fn save_or_find(self, uuid: Uuid, owner: QuestionOwner, conn: &PgConnection) -> SResult<i32> {
+        if self.is_none() {
+            Ok(TestQuestion::find_by_uuid_for_owner(uuid, owner, conn)?.id)
         } else {
-            Ok(TestQuestion::find_by_uuid_for_test_paper(uuid, test_paper_id, conn)?.id)
+            self.save(uuid, owner, conn)
         }
     }
 }
@@ -138,21 +553,45 @@ impl TestQuestionPatch {
 #[derive(GraphQLInputObject)]
 pub struct TestQuestionForm {
     /// Question text.
-    question: String,
+    pub question: String,
+    /// Topic of this question.
+    pub topic: Option<String>,
+    /// Tags of this question.
+    pub tags: Option<Vec<String>>,
+    /// Difficulty level of this question.
+    pub difficulty: Option<Difficulty>,
+    /// Explanation of the answer shown after the results are out.
+    pub explanation: Option<String>,
     /// List of options for this question.
-    options: Vec<QuestionOptionForm>,
+    pub options: Vec<QuestionOptionForm>,
+}
+
+impl Validate for TestQuestionForm {
+    fn validate(&self, validator: &mut Validator) {
+        validator.not_blank("question", &self.question);
+        validator.not_empty("options", &self.options);
+        validator.each("options", &self.options);
+    }
 }
 
 impl TestQuestionForm {
     pub fn save_multiple(
         vec: Vec<TestQuestionForm>,
-        test_paper_id: i32,
+        owner: QuestionOwner,
         conn: &PgConnection,
     ) -> SResult<()> {
-        for quest in vec {
+        let first_position = TestQuestion::next_position(owner, conn)?;
+        for (position, quest) in (first_position..).zip(vec) {
             let new_quest = NewTestQuestion {
                 question: quest.question,
-                test_paper_id,
+                test_paper_id: owner.test_paper_id(),
+                position,
+                question_bank_id: owner.question_bank_id(),
+                topic: quest.topic,
+                tags: quest.tags.unwrap_or_default(),
+                difficulty: quest.difficulty,
+                explanation: quest.explanation,
+                lock_version: 1,
             };
             let new_id = new_quest.save(conn)?;
             QuestionOptionForm::save_multiple(quest.options, new_id, conn)?;
@@ -163,26 +602,50 @@ impl TestQuestionForm {
 
 /// A type to update a test question.
 #[derive(GraphQLInputObject)]
-struct TestQuestionUpdate {
+pub struct TestQuestionUpdate {
     /// Id of a test question.
-    id: Uuid,
+    pub id: Uuid,
+    /// Lock version of the question being updated.
+    pub lock_version: i32,
     /// New question text.
-    question: Option<String>,
+    pub question: Option<String>,
+    /// New topic of a question.
+    pub topic: Option<Option<String>>,
+    /// New tags of a question.
+    pub tags: Option<Vec<String>>,
+    /// New difficulty level of a question.
+    pub difficulty: Option<Option<Difficulty>>,
+    /// New explanation of the answer.
+    pub explanation: Option<Option<String>>,
     /// Update type for options.
-    options: QuestionOptionsUpdate,
+    pub options: QuestionOptionsUpdate,
+}
+
+impl Validate for TestQuestionUpdate {
+    fn validate(&self, validator: &mut Validator) {
+        if let Some(ref question) = self.question {
+            validator.not_blank("question", question);
+        }
+        validator.nested("options", &self.options);
+    }
 }
 
 impl TestQuestionUpdate {
     fn save_multiple(
         vec: Vec<TestQuestionUpdate>,
-        test_paper_id: i32,
+        owner: QuestionOwner,
         conn: &PgConnection,
     ) -> SResult<()> {
         for quest in vec {
+            TestQuestion::lock(quest.id, owner, quest.lock_version, conn)?;
             let quest_patch = TestQuestionPatch {
                 question: quest.question,
+                topic: quest.topic,
+                tags: quest.tags,
+                difficulty: quest.difficulty,
+                explanation: quest.explanation,
             };
-            let question_id = quest_patch.save_or_find(quest.id, test_paper_id, conn)?;
+            let question_id = quest_patch.save_or_find(quest.id, owner, conn)?;
             quest.options.save(question_id, conn)?;
         }
         Ok(())
@@ -193,18 +656,45 @@ impl TestQuestionUpdate {
 #[derive(GraphQLInputObject)]
 pub struct TestQuestionsUpdate {
     /// List of new questions.
-    new: Vec<TestQuestionForm>,
+    pub new: Vec<TestQuestionForm>,
     /// List of updated questions.
-    update: Vec<TestQuestionUpdate>,
+    pub update: Vec<TestQuestionUpdate>,
     /// List of ids to delete older questions.
-    remove: Vec<Uuid>,
+    pub remove: Vec<Uuid>,
+}
+
+impl Validate for TestQuestionsUpdate {
+    fn validate(&self, validator: &mut Validator) {
+        validator.each("new", &self.new);
+        validator.each("update", &self.update);
+    }
 }
 
 impl TestQuestionsUpdate {
-    pub fn save(self, test_paper_id: i32, conn: &PgConnection) -> SResult<()> {
-        TestQuestionForm::save_multiple(self.new, test_paper_id, conn)?;
-        TestQuestionUpdate::save_multiple(self.update, test_paper_id, conn)?;
-        TestQuestion::delete_multiple(self.remove, test_paper_id, conn)?;
+    /// Ids of the existing questions which are updated or removed.
+    pub fn changed_ids(&self) -> Vec<Uuid> {
+        self.update
+            .iter()
+            .map(|quest| quest.id)
+            .chain(self.remove.iter().cloned())
+            .collect()
+    }
+
+    /// Points the updated and removed questions and options to their copies.
+    pub fn remap(&mut self, copies: &CopiedIds) {
+        for quest in &mut self.update {
+            remap(&mut quest.id, copies);
+            quest.options.remap(copies);
+        }
+        for id in &mut self.remove {
+            remap(id, copies);
+        }
+    }
+
+    pub fn save(self, owner: QuestionOwner, conn: &PgConnection) -> SResult<()> {
+        TestQuestionForm::save_multiple(self.new, owner, conn)?;
+        TestQuestionUpdate::save_multiple(self.update, owner, conn)?;
+        TestQuestion::delete_multiple(self.remove, owner, conn)?;
         Ok(())
     }
 }
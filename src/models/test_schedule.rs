@@ -1,9 +1,10 @@
 use chrono::{Duration, NaiveDateTime, Utc};
 use diesel::{self, prelude::*};
-use errors::SResult;
+use errors::{Error, SResult};
 use models::test_paper::TestPaper;
 use schema::test_schedules;
 use uuid::Uuid;
+use validation::{validate, Validate, Validator};
 
 #[derive(Identifiable, Queryable)]
 pub struct TestSchedule {
@@ -12,6 +13,7 @@ pub struct TestSchedule {
     pub test_paper_id: i32,
     pub time: NaiveDateTime,
     pub duration: i32,
+    pub lock_version: i32,
 }
 
 impl TestSchedule {
@@ -38,6 +40,43 @@ impl TestSchedule {
                 .get_result(conn)?,
         )
     }
+
+    /// Moves all the schedules of a test paper to another test paper.
+    pub fn move_all(from_paper_id: i32, to_paper_id: i32, conn: &PgConnection) -> SResult<()> {
+        diesel::update(
+            test_schedules::table.filter(test_schedules::test_paper_id.eq(from_paper_id)),
+        ).set(test_schedules::test_paper_id.eq(to_paper_id))
+        .execute(conn)?;
+        Ok(())
+    }
+
+    /// Copies all the schedules of a test paper to another test paper.
+    pub fn copy_all(from_paper_id: i32, to_paper_id: i32, conn: &PgConnection) -> SResult<()> {
+        let new_schedules: Vec<_> = TestSchedule::find_all_for_test_paper(from_paper_id, conn)?
+            .into_iter()
+            .map(|schedule| NewTestSchedule {
+                test_paper_id: to_paper_id,
+                time: schedule.time,
+                duration: schedule.duration,
+            }).collect();
+        diesel::insert_into(test_schedules::table)
+            .values(new_schedules)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn end_time(&self) -> NaiveDateTime {
+        self.time + Duration::seconds(self.duration as i64)
+    }
+
+    pub fn has_ended(&self) -> bool {
+        self.end_time() <= Utc::now().naive_utc()
+    }
+
+    pub fn is_happening(&self) -> bool {
+        let now = Utc::now().naive_utc();
+        self.time < now && self.end_time() > now
+    }
 }
 
 graphql_object!(TestSchedule: () |&self| {
@@ -61,11 +100,16 @@ graphql_object!(TestSchedule: () |&self| {
         self.duration
     }
 
+    field lock_version() -> i32 
+        as "Counter of the updates of a test schedule, which an update must send back."
+    {
+        self.lock_version
+    }
+
     field is_happening() -> bool 
         as "Specifies whether a test is currently happening."
     {
-        let now = Utc::now().naive_utc();
-        self.time < now && self.time + Duration::seconds(self.duration as i64) > now
+        self.is_happening()
     }
 });
 
@@ -93,12 +137,27 @@ struct TestSchedulePatch {
 }
 
 impl TestSchedulePatch {
-    fn save(self, uuid: Uuid, conn: &PgConnection) -> SResult<TestSchedule> {
-        Ok(
-            diesel::update(test_schedules::table.filter(test_schedules::uuid.eq(uuid)))
-                .set(self)
-                .get_result(conn)?,
-        )
+    /// Updates a test schedule and bumps its lock version, failing if it was
+    /// updated since the given version was loaded.
+    fn save(self, uuid: Uuid, lock_version: i32, conn: &PgConnection) -> SResult<TestSchedule> {
+        let saved = diesel::update(
+            test_schedules::table.filter(
+                test_schedules::uuid
+                    .eq(uuid)
+                    .and(test_schedules::lock_version.eq(lock_version)),
+            ),
+        ).set((
+            self,
+            test_schedules::lock_version.eq(test_schedules::lock_version + 1),
+        )).get_result(conn)
+        .optional()?;
+        match saved {
+            Some(schedule) => Ok(schedule),
+            None => {
+                TestSchedule::find_by_uuid(uuid, conn)?;
+                Err(Error::StaleUpdate)
+            }
+        }
     }
 }
 
@@ -113,9 +172,19 @@ pub struct TestScheduleForm {
     duration: i32,
 }
 
+impl Validate for TestScheduleForm {
+    fn validate(&self, validator: &mut Validator) {
+        validator.positive("duration", self.duration);
+    }
+}
+
 impl TestScheduleForm {
     pub fn save(self, conn: &PgConnection) -> SResult<TestSchedule> {
+        validate(&self)?;
         let test_paper = TestPaper::find_by_uuid(self.test_paper_id, conn)?;
+        if !test_paper.is_published() {
+            Err(Error::NotPublished)?;
+        }
         let new_schedule = NewTestSchedule {
             test_paper_id: test_paper.id,
             time: self.time,
@@ -130,18 +199,29 @@ impl TestScheduleForm {
 pub struct TestScheduleUpdate {
     /// Id of a test schedule.
     id: Uuid,
+    /// Lock version of the test schedule being updated.
+    lock_version: i32,
     /// New time at which the test will start.
     time: Option<NaiveDateTime>,
     /// New duration of the test.
     duration: Option<i32>,
 }
 
+impl Validate for TestScheduleUpdate {
+    fn validate(&self, validator: &mut Validator) {
+        if let Some(duration) = self.duration {
+            validator.positive("duration", duration);
+        }
+    }
+}
+
 impl TestScheduleUpdate {
     pub fn save(self, conn: &PgConnection) -> SResult<TestSchedule> {
+        validate(&self)?;
         let schedule_patch = TestSchedulePatch {
             time: self.time,
             duration: self.duration,
         };
-        schedule_patch.save(self.id, conn)
+        schedule_patch.save(self.id, self.lock_version, conn)
     }
 }
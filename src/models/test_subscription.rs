@@ -1,4 +1,5 @@
-use diesel::prelude::*;
+use connection::{Cursor, Seek};
+use diesel::{self, dsl, prelude::*};
 use errors::SResult;
 use models::{test_paper::TestPaper, test_schedule::TestSchedule};
 use schema::test_subscriptions;
@@ -15,10 +16,51 @@ pub struct TestSubscription {
 }
 
 impl TestSubscription {
-    pub fn find_all_for_user(user_id: i32, conn: &PgConnection) -> SResult<Vec<TestSubscription>> {
+    pub fn count_for_user(user_id: i32, conn: &PgConnection) -> SResult<i64> {
         Ok(test_subscriptions::table
             .filter(test_subscriptions::user_id.eq(user_id))
-            .load(conn)?)
+            .count()
+            .get_result(conn)?)
+    }
+
+    /// A page of the subscriptions of a user in the order they were made.
+    pub fn find_page_for_user(
+        user_id: i32,
+        seek: &Seek,
+        conn: &PgConnection,
+    ) -> SResult<Vec<TestSubscription>> {
+        let subscriptions = test_subscriptions::table
+            .filter(test_subscriptions::user_id.eq(user_id))
+            .into_boxed();
+        Ok(seek!(subscriptions, seek, false, test_subscriptions::id).load(conn)?)
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        Cursor::new(None, self.id)
+    }
+
+    /// Whether a user is subscribed to a test schedule.
+    pub fn exists_for_schedule(
+        user_id: i32,
+        test_schedule_id: i32,
+        conn: &PgConnection,
+    ) -> SResult<bool> {
+        Ok(diesel::select(dsl::exists(
+            test_subscriptions::table.filter(
+                test_subscriptions::user_id
+                    .eq(user_id)
+                    .and(test_subscriptions::test_schedule_id.eq(test_schedule_id)),
+            ),
+        )).get_result(conn)?)
+    }
+
+    /// Moves all the subscriptions of a test paper to another test paper.
+    pub fn move_all(from_paper_id: i32, to_paper_id: i32, conn: &PgConnection) -> SResult<()> {
+        diesel::update(
+            test_subscriptions::table.filter(test_subscriptions::test_paper_id.eq(from_paper_id)),
+        ).set(test_subscriptions::test_paper_id.eq(to_paper_id))
+        .execute(conn)?;
+        Ok(())
     }
 }
 
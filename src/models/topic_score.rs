@@ -0,0 +1,83 @@
+use diesel::prelude::*;
+use errors::SResult;
+use models::{question_answer::QuestionAnswer, test_attempt::TestAttempt};
+use std::collections::HashSet;
+
+/// Score of a test attempt for the questions of a single topic.
+pub struct TopicScore {
+    pub topic: Option<String>,
+    pub total: i32,
+    pub answered: i32,
+    pub correct: i32,
+}
+
+impl TopicScore {
+    /// Breaks down the score of an attempt by the topics of its questions.
+    pub fn find_all_for_attempt(
+        attempt: &TestAttempt,
+        conn: &PgConnection,
+    ) -> SResult<Vec<TopicScore>> {
+        let answered: HashSet<i32> = QuestionAnswer::find_all(attempt.id, conn)?
+            .into_iter()
+            .map(|answer| answer.test_question_id)
+            .collect();
+        let correct: HashSet<i32> = QuestionAnswer::find_correct_question_ids(attempt.id, conn)?
+            .into_iter()
+            .collect();
+
+        let mut scores: Vec<TopicScore> = Vec::new();
+        for attempt_question in attempt.questions(false, conn)? {
+            let question = attempt_question.question;
+            let index = match scores.iter().position(|score| score.topic == question.topic) {
+                Some(index) => index,
+                None => {
+                    scores.push(TopicScore {
+                        topic: question.topic.clone(),
+                        total: 0,
+                        answered: 0,
+                        correct: 0,
+                    });
+                    scores.len() - 1
+                }
+            };
+
+            let score = &mut scores[index];
+            score.total += 1;
+            if answered.contains(&question.id) {
+                score.answered += 1;
+            }
+            if correct.contains(&question.id) {
+                score.correct += 1;
+            }
+        }
+        Ok(scores)
+    }
+}
+
+graphql_object!(TopicScore: () | &self | {
+    description: "A type representing the score of an attempt for a single topic."
+
+    field topic() -> &Option<String>
+        as "Topic of the questions. Null for questions without a topic."
+    {
+        &self.topic
+    }
+
+    field total() -> i32
+        as "Number of questions of the topic in the attempt."
+    {
+        self.total
+    }
+
+    field answered() -> i32
+        as "Number of questions of the topic that were answered."
+    {
+        self.answered
+    }
+
+    field correct() -> i32
+        as "Number of questions of the topic that were answered correctly."
+    {
+        self.correct
+    }
+});
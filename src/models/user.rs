@@ -1,6 +1,8 @@
-use super::JoinPatch;
+use super::{escape_like, is_descending, JoinPatch, SortDirection};
 use basic::BasicUser;
 use bcrypt;
+use chrono::NaiveDateTime;
+use connection::{Connection, Cursor, Page, Seek, SortKey};
 use db_types::*;
 use diesel::{
     self,
@@ -9,14 +11,19 @@ use diesel::{
     pg::Pg,
     prelude::*,
     serialize::{self, IsNull, Output, ToSql},
+    sql_types::{Nullable, Text},
 };
 use errors::{Error, SResult};
 use models::{test_attempt::TestAttempt, test_subscription::TestSubscription};
+use password_policy;
 use schema::users;
 use std::io::Write;
 use uuid::Uuid;
+use validation::{validate, Validate, Validator};
 use {Context, AUTH_CACHE};
 
+sql_function!(fn coalesce(x: Nullable<Text>, y: Text) -> Text);
+
 #[derive(Identifiable, Queryable)]
 pub struct User {
     pub id: i32,
@@ -28,6 +35,7 @@ pub struct User {
     pub gender: Option<Gender>,
     pub contact: Option<String>,
     pub type_: UserType,
+    pub created_at: NaiveDateTime,
 }
 
 impl User {
@@ -35,6 +43,10 @@ impl User {
         Ok(users::table.find(id).get_result(conn)?)
     }
 
+    pub fn find_many(ids: &[i32], conn: &PgConnection) -> SResult<Vec<User>> {
+        Ok(users::table.filter(users::id.eq_any(ids)).load(conn)?)
+    }
+
     pub fn find_by_uuid(uuid: Uuid, conn: &PgConnection) -> SResult<User> {
         let user = users::table.filter(users::uuid.eq(uuid)).get_result(conn)?;
         Ok(user)
@@ -58,24 +70,109 @@ impl User {
             .get_result(conn)?)
     }
 
-    pub fn find_all(query: Option<String>, conn: &PgConnection) -> SResult<Vec<User>> {
-        let users = if let Some(query) = query {
+    /// Users matching the query and the filter, or all of them without
+    /// either.
+    fn search(
+        query: Option<String>,
+        filter: Option<&UserFilter>,
+    ) -> users::BoxedQuery<'static, Pg> {
+        let mut users = users::table.into_boxed();
+        if let Some(query) = query {
             let like_str = format!("%{}%", query);
 
             // Currently `ilike` comparisons are not supported for nullable
             // types in diesel.
-            users::table
-                .filter(
-                    users::first_name
-                        .like(&like_str)
-                        .or(users::last_name.like(&like_str))
-                        .or(users::email.ilike(&like_str))
-                        .or(users::contact.like(&like_str)),
-                ).load(conn)?
-        } else {
-            users::table.load(conn)?
+            users = users.filter(
+                users::first_name
+                    .like(like_str.clone())
+                    .or(users::last_name.like(like_str.clone()))
+                    .or(users::email.ilike(like_str.clone()))
+                    .or(users::contact.like(like_str)),
+            );
+        }
+        if let Some(filter) = filter {
+            if let Some(type_) = filter.type_ {
+                users = users.filter(users::type_.eq(type_));
+            }
+            if let Some(gender) = filter.gender {
+                users = users.filter(users::gender.eq(gender));
+            }
+            if let Some(ref domain) = filter.email_domain {
+                let domain = domain.trim_left_matches('@');
+                let like_str = format!("%@{}", escape_like(domain));
+                users = users.filter(users::email.ilike(like_str));
+            }
+            if let Some(created_after) = filter.created_after {
+                users = users.filter(users::created_at.ge(created_after));
+            }
+            if let Some(created_before) = filter.created_before {
+                users = users.filter(users::created_at.lt(created_before));
+            }
+        }
+        users
+    }
+
+    pub fn count_all(
+        query: Option<String>,
+        filter: Option<&UserFilter>,
+        conn: &PgConnection,
+    ) -> SResult<i64> {
+        Ok(User::search(query, filter).count().get_result(conn)?)
+    }
+
+    /// A page of the users matching the query and the filter, sorted by
+    /// their ids unless a sort is given. The id is always the last key so
+    /// that the cursors are unique. Users without a first or last name are
+    /// sorted as if it were empty.
+    pub fn find_page(
+        query: Option<String>,
+        filter: Option<&UserFilter>,
+        sort: Option<&UserSort>,
+        seek: &Seek,
+        conn: &PgConnection,
+    ) -> SResult<Vec<User>> {
+        let users = User::search(query, filter);
+        let sort = match sort {
+            Some(sort) => sort,
+            None => return Ok(seek!(users, seek, false, users::id).load(conn)?),
+        };
+        let descending = is_descending(sort.direction);
+        let users = match sort.field {
+            UserSortField::CreatedAt => {
+                seek!(users, seek, descending, users::id, users::created_at, key_time)
+            }
+            UserSortField::Email => {
+                seek!(users, seek, descending, users::id, users::email, key_text)
+            }
+            UserSortField::FirstName => seek!(
+                users,
+                seek,
+                descending,
+                users::id,
+                coalesce(users::first_name, ""),
+                key_text
+            ),
+            UserSortField::LastName => seek!(
+                users,
+                seek,
+                descending,
+                users::id,
+                coalesce(users::last_name, ""),
+                key_text
+            ),
         };
-        Ok(users)
+        Ok(users.load(conn)?)
+    }
+
+    /// Position of a user in a list with the sort.
+    pub fn cursor(&self, sort: Option<&UserSort>) -> Cursor {
+        let key = sort.map(|sort| match sort.field {
+            UserSortField::CreatedAt => SortKey::Time(self.created_at),
+            UserSortField::Email => SortKey::Text(self.email.clone()),
+            UserSortField::FirstName => SortKey::Text(self.first_name.clone().unwrap_or_default()),
+            UserSortField::LastName => SortKey::Text(self.last_name.clone().unwrap_or_default()),
+        });
+        Cursor::new(key, self.id)
     }
 
     pub fn delete_by_uuid(uuid: Uuid, conn: &PgConnection) -> SResult<User> {
@@ -83,6 +180,39 @@ impl User {
         Ok(user)
     }
 
+    /// Creates a new admin.
+    pub fn create_admin(email: String, password: &str, conn: &PgConnection) -> SResult<User> {
+        let new_user = NewUser {
+            email,
+            password: bcrypt::hash(password, bcrypt::DEFAULT_COST)?,
+            type_: UserType::Admin,
+            ..NewUser::default()
+        };
+        new_user.save(conn)
+    }
+
+    pub fn promote(&self, conn: &PgConnection) -> SResult<User> {
+        let user_patch = UserPatch {
+            type_: Some(UserType::Admin),
+            ..UserPatch::default()
+        };
+        user_patch.save(self.uuid, conn)
+    }
+
+    /// Sets a new password without verifying the current one.
+    pub fn reset_password(&self, password: &str, conn: &PgConnection) -> SResult<User> {
+        let user_patch = UserPatch {
+            password: Some(bcrypt::hash(password, bcrypt::DEFAULT_COST)?),
+            ..UserPatch::default()
+        };
+        user_patch.save(self.uuid, conn)
+    }
+
+    /// Hash of the password, which only a backup needs.
+    pub fn password_hash(&self) -> &str {
+        &self.password
+    }
+
     pub fn is_admin(&self) -> bool {
         match self.type_ {
             UserType::Admin => true,
@@ -147,25 +277,90 @@ graphql_object!(User: Context |&self| {
         &self.type_
     }
 
-    field test_subscriptions(&executor) -> SResult<Vec<TestSubscription>> 
+    field created_at() -> &NaiveDateTime
+        as "Time when a user was created."
+    {
+        &self.created_at
+    }
+
+    field test_subscriptions(
+        &executor,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>
+    ) -> SResult<Connection<TestSubscription>> 
         as "Tests a user has subscribed to." 
     {
         let ctx = executor.context();
         ctx.me_only(self.id)?;
-        TestSubscription::find_all_for_user(self.id, &ctx.conn)
+        let page = Page::new(first, after, last, before)?;
+        let total_count = TestSubscription::count_for_user(self.id, &ctx.conn)?;
+        Connection::load(
+            &page,
+            total_count,
+            |seek| TestSubscription::find_page_for_user(self.id, seek, &ctx.conn),
+            TestSubscription::cursor,
+        )
     }
 
-    field test_rooms(&executor) -> SResult<Vec<TestAttempt>> 
+    field test_rooms(
+        &executor,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>
+    ) -> SResult<Connection<TestAttempt>> 
         as "Tests a user has taken." 
     {
         let ctx = executor.context();
         ctx.me_only(self.id)?;
-        TestAttempt::find_for_user(self.id, &ctx.conn)
+        let page = Page::new(first, after, last, before)?;
+        let total_count = TestAttempt::count_for_user(self.id, &ctx.conn)?;
+        Connection::load(
+            &page,
+            total_count,
+            |seek| TestAttempt::find_page_for_user(self.id, seek, &ctx.conn),
+            TestAttempt::cursor,
+        )
     }
 });
 
+/// Filter for a list of users.
+#[derive(GraphQLInputObject)]
+pub struct UserFilter {
+    /// Type of a user.
+    type_: Option<UserType>,
+    /// Gender of a user.
+    gender: Option<Gender>,
+    /// Domain of the email of a user, such as `example.com`.
+    email_domain: Option<String>,
+    /// Matches users created at or after this time.
+    created_after: Option<NaiveDateTime>,
+    /// Matches users created before this time.
+    created_before: Option<NaiveDateTime>,
+}
+
+/// Field by which a list of users is sorted.
+#[derive(Clone, Copy, GraphQLEnum)]
+pub enum UserSortField {
+    CreatedAt,
+    Email,
+    FirstName,
+    LastName,
+}
+
+/// Order of a list of users.
+#[derive(GraphQLInputObject)]
+pub struct UserSort {
+    /// Field by which the users are sorted.
+    field: UserSortField,
+    /// Direction of the sort, ascending unless given.
+    direction: Option<SortDirection>,
+}
+
 /// Gender of a user.
-#[derive(Debug, FromSqlRow, AsExpression, GraphQLEnum)]
+#[derive(Debug, Clone, Copy, FromSqlRow, AsExpression, GraphQLEnum, Serialize, Deserialize)]
 #[sql_type = "Gender_type"]
 pub enum Gender {
     Male,
@@ -197,7 +392,9 @@ impl ToSql<Gender_type, Pg> for Gender {
 }
 
 /// Type of a user.
-#[derive(Debug, FromSqlRow, AsExpression, QueryId, GraphQLEnum)]
+#[derive(
+    Debug, Clone, Copy, FromSqlRow, AsExpression, QueryId, GraphQLEnum, Serialize, Deserialize,
+)]
 #[sql_type = "User_type"]
 pub enum UserType {
     Admin,
@@ -282,8 +479,17 @@ pub struct UserForm {
     password: String,
 }
 
+impl Validate for UserForm {
+    fn validate(&self, validator: &mut Validator) {
+        validator.email("email", &self.email);
+        validator.not_blank("password", &self.password);
+    }
+}
+
 impl UserForm {
     pub fn save(self, conn: &PgConnection) -> SResult<User> {
+        validate(&self)?;
+        password_policy::check(&self.password, &self.email)?;
         let has_users = User::exists_any(conn)?;
         let password = bcrypt::hash(&self.password, bcrypt::DEFAULT_COST)?;
         let new_user = NewUser {
@@ -345,6 +551,17 @@ pub struct UserCredentialsUpdate {
     password: String,
 }
 
+impl Validate for UserCredentialsUpdate {
+    fn validate(&self, validator: &mut Validator) {
+        if let Some(ref email) = self.email {
+            validator.email("email", email);
+        }
+        if let Some(ref new_password) = self.new_password {
+            validator.not_blank("newPassword", new_password);
+        }
+    }
+}
+
 impl UserCredentialsUpdate {
     fn hashed_password(&self) -> SResult<Option<String>> {
         if let Some(ref new_password) = self.new_password {
@@ -356,9 +573,14 @@ impl UserCredentialsUpdate {
     }
 
     pub fn save(self, id: Uuid, conn: &PgConnection) -> SResult<User> {
+        validate(&self)?;
         // Verify the password regardless of the update.
         let user = User::find_by_uuid(id, conn)?;
-        verify_user(user, &self.password)?;
+        let user = verify_user(user, &self.password)?;
+        if let Some(ref new_password) = self.new_password {
+            let email = self.email.as_ref().unwrap_or(&user.email);
+            password_policy::check(new_password, email)?;
+        }
 
         let password_hash = self.hashed_password()?;
         let user_patch = UserPatch {
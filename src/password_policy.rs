@@ -0,0 +1,217 @@
+//! Rules which new passwords must follow, set from the configuration when
+//! the server or the admin tool starts. Besides the length and the kinds of
+//! characters, passwords are looked up in an optional local list of the
+//! SHA-1 hashes of breached passwords, in the formats of Pwned Passwords:
+//! either one file of `HASH:COUNT` lines, or a directory of range files
+//! named by the first five characters of the hashes in uppercase, with
+//! `SUFFIX:COUNT` lines as the k-anonymity API returns them. A single file
+//! is read into memory when the policy is set, so it suits short lists
+//! only. In a directory, only the range file of a password is read when it
+//! is checked, which is how the whole set of Pwned Passwords can be used.
+
+use config::{ConfigError, PasswordConfig};
+use errors::{Error, SResult};
+use sha1::{Digest, Sha1};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+type Hash = [u8; 20];
+
+lazy_static! {
+    static ref POLICY: RwLock<PasswordPolicy> = RwLock::new(PasswordPolicy::default());
+}
+
+struct PasswordPolicy {
+    min_length: usize,
+    require_lowercase: bool,
+    require_uppercase: bool,
+    require_digit: bool,
+    require_symbol: bool,
+    breached: BreachedList,
+}
+
+/// Where the hashes of the breached passwords are looked up.
+enum BreachedList {
+    None,
+    /// Sorted hashes read from a single file.
+    Hashes(Vec<Hash>),
+    /// Directory of the range files.
+    Ranges(PathBuf),
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> PasswordPolicy {
+        PasswordPolicy::new(&PasswordConfig::default(), BreachedList::None)
+    }
+}
+
+impl PasswordPolicy {
+    fn new(config: &PasswordConfig, breached: BreachedList) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: config.min_length,
+            require_lowercase: config.require_lowercase,
+            require_uppercase: config.require_uppercase,
+            require_digit: config.require_digit,
+            require_symbol: config.require_symbol,
+            breached,
+        }
+    }
+}
+
+/// Why a password is not allowed.
+#[derive(Debug, Clone, Copy)]
+pub enum Weakness {
+    TooShort(usize),
+    NoLowercase,
+    NoUppercase,
+    NoDigit,
+    NoSymbol,
+    SameAsEmail,
+    Breached,
+}
+
+impl Weakness {
+    pub fn code(self) -> &'static str {
+        match self {
+            Weakness::TooShort(_) => "TOO_SHORT",
+            Weakness::NoLowercase => "NO_LOWERCASE",
+            Weakness::NoUppercase => "NO_UPPERCASE",
+            Weakness::NoDigit => "NO_DIGIT",
+            Weakness::NoSymbol => "NO_SYMBOL",
+            Weakness::SameAsEmail => "SAME_AS_EMAIL",
+            Weakness::Breached => "BREACHED",
+        }
+    }
+
+    pub fn message(self) -> String {
+        match self {
+            Weakness::TooShort(min_length) => {
+                format!("It must have at least {} characters.", min_length)
+            }
+            Weakness::NoLowercase => "It must have a lowercase letter.".into(),
+            Weakness::NoUppercase => "It must have an uppercase letter.".into(),
+            Weakness::NoDigit => "It must have a digit.".into(),
+            Weakness::NoSymbol => "It must have a symbol.".into(),
+            Weakness::SameAsEmail => "It must not be the email.".into(),
+            Weakness::Breached => "It has appeared in a data breach.".into(),
+        }
+    }
+}
+
+/// Sets the policy of the configuration, reading the breached passwords
+/// unless they are in a directory of range files.
+pub fn configure(config: &PasswordConfig) -> Result<(), ConfigError> {
+    let breached = match config.breached_list {
+        Some(ref path) if path.is_dir() => BreachedList::Ranges(path.clone()),
+        Some(ref path) => {
+            let read_error = |err: io::Error| ConfigError::Read(path.clone(), err.to_string());
+            let file = File::open(path).map_err(read_error)?;
+            let mut hashes = Vec::new();
+            for (index, line) in BufReader::new(file).lines().enumerate() {
+                let line = line.map_err(read_error)?;
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let hash = parse_hash(line.split(':').next().unwrap_or_default())
+                    .ok_or_else(|| {
+                        let message = format!("Line {} is not a SHA-1 hash: {:?}", index + 1, line);
+                        ConfigError::Parse(path.clone(), message)
+                    })?;
+                hashes.push(hash);
+            }
+            hashes.sort_unstable();
+            hashes.dedup();
+            BreachedList::Hashes(hashes)
+        }
+        None => BreachedList::None,
+    };
+    *POLICY.write().unwrap() = PasswordPolicy::new(config, breached);
+    Ok(())
+}
+
+impl BreachedList {
+    fn contains(&self, password: &str) -> SResult<bool> {
+        let mut hash = [0; 20];
+        hash.copy_from_slice(&Sha1::digest(password.as_bytes()));
+        match *self {
+            BreachedList::None => Ok(false),
+            BreachedList::Hashes(ref hashes) => Ok(hashes.binary_search(&hash).is_ok()),
+            BreachedList::Ranges(ref dir) => {
+                let hex: String = hash.iter().map(|byte| format!("{:02X}", byte)).collect();
+                let (prefix, suffix) = hex.split_at(5);
+                range_contains(&dir.join(prefix), suffix)
+            }
+        }
+    }
+}
+
+/// Whether a range file has a line with the suffix of a hash. A missing
+/// range file has no hashes.
+fn range_contains(path: &Path, suffix: &str) -> SResult<bool> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => Err(err)?,
+    };
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line_suffix = line.trim().split(':').next().unwrap_or_default();
+        if line_suffix.eq_ignore_ascii_case(suffix) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn parse_hash(hex: &str) -> Option<Hash> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+    let mut hash = [0; 20];
+    for (index, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+/// Checks a new password of the user with the email, failing with every
+/// rule it breaks.
+pub fn check(password: &str, email: &str) -> SResult<()> {
+    let policy = POLICY.read().unwrap();
+    let mut weaknesses = Vec::new();
+    if password.chars().count() < policy.min_length {
+        weaknesses.push(Weakness::TooShort(policy.min_length));
+    }
+    if policy.require_lowercase && !password.chars().any(char::is_lowercase) {
+        weaknesses.push(Weakness::NoLowercase);
+    }
+    if policy.require_uppercase && !password.chars().any(char::is_uppercase) {
+        weaknesses.push(Weakness::NoUppercase);
+    }
+    if policy.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+        weaknesses.push(Weakness::NoDigit);
+    }
+    if policy.require_symbol && password.chars().all(char::is_alphanumeric) {
+        weaknesses.push(Weakness::NoSymbol);
+    }
+    let password_lower = password.to_lowercase();
+    let email_lower = email.trim().to_lowercase();
+    let local_part = email_lower.split('@').next().unwrap_or_default();
+    if password_lower == email_lower || password_lower == local_part {
+        weaknesses.push(Weakness::SameAsEmail);
+    }
+    if policy.breached.contains(password)? {
+        weaknesses.push(Weakness::Breached);
+    }
+
+    if weaknesses.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::WeakPassword(weaknesses))
+    }
+}
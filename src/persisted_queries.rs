@@ -0,0 +1,72 @@
+//! Queries registered at deploy time, which clients send by the SHA-256 hash
+//! of their text instead of the text itself. The manifest is a JSON object
+//! of the documents keyed by the lowercase hex encoded hashes, like
+//! `{"5f1c…": "query Me { me { email } }"}`, so `sha256sum` of a document is
+//! its key. In the strict mode only these queries are executed.
+
+use config::{ConfigError, GraphqlConfig};
+use errors::{Error, SResult};
+use serde_json;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fs};
+
+pub struct PersistedQueries {
+    queries: HashMap<String, String>,
+    is_strict: bool,
+}
+
+impl PersistedQueries {
+    /// Reads the manifest of the configuration, making sure that every
+    /// document matches its hash.
+    pub fn load(config: &GraphqlConfig) -> Result<PersistedQueries, ConfigError> {
+        let queries = match config.persisted_queries {
+            Some(ref path) => {
+                let content = fs::read_to_string(path)
+                    .map_err(|err| ConfigError::Read(path.clone(), err.to_string()))?;
+                let queries: HashMap<String, String> = serde_json::from_str(&content)
+                    .map_err(|err| ConfigError::Parse(path.clone(), err.to_string()))?;
+                for (hash, query) in &queries {
+                    if *hash != sha256(query) {
+                        let message = format!("{:?} is not the hash of its query.", hash);
+                        return Err(ConfigError::Invalid("graphql.persisted_queries", message));
+                    }
+                }
+                queries
+            }
+            None => HashMap::new(),
+        };
+        Ok(PersistedQueries {
+            queries,
+            is_strict: config.persisted_only,
+        })
+    }
+
+    /// The document to execute for a request with the query, the hash or
+    /// both. A query sent along its hash must match it, and in the strict
+    /// mode the hash must be persisted.
+    pub fn resolve(&self, query: Option<String>, hash: Option<&str>) -> SResult<String> {
+        let hash = hash.map(str::to_lowercase);
+        match (query, hash) {
+            (Some(query), hash) => {
+                let query_hash = sha256(&query);
+                if hash.map_or(false, |hash| hash != query_hash) {
+                    Err(Error::PersistedQueryMismatch)
+                } else if self.is_strict && !self.queries.contains_key(&query_hash) {
+                    Err(Error::QueryNotAllowed)
+                } else {
+                    Ok(query)
+                }
+            }
+            (None, Some(hash)) => self
+                .queries
+                .get(&hash)
+                .cloned()
+                .ok_or(Error::PersistedQueryNotFound),
+            (None, None) => Err(Error::PersistedQueryNotFound),
+        }
+    }
+}
+
+fn sha256(query: &str) -> String {
+    format!("{:x}", Sha256::digest(query.as_bytes()))
+}
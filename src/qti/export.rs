@@ -0,0 +1,165 @@
+use super::{escape, ITEM_TYPE, TEST_TYPE};
+use diesel::PgConnection;
+use errors::SResult;
+use models::{
+    question_option::QuestionOption,
+    test_paper::TestPaper,
+    test_question::{QuestionOwner, TestQuestion},
+};
+use std::io::{Cursor, Write};
+use uuid::Uuid;
+use zip::{write::FileOptions, ZipWriter};
+
+/// Writes the questions of a test paper to a QTI 2.1 package. Every question
+/// becomes a choice item worth a single mark. Questions drawn from question
+/// banks differ for every attempt, so they are left out.
+pub fn export(paper: &TestPaper, conn: &PgConnection) -> SResult<Vec<u8>> {
+    let mut questions = Vec::new();
+    for question in TestQuestion::find_all(QuestionOwner::TestPaper(paper.id), conn)? {
+        let options = QuestionOption::find_all(question.id, conn)?;
+        questions.push((question, options));
+    }
+    write_package(paper, &questions)
+}
+
+/// Writes a package of a test paper and its questions with their options.
+pub fn write_package(
+    paper: &TestPaper,
+    questions: &[(TestQuestion, Vec<QuestionOption>)],
+) -> SResult<Vec<u8>> {
+    let items: Vec<_> = questions
+        .iter()
+        .map(|&(ref question, ref options)| (item_id(question.uuid), item_xml(question, options)))
+        .collect();
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("imsmanifest.xml", FileOptions::default())?;
+    zip.write_all(manifest_xml(paper, &items).as_bytes())?;
+    zip.start_file("test.xml", FileOptions::default())?;
+    zip.write_all(test_xml(paper, &items).as_bytes())?;
+    for &(ref id, ref xml) in &items {
+        zip.start_file(item_href(id), FileOptions::default())?;
+        zip.write_all(xml.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// QTI identifiers cannot start with a digit, unlike the uuids.
+fn item_id(uuid: Uuid) -> String {
+    format!("Q-{}", uuid)
+}
+
+fn choice_id(uuid: Uuid) -> String {
+    format!("O-{}", uuid)
+}
+
+fn item_href(id: &str) -> String {
+    format!("items/{}.xml", id)
+}
+
+fn manifest_xml(paper: &TestPaper, items: &[(String, String)]) -> String {
+    let test_id = format!("T-{}", paper.uuid);
+    let mut xml = String::new();
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(&format!(
+        r#"<manifest xmlns="http://www.imsglobal.org/xsd/imscp_v1p1" identifier="M-{}">"#,
+        paper.uuid
+    ));
+    xml.push_str("<metadata><schema>QTIv2.1 Package</schema>");
+    xml.push_str("<schemaversion>1.0.0</schemaversion></metadata>");
+    xml.push_str("<organizations/><resources>");
+    xml.push_str(&format!(
+        r#"<resource identifier="{}" type="{}" href="test.xml"><file href="test.xml"/>"#,
+        test_id, TEST_TYPE
+    ));
+    for &(ref id, _) in items {
+        xml.push_str(&format!(r#"<dependency identifierref="{}"/>"#, id));
+    }
+    xml.push_str("</resource>");
+    for &(ref id, _) in items {
+        let href = item_href(id);
+        xml.push_str(&format!(
+            r#"<resource identifier="{}" type="{}" href="{}"><file href="{}"/></resource>"#,
+            id, ITEM_TYPE, href, href
+        ));
+    }
+    xml.push_str("</resources></manifest>");
+    xml
+}
+
+fn test_xml(paper: &TestPaper, items: &[(String, String)]) -> String {
+    let mut xml = String::new();
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(&format!(
+        r#"<assessmentTest xmlns="http://www.imsglobal.org/xsd/imsqti_v2p1" identifier="T-{}" title="{}">"#,
+        paper.uuid,
+        escape(&paper.name)
+    ));
+    xml.push_str(
+        r#"<testPart identifier="part" navigationMode="nonlinear" submissionMode="simultaneous">"#,
+    );
+    xml.push_str(&format!(
+        r#"<assessmentSection identifier="section" title="{}" visible="true">"#,
+        escape(&paper.name)
+    ));
+    for &(ref id, _) in items {
+        xml.push_str(&format!(
+            r#"<assessmentItemRef identifier="{}" href="{}"/>"#,
+            id,
+            item_href(id)
+        ));
+    }
+    xml.push_str("</assessmentSection></testPart></assessmentTest>");
+    xml
+}
+
+fn item_xml(question: &TestQuestion, options: &[QuestionOption]) -> String {
+    let correct: Vec<&QuestionOption> = options
+        .iter()
+        .filter(|option| option.is_correct == Some(true))
+        .collect();
+    let cardinality = if correct.len() > 1 {
+        "multiple"
+    } else {
+        "single"
+    };
+
+    let mut xml = String::new();
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(&format!(
+        r#"<assessmentItem xmlns="http://www.imsglobal.org/xsd/imsqti_v2p1" identifier="{}" title="{}" adaptive="false" timeDependent="false">"#,
+        item_id(question.uuid),
+        escape(&question.question)
+    ));
+    xml.push_str(&format!(
+        r#"<responseDeclaration identifier="RESPONSE" cardinality="{}" baseType="identifier">"#,
+        cardinality
+    ));
+    xml.push_str("<correctResponse>");
+    for option in &correct {
+        xml.push_str(&format!("<value>{}</value>", choice_id(option.uuid)));
+    }
+    xml.push_str("</correctResponse></responseDeclaration>");
+    xml.push_str(r#"<outcomeDeclaration identifier="SCORE" cardinality="single" baseType="float">"#);
+    xml.push_str("<defaultValue><value>0</value></defaultValue></outcomeDeclaration>");
+    xml.push_str(
+        r#"<outcomeDeclaration identifier="MAXSCORE" cardinality="single" baseType="float">"#,
+    );
+    xml.push_str("<defaultValue><value>1</value></defaultValue></outcomeDeclaration>");
+    xml.push_str(&format!(
+        r#"<itemBody><choiceInteraction responseIdentifier="RESPONSE" shuffle="false" maxChoices="{}">"#,
+        if correct.len() > 1 { 0 } else { 1 }
+    ));
+    xml.push_str(&format!("<prompt>{}</prompt>", escape(&question.question)));
+    for option in options {
+        xml.push_str(&format!(
+            r#"<simpleChoice identifier="{}">{}</simpleChoice>"#,
+            choice_id(option.uuid),
+            escape(&option.option)
+        ));
+    }
+    xml.push_str("</choiceInteraction></itemBody>");
+    xml.push_str(r#"<responseProcessing template="http://www.imsglobal.org/question/qti_v2p1/rptemplates/match_correct"/>"#);
+    xml.push_str("</assessmentItem>");
+    xml
+}
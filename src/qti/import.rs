@@ -0,0 +1,241 @@
+use super::{SkippedItem, ITEM_TYPE, TEST_TYPE};
+use errors::{Error, SResult};
+use models::{question_option::QuestionOptionForm, test_question::TestQuestionForm};
+use std::io::{Cursor, Read};
+use xml::{attribute::OwnedAttribute, reader::XmlEvent, EventReader};
+use zip::ZipArchive;
+
+/// Questions read from a package.
+pub struct ParsedPackage {
+    /// Title of the assessment test, if the package has one.
+    pub title: Option<String>,
+    pub questions: Vec<TestQuestionForm>,
+    pub skipped: Vec<SkippedItem>,
+}
+
+struct Resource {
+    identifier: String,
+    type_: String,
+    href: String,
+}
+
+/// Reads the choice items of a package in the order of its manifest. Items
+/// of any other type are skipped and reported.
+pub fn parse(package: &[u8]) -> SResult<ParsedPackage> {
+    let mut archive = ZipArchive::new(Cursor::new(package))
+        .map_err(|err| Error::InvalidPackage(format!("Could not open the package: {}", err)))?;
+    let resources = parse_manifest(&read_file(&mut archive, "imsmanifest.xml")?)?;
+
+    let mut title = None;
+    if let Some(test) = resources.iter().find(|resource| resource.type_ == TEST_TYPE) {
+        title = parse_test_title(&test.href, &read_file(&mut archive, &test.href)?)?;
+    }
+
+    let mut questions = Vec::new();
+    let mut skipped = Vec::new();
+    for resource in resources.iter().filter(|resource| resource.type_ == ITEM_TYPE) {
+        let xml = read_file(&mut archive, &resource.href)?;
+        match parse_item(&resource.href, &xml)? {
+            Ok(question) => questions.push(question),
+            Err(reason) => skipped.push(SkippedItem {
+                identifier: resource.identifier.clone(),
+                reason,
+            }),
+        }
+    }
+
+    Ok(ParsedPackage {
+        title,
+        questions,
+        skipped,
+    })
+}
+
+fn read_file(archive: &mut ZipArchive<Cursor<&[u8]>>, href: &str) -> SResult<String> {
+    let path = href.trim_start_matches("./");
+    let mut content = String::new();
+    archive
+        .by_name(path)
+        .map_err(|_| Error::InvalidPackage(format!("The package has no `{}`.", path)))?
+        .read_to_string(&mut content)
+        .map_err(|_| Error::InvalidPackage(format!("Could not read `{}`.", path)))?;
+    Ok(content)
+}
+
+fn attribute<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attribute| attribute.name.local_name == name)
+        .map(|attribute| attribute.value.as_str())
+}
+
+fn invalid_xml<E: ::std::fmt::Display>(href: &str, err: E) -> Error {
+    Error::InvalidPackage(format!("`{}` is not valid XML: {}", href, err))
+}
+
+fn parse_manifest(xml: &str) -> SResult<Vec<Resource>> {
+    let mut resources = Vec::new();
+    for event in EventReader::new(xml.as_bytes()) {
+        match event.map_err(|err| invalid_xml("imsmanifest.xml", err))? {
+            XmlEvent::StartElement {
+                ref name,
+                ref attributes,
+                ..
+            } if name.local_name == "resource" =>
+            {
+                let identifier = attribute(attributes, "identifier");
+                let type_ = attribute(attributes, "type");
+                let href = attribute(attributes, "href");
+                if let (Some(identifier), Some(type_), Some(href)) = (identifier, type_, href) {
+                    resources.push(Resource {
+                        identifier: identifier.to_string(),
+                        type_: type_.to_string(),
+                        href: href.to_string(),
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(resources)
+}
+
+fn parse_test_title(href: &str, xml: &str) -> SResult<Option<String>> {
+    for event in EventReader::new(xml.as_bytes()) {
+        match event.map_err(|err| invalid_xml(href, err))? {
+            XmlEvent::StartElement {
+                ref name,
+                ref attributes,
+                ..
+            } if name.local_name == "assessmentTest" =>
+            {
+                return Ok(attribute(attributes, "title").map(str::to_string));
+            }
+            _ => (),
+        }
+    }
+    Ok(None)
+}
+
+/// Parses an item to a question, or to the reason it is not supported.
+fn parse_item(href: &str, xml: &str) -> SResult<Result<TestQuestionForm, String>> {
+    // Names of the open elements.
+    let mut path: Vec<String> = Vec::new();
+    let mut question = String::new();
+    let mut choices: Vec<(String, String)> = Vec::new();
+    let mut correct: Vec<String> = Vec::new();
+    let mut interactions = 0;
+
+    for event in EventReader::new(xml.as_bytes()) {
+        match event.map_err(|err| invalid_xml(href, err))? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let name = name.local_name;
+                if name.ends_with("Interaction") {
+                    if name != "choiceInteraction" {
+                        return Ok(Err(format!("The {} is not supported.", name)));
+                    }
+                    interactions += 1;
+                } else if name == "simpleChoice" {
+                    let identifier = attribute(&attributes, "identifier").unwrap_or_default();
+                    choices.push((identifier.to_string(), String::new()));
+                } else if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                    if let Some(text) = text_of(&path, &mut question, &mut choices) {
+                        start_line(text);
+                    }
+                } else if name == "mapEntry" {
+                    // Partial marks of a mapping count a choice as correct.
+                    let key = attribute(&attributes, "mapKey");
+                    let value = attribute(&attributes, "mappedValue")
+                        .and_then(|value| value.parse::<f64>().ok());
+                    if let (Some(key), Some(value)) = (key, value) {
+                        if value > 0.0 {
+                            correct.push(key.to_string());
+                        }
+                    }
+                }
+                path.push(name);
+            }
+            XmlEvent::EndElement { .. } => {
+                path.pop();
+            }
+            XmlEvent::Characters(chunk) | XmlEvent::CData(chunk) => {
+                if path.iter().any(|open| open == "correctResponse") {
+                    correct.push(chunk.trim().to_string());
+                } else if let Some(text) = text_of(&path, &mut question, &mut choices) {
+                    text.push_str(&chunk);
+                }
+            }
+            // Spaces between inline elements, unlike the indentation of the
+            // elements, are a part of the text.
+            XmlEvent::Whitespace(ref chunk) if !chunk.contains('\n') => {
+                if let Some(text) = text_of(&path, &mut question, &mut choices) {
+                    text.push_str(chunk);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if interactions == 0 {
+        return Ok(Err("Items without a choice interaction are not supported.".into()));
+    }
+    if interactions > 1 {
+        return Ok(Err("Items with more than one interaction are not supported.".into()));
+    }
+    let question = question.trim().to_string();
+    if question.is_empty() {
+        return Ok(Err("The item has no question text.".into()));
+    }
+    if choices.len() < 2 {
+        return Ok(Err("The item has less than two choices.".into()));
+    }
+    if !choices.iter().any(|(identifier, _)| correct.contains(identifier)) {
+        return Ok(Err("The item has no correct response.".into()));
+    }
+
+    let options = choices
+        .into_iter()
+        .map(|(identifier, text)| QuestionOptionForm {
+            option: text.trim().to_string(),
+            is_correct: Some(correct.contains(&identifier)),
+            feedback: None,
+        }).collect();
+    Ok(Ok(TestQuestionForm {
+        question,
+        topic: None,
+        tags: None,
+        difficulty: None,
+        explanation: None,
+        options,
+    }))
+}
+
+/// Elements whose text starts on a line of its own.
+const BLOCK_ELEMENTS: &[&str] = &["p", "div", "br", "li", "prompt"];
+
+/// The text which the content of the open elements belongs to, which is
+/// either the question or the last choice.
+fn text_of<'a>(
+    path: &[String],
+    question: &'a mut String,
+    choices: &'a mut Vec<(String, String)>,
+) -> Option<&'a mut String> {
+    let inside = |name: &str| path.iter().any(|open| open == name);
+    if inside("simpleChoice") {
+        choices.last_mut().map(|&mut (_, ref mut choice)| choice)
+    } else if inside("itemBody") {
+        Some(question)
+    } else {
+        None
+    }
+}
+
+fn start_line(text: &mut String) {
+    let end = text.trim_end_matches(|c| c == ' ' || c == '\t').len();
+    text.truncate(end);
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
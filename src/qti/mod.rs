@@ -0,0 +1,300 @@
+//! Exchange of test papers with other LMSes as IMS QTI 2.1 packages. A package
+//! is a zip of the `imsmanifest.xml`, an assessment test and its items.
+
+use base64;
+use diesel::PgConnection;
+use errors::{Error, SResult};
+use models::test_paper::{TestPaper, TestPaperForm, TestType};
+use Context;
+
+mod export;
+mod import;
+
+pub use self::export::export;
+
+const TEST_TYPE: &str = "imsqti_test_xmlv2p1";
+const ITEM_TYPE: &str = "imsqti_item_xmlv2p1";
+
+/// An item of a package which could not be imported.
+pub struct SkippedItem {
+    identifier: String,
+    reason: String,
+}
+
+graphql_object!(SkippedItem: () |&self| {
+    description: "An item of a QTI package which was skipped during the import."
+
+    field identifier() -> &str
+        as "Identifier of the item in the package manifest."
+    {
+        &self.identifier
+    }
+
+    field reason() -> &str
+        as "Reason why the item was skipped."
+    {
+        &self.reason
+    }
+});
+
+/// A type to import a test paper from a QTI package.
+#[derive(GraphQLInputObject)]
+pub struct QtiImport {
+    /// Name of the test paper. Defaults to the title of the assessment test.
+    name: Option<String>,
+    /// Description of the test paper.
+    description: Option<String>,
+    /// Type of the test paper.
+    type_: TestType,
+    /// Base64 encoded QTI 2.1 package.
+    package: String,
+}
+
+impl QtiImport {
+    pub fn save(self, conn: &PgConnection) -> SResult<QtiImportResult> {
+        let package = base64::decode(&self.package)
+            .map_err(|_| Error::InvalidPackage("The package is not base64 encoded.".into()))?;
+        let parsed = import::parse(&package)?;
+        let name = match self.name.or(parsed.title) {
+            Some(name) => name,
+            None => Err(Error::InvalidPackage(
+                "The package has no assessment test title, so a name is required.".into(),
+            ))?,
+        };
+
+        let form = TestPaperForm {
+            name,
+            description: self.description,
+            type_: self.type_,
+            shuffle: None,
+            questions: parsed.questions,
+            draw_rules: None,
+        };
+        Ok(QtiImportResult {
+            test_paper: form.save(conn)?,
+            skipped: parsed.skipped,
+        })
+    }
+}
+
+/// The test paper imported from a QTI package with the items left out.
+pub struct QtiImportResult {
+    test_paper: TestPaper,
+    skipped: Vec<SkippedItem>,
+}
+
+graphql_object!(QtiImportResult: Context |&self| {
+    description: "Result of importing a QTI package."
+
+    field test_paper() -> &TestPaper
+        as "The imported test paper."
+    {
+        &self.test_paper
+    }
+
+    field skipped() -> &Vec<SkippedItem>
+        as "Items of the package with an unsupported type which were skipped."
+    {
+        &self.skipped
+    }
+});
+
+/// Escapes the text for XML content and attribute values.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+    use models::{
+        question_option::QuestionOption, test_paper::PaperStatus, test_question::TestQuestion,
+    };
+    use std::io::{Cursor, Write};
+    use uuid::Uuid;
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn uuid(number: i32) -> Uuid {
+        Uuid::parse_str(&format!("00000000-0000-0000-0000-{:012}", number)).unwrap()
+    }
+
+    fn paper() -> TestPaper {
+        TestPaper {
+            id: 1,
+            uuid: uuid(1),
+            name: "Planets & <moons>".into(),
+            description: None,
+            type_: TestType::Scheduled,
+            shuffle: false,
+            status: PaperStatus::Published,
+            version: 1,
+            previous_version_id: None,
+            is_latest: true,
+            created_at: NaiveDateTime::from_timestamp(0, 0),
+            lock_version: 1,
+        }
+    }
+
+    fn question(
+        id: i32,
+        text: &str,
+        options: &[(&str, bool)],
+    ) -> (TestQuestion, Vec<QuestionOption>) {
+        let question = TestQuestion {
+            id,
+            uuid: uuid(id * 100),
+            question: text.into(),
+            test_paper_id: Some(1),
+            position: id,
+            question_bank_id: None,
+            topic: None,
+            tags: Vec::new(),
+            difficulty: None,
+            explanation: None,
+            lock_version: 1,
+        };
+        let options = (0..)
+            .zip(options)
+            .map(|(position, &(option, is_correct))| QuestionOption {
+                id: id * 100 + position + 1,
+                uuid: uuid(id * 100 + position + 1),
+                option: option.into(),
+                test_question_id: id,
+                is_correct: Some(is_correct),
+                position,
+                feedback: None,
+            }).collect();
+        (question, options)
+    }
+
+    #[test]
+    fn imports_the_export() {
+        let paper = paper();
+        let questions = vec![
+            question(
+                1,
+                "Which planet is the largest?",
+                &[("Mars", false), ("Jupiter", true), ("Venus", false)],
+            ),
+            question(
+                2,
+                "Which planets are < 1 AU & rocky?",
+                &[("Mercury", true), ("Venus", true), ("\"Jupiter\"", false)],
+            ),
+            question(
+                3,
+                "What is H<sub>2</sub>O?\nPick one.",
+                &[("Water,\nor ice", true), ("Na<sub>Cl</sub>", false)],
+            ),
+        ];
+        let package = export::write_package(&paper, &questions).unwrap();
+        let parsed = import::parse(&package).unwrap();
+
+        assert_eq!(parsed.title, Some(paper.name));
+        assert!(parsed.skipped.is_empty());
+        assert_eq!(parsed.questions.len(), questions.len());
+        for (imported, &(ref question, ref options)) in parsed.questions.iter().zip(&questions) {
+            assert_eq!(imported.question, question.question);
+            let imported: Vec<_> = imported
+                .options
+                .iter()
+                .map(|option| (option.option.as_str(), option.is_correct == Some(true)))
+                .collect();
+            let exported: Vec<_> = options
+                .iter()
+                .map(|option| (option.option.as_str(), option.is_correct == Some(true)))
+                .collect();
+            assert_eq!(imported, exported);
+        }
+    }
+
+    fn package(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for &(name, content) in files {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn unsupported_package() -> Vec<u8> {
+        package(&[
+            (
+                "imsmanifest.xml",
+                include_str!("fixtures/unsupported/imsmanifest.xml"),
+            ),
+            ("test.xml", include_str!("fixtures/unsupported/test.xml")),
+            (
+                "items/planet.xml",
+                include_str!("fixtures/unsupported/items/planet.xml"),
+            ),
+            (
+                "items/essay.xml",
+                include_str!("fixtures/unsupported/items/essay.xml"),
+            ),
+        ])
+    }
+
+    #[test]
+    fn skips_unsupported_interactions() {
+        let parsed = import::parse(&unsupported_package()).unwrap();
+
+        assert_eq!(parsed.title, Some("Astronomy".into()));
+        assert_eq!(parsed.questions.len(), 1);
+        let question = &parsed.questions[0];
+        assert_eq!(question.question, "Which planet is the largest?");
+        let options: Vec<_> = question
+            .options
+            .iter()
+            .map(|option| (option.option.as_str(), option.is_correct))
+            .collect();
+        assert_eq!(options, vec![("Mars", Some(false)), ("Jupiter", Some(true))]);
+
+        assert_eq!(parsed.skipped.len(), 1);
+        assert_eq!(parsed.skipped[0].identifier, "essay");
+        assert_eq!(
+            parsed.skipped[0].reason,
+            "The extendedTextInteraction is not supported."
+        );
+    }
+
+    #[test]
+    fn keeps_the_lines_of_marked_up_text() {
+        let parsed = import::parse(&package(&[
+            (
+                "imsmanifest.xml",
+                include_str!("fixtures/markup/imsmanifest.xml"),
+            ),
+            ("test.xml", include_str!("fixtures/markup/test.xml")),
+            (
+                "items/water.xml",
+                include_str!("fixtures/markup/items/water.xml"),
+            ),
+        ])).unwrap();
+
+        assert_eq!(parsed.questions.len(), 1);
+        let question = &parsed.questions[0];
+        assert_eq!(
+            question.question,
+            "What is H2O?\nPick one answer.\nOnly one.\nChoose the name."
+        );
+        let options: Vec<_> = question
+            .options
+            .iter()
+            .map(|option| (option.option.as_str(), option.is_correct))
+            .collect();
+        assert_eq!(options, vec![("Water", Some(true)), ("NaCl salt", Some(false))]);
+    }
+}
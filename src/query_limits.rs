@@ -0,0 +1,802 @@
+//! Limits on the shape of GraphQL queries, checked before they are executed
+//! so that a single request cannot tie up the database. Juniper does not
+//! expose the documents it parses, so queries are read here just enough to
+//! measure their depth, complexity and aliases. A query which cannot be read
+//! is rejected, as it could not be measured.
+
+use config::GraphqlConfig;
+use connection::MAX_PAGE_SIZE;
+use errors::{Error, SResult};
+use juniper::InputValue;
+use std::collections::HashMap;
+
+/// Deepest nesting of lists and objects in the values of a query.
+const MAX_VALUE_NESTING: usize = 32;
+
+pub struct QueryLimits {
+    max_depth: usize,
+    max_complexity: u64,
+    max_aliases: usize,
+    field_costs: HashMap<String, u64>,
+}
+
+impl QueryLimits {
+    pub fn new(config: &GraphqlConfig) -> QueryLimits {
+        QueryLimits {
+            max_depth: config.max_depth,
+            max_complexity: config.max_complexity,
+            max_aliases: config.max_aliases,
+            field_costs: config.field_costs.clone(),
+        }
+    }
+
+    /// Checks the operation of a request which is going to be executed.
+    pub fn check(
+        &self,
+        query: &str,
+        operation_name: Option<&str>,
+        variables: Option<&InputValue>,
+    ) -> SResult<()> {
+        let tokens = tokenize(query).ok_or(Error::InvalidQuery)?;
+        // Nesting beyond the depth limit is rejected while parsing, before it
+        // can overflow the stack. Inline fragments count as a level here.
+        let mut parser = Parser::new(tokens, self.max_depth + 1);
+        let document = match parser.document() {
+            Some(document) => document,
+            None if parser.is_too_deep => return Err(too_complex("depth", self.max_depth as u64)),
+            None => return Err(Error::InvalidQuery),
+        };
+        let operation = match operation_name {
+            Some(name) => document
+                .operations
+                .iter()
+                .find(|operation| operation.name == Some(name)),
+            None if document.operations.len() == 1 => document.operations.first(),
+            None => None,
+        };
+        let operation = match operation {
+            Some(operation) => operation,
+            None => return Ok(()),
+        };
+
+        let variables = variables
+            .and_then(InputValue::to_object_value)
+            .unwrap_or_default();
+        let mut measure = Measure {
+            limits: self,
+            fragments: &document.fragments,
+            variables: &variables,
+            defaults: &operation.defaults,
+            spreading: Vec::new(),
+            complexity: 0,
+            aliases: 0,
+        };
+        measure.selections(&operation.selections, 0, 1)
+    }
+
+    fn cost(&self, field: &str) -> u64 {
+        self.field_costs.get(field).cloned().unwrap_or(1)
+    }
+}
+
+/// Walks an operation, failing as soon as it is over a limit. Every field
+/// adds at least 1 to the complexity, so the walk ends early even when
+/// fragments spread each other many times over.
+struct Measure<'a> {
+    limits: &'a QueryLimits,
+    fragments: &'a HashMap<&'a str, Vec<Selection<'a>>>,
+    variables: &'a HashMap<&'a str, &'a InputValue>,
+    /// Integer default values of the variables of the operation.
+    defaults: &'a HashMap<&'a str, i64>,
+    /// Fragments being spread, to skip cycles which Juniper rejects anyway.
+    spreading: Vec<&'a str>,
+    complexity: u64,
+    aliases: usize,
+}
+
+impl<'a> Measure<'a> {
+    fn selections(
+        &mut self,
+        selections: &'a [Selection<'a>],
+        depth: usize,
+        multiplier: u64,
+    ) -> SResult<()> {
+        for selection in selections {
+            match *selection {
+                Selection::Field(ref field) => self.field(field, depth + 1, multiplier)?,
+                Selection::InlineFragment(ref selections) => {
+                    self.selections(selections, depth, multiplier)?
+                }
+                Selection::FragmentSpread(name) => {
+                    if self.spreading.contains(&name) {
+                        continue;
+                    }
+                    let fragments = self.fragments;
+                    if let Some(selections) = fragments.get(name) {
+                        self.spreading.push(name);
+                        self.selections(selections, depth, multiplier)?;
+                        self.spreading.pop();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn field(&mut self, field: &'a Field<'a>, depth: usize, multiplier: u64) -> SResult<()> {
+        let limits = self.limits;
+        if depth > limits.max_depth {
+            return Err(too_complex("depth", limits.max_depth as u64));
+        }
+        if field.is_aliased {
+            self.aliases += 1;
+            if self.aliases > limits.max_aliases {
+                return Err(too_complex("aliases", limits.max_aliases as u64));
+            }
+        }
+        let cost = limits.cost(field.name).saturating_mul(multiplier);
+        self.complexity = self.complexity.saturating_add(cost);
+        if self.complexity > limits.max_complexity {
+            return Err(too_complex("complexity", limits.max_complexity));
+        }
+
+        let multiplier = multiplier.saturating_mul(self.page_size(field));
+        self.selections(&field.selections, depth, multiplier)
+    }
+
+    /// Most items a field can list, as given by its `first` or `last`
+    /// arguments. A connection lists a full page without them.
+    fn page_size(&self, field: &'a Field<'a>) -> u64 {
+        let count = field
+            .arguments
+            .iter()
+            .filter(|&&(name, _)| name == "first" || name == "last")
+            .filter_map(|&(_, ref argument)| match *argument {
+                Argument::Int(count) => Some(count),
+                Argument::Variable(name) => self.variable(name),
+                Argument::Other => None,
+            }).max();
+        match count {
+            Some(count) => count.max(1).min(MAX_PAGE_SIZE) as u64,
+            None if self.selects_edges(&field.selections, &mut Vec::new()) => MAX_PAGE_SIZE as u64,
+            None => 1,
+        }
+    }
+
+    /// Integer value of a variable, or its default when it is not given.
+    fn variable(&self, name: &str) -> Option<i64> {
+        match self.variables.get(name) {
+            Some(&&InputValue::Int(count)) => Some(i64::from(count)),
+            Some(_) => None,
+            None => self.defaults.get(name).cloned(),
+        }
+    }
+
+    /// Whether the selections are of a connection, which has `edges`.
+    fn selects_edges(&self, selections: &'a [Selection<'a>], spread: &mut Vec<&'a str>) -> bool {
+        selections.iter().any(|selection| match *selection {
+            Selection::Field(ref field) => field.name == "edges",
+            Selection::InlineFragment(ref selections) => self.selects_edges(selections, spread),
+            Selection::FragmentSpread(name) => {
+                if spread.contains(&name) {
+                    return false;
+                }
+                spread.push(name);
+                let fragments = self.fragments;
+                fragments
+                    .get(name)
+                    .map_or(false, |selections| self.selects_edges(selections, spread))
+            }
+        })
+    }
+}
+
+fn too_complex(limit: &'static str, max: u64) -> Error {
+    Error::QueryTooComplex { limit, max }
+}
+
+/// Keywords of the operations of a document, like `subscription`, with
+/// their byte offsets. Gives nothing when the document cannot be read.
+pub fn operation_keywords(source: &str) -> Option<Vec<(usize, &str)>> {
+    let mut keywords = Vec::new();
+    let mut braces = 0usize;
+    let mut parentheses = 0usize;
+    // A definition starts the document and follows the selection set which
+    // ends the previous one. Braces in parentheses are object values.
+    let mut is_definition_next = true;
+    for token in tokenize(source)? {
+        let is_definition = is_definition_next;
+        is_definition_next = false;
+        match token {
+            Token::Name(name) if is_definition && name != "fragment" => {
+                // Names are slices of the source.
+                let offset = name.as_ptr() as usize - source.as_ptr() as usize;
+                keywords.push((offset, name));
+            }
+            Token::Punctuator('(') => parentheses += 1,
+            Token::Punctuator(')') => parentheses = parentheses.checked_sub(1)?,
+            Token::Punctuator('{') => braces += 1,
+            Token::Punctuator('}') => {
+                braces = braces.checked_sub(1)?;
+                is_definition_next = braces == 0 && parentheses == 0;
+            }
+            _ => (),
+        }
+    }
+    Some(keywords)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Punctuator(char),
+    Spread,
+    Name(&'a str),
+    Int(i64),
+    /// Floats and strings, which only matter as arguments.
+    Literal,
+}
+
+fn tokenize(source: &str) -> Option<Vec<Token>> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b' ' | b'\t' | b'\n' | b'\r' | b',' => i += 1,
+            0xEF if bytes[i..].starts_with("\u{feff}".as_bytes()) => i += 3,
+            b'#' => {
+                while i < bytes.len() && bytes[i] != b'\n' && bytes[i] != b'\r' {
+                    i += 1;
+                }
+            }
+            b'.' if bytes[i..].starts_with(b"...") => {
+                tokens.push(Token::Spread);
+                i += 3;
+            }
+            b'"' if bytes[i..].starts_with(br#"""""#) => {
+                i += 3;
+                loop {
+                    if i >= bytes.len() {
+                        return None;
+                    } else if bytes[i..].starts_with(br#"\""""#) {
+                        i += 4;
+                    } else if bytes[i..].starts_with(br#"""""#) {
+                        i += 3;
+                        break;
+                    } else {
+                        i += 1;
+                    }
+                }
+                tokens.push(Token::Literal);
+            }
+            b'"' => {
+                i += 1;
+                loop {
+                    match bytes.get(i) {
+                        None | Some(b'\n') | Some(b'\r') => return None,
+                        Some(b'\\') => i += 2,
+                        Some(b'"') => break,
+                        Some(_) => i += 1,
+                    }
+                }
+                i += 1;
+                tokens.push(Token::Literal);
+            }
+            b'-' | b'0'..=b'9' => {
+                let start = i;
+                i += 1;
+                while i < bytes.len() {
+                    match bytes[i] {
+                        b'0'..=b'9' | b'.' | b'e' | b'E' | b'+' | b'-' => i += 1,
+                        _ => break,
+                    }
+                }
+                let number = &source[start..i];
+                tokens.push(number.parse().map(Token::Int).unwrap_or(Token::Literal));
+            }
+            b'_' | b'a'..=b'z' | b'A'..=b'Z' => {
+                let start = i;
+                while i < bytes.len() && (bytes[i] == b'_' || bytes[i].is_ascii_alphanumeric()) {
+                    i += 1;
+                }
+                tokens.push(Token::Name(&source[start..i]));
+            }
+            c @ b'{' | c @ b'}' | c @ b'(' | c @ b')' | c @ b'[' | c @ b']' | c @ b':'
+            | c @ b'$' | c @ b'@' | c @ b'!' | c @ b'=' | c @ b'|' | c @ b'&' => {
+                tokens.push(Token::Punctuator(c as char));
+                i += 1;
+            }
+            _ => return None,
+        }
+    }
+    Some(tokens)
+}
+
+struct Document<'a> {
+    operations: Vec<Operation<'a>>,
+    fragments: HashMap<&'a str, Vec<Selection<'a>>>,
+}
+
+struct Operation<'a> {
+    name: Option<&'a str>,
+    defaults: HashMap<&'a str, i64>,
+    selections: Vec<Selection<'a>>,
+}
+
+enum Selection<'a> {
+    Field(Field<'a>),
+    FragmentSpread(&'a str),
+    InlineFragment(Vec<Selection<'a>>),
+}
+
+struct Field<'a> {
+    is_aliased: bool,
+    name: &'a str,
+    arguments: Vec<(&'a str, Argument<'a>)>,
+    selections: Vec<Selection<'a>>,
+}
+
+enum Argument<'a> {
+    Int(i64),
+    Variable(&'a str),
+    Other,
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    /// Selection sets being parsed, and the most allowed.
+    nesting: usize,
+    max_nesting: usize,
+    /// Lists, objects and list types being parsed.
+    value_nesting: usize,
+    /// Whether parsing stopped as the selection sets nest too deep.
+    is_too_deep: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: Vec<Token<'a>>, max_nesting: usize) -> Parser<'a> {
+        Parser {
+            tokens,
+            position: 0,
+            nesting: 0,
+            max_nesting,
+            value_nesting: 0,
+            is_too_deep: false,
+        }
+    }
+
+    fn enter_value(&mut self) -> Option<()> {
+        self.value_nesting += 1;
+        if self.value_nesting > MAX_VALUE_NESTING {
+            None
+        } else {
+            Some(())
+        }
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).cloned()
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn is_next(&self, c: char) -> bool {
+        self.peek() == Some(Token::Punctuator(c))
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        match self.next()? {
+            Token::Punctuator(next) if next == c => Some(()),
+            _ => None,
+        }
+    }
+
+    fn name(&mut self) -> Option<&'a str> {
+        match self.next()? {
+            Token::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn document(&mut self) -> Option<Document<'a>> {
+        let mut document = Document {
+            operations: Vec::new(),
+            fragments: HashMap::new(),
+        };
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Punctuator('{') => document.operations.push(Operation {
+                    name: None,
+                    defaults: HashMap::new(),
+                    selections: self.selection_set()?,
+                }),
+                Token::Name("fragment") => {
+                    self.next();
+                    let name = self.name()?;
+                    self.type_condition()?;
+                    self.directives()?;
+                    document.fragments.insert(name, self.selection_set()?);
+                }
+                Token::Name(_) => {
+                    self.next();
+                    let name = match self.peek() {
+                        Some(Token::Name(name)) => {
+                            self.next();
+                            Some(name)
+                        }
+                        _ => None,
+                    };
+                    let defaults = if self.is_next('(') {
+                        self.variable_definitions()?
+                    } else {
+                        HashMap::new()
+                    };
+                    self.directives()?;
+                    document.operations.push(Operation {
+                        name,
+                        defaults,
+                        selections: self.selection_set()?,
+                    });
+                }
+                _ => return None,
+            }
+        }
+        Some(document)
+    }
+
+    fn type_condition(&mut self) -> Option<()> {
+        match self.name()? {
+            "on" => self.name().map(|_| ()),
+            _ => None,
+        }
+    }
+
+    /// Reads the variables of an operation, returning the integer defaults.
+    fn variable_definitions(&mut self) -> Option<HashMap<&'a str, i64>> {
+        self.expect('(')?;
+        let mut defaults = HashMap::new();
+        while !self.is_next(')') {
+            self.expect('$')?;
+            let name = self.name()?;
+            self.expect(':')?;
+            self.type_reference()?;
+            if self.is_next('=') {
+                self.next();
+                if let Argument::Int(value) = self.value()? {
+                    defaults.insert(name, value);
+                }
+            }
+        }
+        self.next();
+        Some(defaults)
+    }
+
+    fn type_reference(&mut self) -> Option<()> {
+        if self.is_next('[') {
+            self.next();
+            self.enter_value()?;
+            self.type_reference()?;
+            self.expect(']')?;
+            self.value_nesting -= 1;
+        } else {
+            self.name()?;
+        }
+        if self.is_next('!') {
+            self.next();
+        }
+        Some(())
+    }
+
+    fn directives(&mut self) -> Option<()> {
+        while self.is_next('@') {
+            self.next();
+            self.name()?;
+            if self.is_next('(') {
+                self.arguments()?;
+            }
+        }
+        Some(())
+    }
+
+    fn selection_set(&mut self) -> Option<Vec<Selection<'a>>> {
+        self.expect('{')?;
+        self.nesting += 1;
+        if self.nesting > self.max_nesting {
+            self.is_too_deep = true;
+            return None;
+        }
+        let mut selections = Vec::new();
+        loop {
+            match self.next()? {
+                Token::Punctuator('}') => {
+                    self.nesting -= 1;
+                    return Some(selections);
+                }
+                Token::Spread => match self.peek()? {
+                    Token::Name(name) if name != "on" => {
+                        self.next();
+                        self.directives()?;
+                        selections.push(Selection::FragmentSpread(name));
+                    }
+                    _ => {
+                        if self.peek() == Some(Token::Name("on")) {
+                            self.type_condition()?;
+                        }
+                        self.directives()?;
+                        selections.push(Selection::InlineFragment(self.selection_set()?));
+                    }
+                },
+                Token::Name(name) => {
+                    let (is_aliased, name) = if self.is_next(':') {
+                        self.next();
+                        (true, self.name()?)
+                    } else {
+                        (false, name)
+                    };
+                    let arguments = if self.is_next('(') {
+                        self.arguments()?
+                    } else {
+                        Vec::new()
+                    };
+                    self.directives()?;
+                    let field_selections = if self.is_next('{') {
+                        self.selection_set()?
+                    } else {
+                        Vec::new()
+                    };
+                    selections.push(Selection::Field(Field {
+                        is_aliased,
+                        name,
+                        arguments,
+                        selections: field_selections,
+                    }));
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn arguments(&mut self) -> Option<Vec<(&'a str, Argument<'a>)>> {
+        self.expect('(')?;
+        let mut arguments = Vec::new();
+        while !self.is_next(')') {
+            let name = self.name()?;
+            self.expect(':')?;
+            arguments.push((name, self.value()?));
+        }
+        self.next();
+        Some(arguments)
+    }
+
+    fn value(&mut self) -> Option<Argument<'a>> {
+        match self.next()? {
+            Token::Int(value) => Some(Argument::Int(value)),
+            Token::Punctuator('$') => self.name().map(Argument::Variable),
+            Token::Punctuator('[') => {
+                self.enter_value()?;
+                while !self.is_next(']') {
+                    self.value()?;
+                }
+                self.next();
+                self.value_nesting -= 1;
+                Some(Argument::Other)
+            }
+            Token::Punctuator('{') => {
+                self.enter_value()?;
+                while !self.is_next('}') {
+                    self.name()?;
+                    self.expect(':')?;
+                    self.value()?;
+                }
+                self.next();
+                self.value_nesting -= 1;
+                Some(Argument::Other)
+            }
+            Token::Name(_) | Token::Literal => Some(Argument::Other),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> QueryLimits {
+        QueryLimits {
+            max_depth: 4,
+            max_complexity: 100,
+            max_aliases: 2,
+            field_costs: HashMap::new(),
+        }
+    }
+
+    fn check(query: &str) -> SResult<()> {
+        limits().check(query, None, None)
+    }
+
+    fn check_with(query: &str, name: &str, value: i32) -> SResult<()> {
+        let variables = vec![(name, InputValue::int(value))].into_iter().collect();
+        let variables = InputValue::object(variables);
+        limits().check(query, None, Some(&variables))
+    }
+
+    /// The limit which rejected a query, or `"invalid"`.
+    fn rejected(result: SResult<()>) -> &'static str {
+        match result {
+            Err(Error::QueryTooComplex { limit, .. }) => limit,
+            Err(Error::InvalidQuery) => "invalid",
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(()) => panic!("Expected the query to be rejected."),
+        }
+    }
+
+    /// The query GraphiQL sends to read the schema.
+    const INTROSPECTION_QUERY: &str = "
+        query IntrospectionQuery {
+          __schema {
+            queryType { name }
+            mutationType { name }
+            subscriptionType { name }
+            types { ...FullType }
+            directives { name description locations args { ...InputValue } }
+          }
+        }
+
+        fragment FullType on __Type {
+          kind
+          name
+          description
+          fields(includeDeprecated: true) {
+            name
+            description
+            args { ...InputValue }
+            type { ...TypeRef }
+            isDeprecated
+            deprecationReason
+          }
+          inputFields { ...InputValue }
+          interfaces { ...TypeRef }
+          enumValues(includeDeprecated: true) {
+            name
+            description
+            isDeprecated
+            deprecationReason
+          }
+          possibleTypes { ...TypeRef }
+        }
+
+        fragment InputValue on __InputValue {
+          name
+          description
+          type { ...TypeRef }
+          defaultValue
+        }
+
+        fragment TypeRef on __Type {
+          kind
+          name
+          ofType {
+            kind
+            name
+            ofType {
+              kind
+              name
+              ofType {
+                kind
+                name
+                ofType {
+                  kind
+                  name
+                  ofType {
+                    kind
+                    name
+                    ofType {
+                      kind
+                      name
+                      ofType { kind name }
+                    }
+                  }
+                }
+              }
+            }
+          }
+        }
+    ";
+
+    #[test]
+    fn counts_depth_through_fragments() {
+        assert!(check("{ a { b { c { d } } } }").is_ok());
+        assert_eq!(rejected(check("{ a { b { c { d { e } } } } }")), "depth");
+        assert!(check("{ a { ...F } } fragment F on A { b { c { d } } }").is_ok());
+        assert_eq!(
+            rejected(check("{ a { ...F } } fragment F on A { b { c { d { e } } } }")),
+            "depth"
+        );
+        assert!(check("{ a { ... on A { b { c { d } } } } }").is_ok());
+        assert_eq!(
+            rejected(check("{ a { ... on A { b { c { d { e } } } } } }")),
+            "depth"
+        );
+    }
+
+    #[test]
+    fn counts_aliases() {
+        assert!(check("{ a: users { id } b: users { id } }").is_ok());
+        assert_eq!(
+            rejected(check("{ a: users { id } b: users { id } c: users { id } }")),
+            "aliases"
+        );
+        let query = "{ ...F ...G } fragment F on Q { a: id } fragment G on Q { ...F b: id }";
+        assert_eq!(rejected(check(query)), "aliases");
+    }
+
+    #[test]
+    fn multiplies_by_page_sizes() {
+        let query = "{ users(first: 10) { edges { node { id } } } }";
+        assert!(check(query).is_ok());
+        let query = "{ users(first: 40) { edges { node { id } } } }";
+        assert_eq!(rejected(check(query)), "complexity");
+        let query = "{ users(last: 40) { edges { node { id } } } }";
+        assert_eq!(rejected(check(query)), "complexity");
+        let query = "{ users { edges { node { id } } } }";
+        assert_eq!(rejected(check(query)), "complexity");
+
+        let query = "query($n: Int) { users(first: $n) { edges { node { id } } } }";
+        assert!(check_with(query, "n", 10).is_ok());
+        assert_eq!(rejected(check_with(query, "n", 40)), "complexity");
+        let query = "query($n: Int = 40) { users(first: $n) { edges { node { id } } } }";
+        assert_eq!(rejected(check(query)), "complexity");
+        assert!(check_with(query, "n", 10).is_ok());
+    }
+
+    #[test]
+    fn skips_fragment_cycles() {
+        let query = "{ a { ...F } } fragment F on A { b ...G } fragment G on A { c ...F }";
+        assert!(check(query).is_ok());
+        assert!(check("{ users { ...F } } fragment F on U { id ...F }").is_ok());
+    }
+
+    #[test]
+    fn reads_block_strings() {
+        assert!(check(r#"{ a(text: """ { "quoted" \""" } """) { b } }"#).is_ok());
+        assert!(check("{ a(text: \"\"\"\n{\n{\n\"\"\") { b } }").is_ok());
+        assert_eq!(rejected(check(r#"{ a(text: """ { \""" }) { b } }"#)), "invalid");
+    }
+
+    #[test]
+    fn skips_the_byte_order_mark_and_comments() {
+        assert!(check("\u{feff}# A comment { {\n{ a # { { {\n { b } }").is_ok());
+        assert!(check("{ a { b { c { d # { e }\n } } } }").is_ok());
+        assert_eq!(rejected(check("{ a # }")), "invalid");
+    }
+
+    #[test]
+    fn finds_the_operation_keywords() {
+        let document = "\u{feff}# subscription\nfragment F on S { a(b: { c: 1 }) }\n\
+                        subscription($d: In = { e: 1 }) { ...F } query Q { f { g } }";
+        let keywords: Vec<_> = operation_keywords(document)
+            .unwrap()
+            .into_iter()
+            .map(|(offset, keyword)| {
+                assert_eq!(&document[offset..offset + keyword.len()], keyword);
+                keyword
+            }).collect();
+        assert_eq!(keywords, vec!["subscription", "query"]);
+        assert_eq!(operation_keywords("{ a } { b }").unwrap().len(), 0);
+        assert!(operation_keywords("{ a } }").is_none());
+    }
+
+    #[test]
+    fn passes_the_introspection_query_at_the_default_limits() {
+        let limits = QueryLimits::new(&GraphqlConfig::default());
+        assert!(limits.check(INTROSPECTION_QUERY, None, None).is_ok());
+        let name = Some("IntrospectionQuery");
+        assert!(limits.check(INTROSPECTION_QUERY, name, None).is_ok());
+    }
+}
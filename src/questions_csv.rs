@@ -0,0 +1,366 @@
+//! Questions of a test paper as a spreadsheet. Every row is a question:
+//!
+//! ```text
+//! id,question,topic,difficulty,tags,explanation,option 1,correct 1,feedback 1,option 2,correct 2
+//! ,Which planet is the largest?,Astronomy,EASY,planets;solar system,,Mars,FALSE,No.,Jupiter,TRUE
+//! ```
+//!
+//! Rows with an id update that question of the test paper and the rest are
+//! created. Tags are separated by `;`. Any number of option, correct and
+//! feedback columns may follow the fixed columns. The feedback of an option
+//! is always set from its column, so that it moves along with the text when
+//! the options are reordered.
+
+use csv::{self, ReaderBuilder, StringRecord, Writer};
+use diesel::PgConnection;
+use errors::{Error, SResult};
+use models::{
+    question_option::{
+        QuestionOption, QuestionOptionForm, QuestionOptionUpdate, QuestionOptionsUpdate,
+    },
+    test_paper::{TestPaper, TestPaperUpdate},
+    test_question::{
+        Difficulty, QuestionOwner, TestQuestion, TestQuestionForm, TestQuestionUpdate,
+        TestQuestionsUpdate,
+    },
+};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+const FIXED_COLUMNS: [&str; 6] = ["id", "question", "topic", "difficulty", "tags", "explanation"];
+
+/// A problem with a row of an uploaded file.
+pub struct RowError {
+    /// Row number as shown by spreadsheets, where the header is row 1.
+    pub row: usize,
+    pub message: String,
+}
+
+struct CsvQuestion {
+    row: usize,
+    id: Option<Uuid>,
+    question: String,
+    topic: Option<String>,
+    difficulty: Option<Difficulty>,
+    tags: Vec<String>,
+    explanation: Option<String>,
+    options: Vec<CsvOption>,
+}
+
+struct CsvOption {
+    option: String,
+    is_correct: bool,
+    feedback: Option<String>,
+}
+
+/// Writes the questions of a test paper in the format it can be uploaded in.
+pub fn export(paper: &TestPaper, conn: &PgConnection) -> SResult<Vec<u8>> {
+    let questions = TestQuestion::find_all(QuestionOwner::TestPaper(paper.id), conn)?;
+    let mut rows = Vec::with_capacity(questions.len());
+    let mut max_options = 0;
+    for question in questions {
+        let options = QuestionOption::find_all(question.id, conn)?;
+        max_options = max_options.max(options.len());
+        rows.push((question, options));
+    }
+
+    let mut writer = Writer::from_writer(Vec::new());
+    let mut header: Vec<String> = FIXED_COLUMNS.iter().map(|&column| column.into()).collect();
+    for number in 1..max_options + 1 {
+        header.push(format!("option {}", number));
+        header.push(format!("correct {}", number));
+        header.push(format!("feedback {}", number));
+    }
+    writer.write_record(&header)?;
+
+    for (question, options) in rows {
+        let mut record = vec![
+            question.uuid.to_string(),
+            question.question,
+            question.topic.unwrap_or_default(),
+            question.difficulty.map_or("", difficulty_name).to_string(),
+            question.tags.join(";"),
+            question.explanation.unwrap_or_default(),
+        ];
+        for option in options {
+            let is_correct = option.is_correct == Some(true);
+            record.push(option.option);
+            record.push(if is_correct { "TRUE" } else { "FALSE" }.to_string());
+            record.push(option.feedback.unwrap_or_default());
+        }
+        // Every record has as many fields as the header.
+        record.resize(header.len(), String::new());
+        writer.write_record(&record)?;
+    }
+    Ok(writer
+        .into_inner()
+        .map_err(|err| csv::Error::from(err.into_error()))?)
+}
+
+/// Creates and updates the questions of a test paper from an uploaded file,
+/// which was made from the version of the paper with the lock version.
+/// Nothing is saved unless every row is valid.
+pub fn import(
+    paper: &TestPaper,
+    lock_version: i32,
+    content: &[u8],
+    conn: &PgConnection,
+) -> SResult<Result<TestPaper, Vec<RowError>>> {
+    if paper.lock_version != lock_version {
+        Err(Error::StaleUpdate)?;
+    }
+    let rows = match parse(content) {
+        Ok(rows) => rows,
+        Err(errors) => return Ok(Err(errors)),
+    };
+
+    let owner = QuestionOwner::TestPaper(paper.id);
+    let existing: HashSet<Uuid> = TestQuestion::find_all(owner, conn)?
+        .into_iter()
+        .map(|question| question.uuid)
+        .collect();
+    let mut seen = HashSet::new();
+    let mut errors = Vec::new();
+    for row in &rows {
+        if let Some(id) = row.id {
+            if !existing.contains(&id) {
+                errors.push(row_error(row.row, "No question of the test paper has this id."));
+            } else if !seen.insert(id) {
+                errors.push(row_error(row.row, "This question is listed more than once."));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Ok(Err(errors));
+    }
+
+    let mut questions = TestQuestionsUpdate {
+        new: Vec::new(),
+        update: Vec::new(),
+        remove: Vec::new(),
+    };
+    for row in rows {
+        match row.id {
+            Some(id) => {
+                let question = TestQuestion::find_by_uuid(id, conn)?;
+                let options = QuestionOption::find_all(question.id, conn)?;
+                // The questions are changed along with the paper, so the lock
+                // version of the paper covers them.
+                questions.update.push(TestQuestionUpdate {
+                    id,
+                    lock_version: question.lock_version,
+                    question: Some(row.question),
+                    topic: Some(row.topic),
+                    tags: Some(row.tags),
+                    difficulty: Some(row.difficulty),
+                    explanation: Some(row.explanation),
+                    options: update_options(options, row.options),
+                });
+            }
+            None => questions.new.push(TestQuestionForm {
+                question: row.question,
+                topic: row.topic,
+                tags: Some(row.tags),
+                difficulty: row.difficulty,
+                explanation: row.explanation,
+                options: row.options.into_iter().map(new_option).collect(),
+            }),
+        }
+    }
+    let update = TestPaperUpdate::questions(paper.uuid, lock_version, questions);
+    Ok(Ok(update.save(conn)?))
+}
+
+/// Matches the options of a row to the existing options by their position.
+fn update_options(existing: Vec<QuestionOption>, row: Vec<CsvOption>) -> QuestionOptionsUpdate {
+    let mut update = QuestionOptionsUpdate {
+        new: Vec::new(),
+        update: Vec::new(),
+        remove: Vec::new(),
+    };
+    let mut row = row.into_iter();
+    for option in existing {
+        match row.next() {
+            Some(row_option) => update.update.push(QuestionOptionUpdate {
+                id: option.uuid,
+                option: Some(row_option.option),
+                is_correct: Some(Some(row_option.is_correct)),
+                feedback: Some(row_option.feedback),
+            }),
+            None => update.remove.push(option.uuid),
+        }
+    }
+    update.new.extend(row.map(new_option));
+    update
+}
+
+fn new_option(option: CsvOption) -> QuestionOptionForm {
+    QuestionOptionForm {
+        option: option.option,
+        is_correct: Some(option.is_correct),
+        feedback: option.feedback,
+    }
+}
+
+fn row_error<S: Into<String>>(row: usize, message: S) -> RowError {
+    RowError {
+        row,
+        message: message.into(),
+    }
+}
+
+fn parse(content: &[u8]) -> Result<Vec<CsvQuestion>, Vec<RowError>> {
+    let mut reader = ReaderBuilder::new().flexible(true).from_reader(content);
+    let header = match reader.headers() {
+        Ok(header) => header.clone(),
+        Err(err) => return Err(vec![row_error(1, err.to_string())]),
+    };
+    let columns = Columns::new(&header).map_err(|message| vec![row_error(1, message)])?;
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let row = index + 2;
+        let parsed = record
+            .map_err(|err| err.to_string())
+            .and_then(|record| columns.parse(row, &record));
+        match parsed {
+            Ok(Some(question)) => rows.push(question),
+            Ok(None) => (),
+            Err(message) => errors.push(row_error(row, message)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(rows)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Positions of the columns in the header.
+struct Columns {
+    fixed: Vec<Option<usize>>,
+    /// Positions of the option, its correct flag and its feedback for every
+    /// option number.
+    options: Vec<(usize, Option<usize>, Option<usize>)>,
+}
+
+impl Columns {
+    fn new(header: &StringRecord) -> Result<Columns, String> {
+        let names: Vec<String> = header.iter().map(|name| name.trim().to_lowercase()).collect();
+        let position = |name: &str| names.iter().position(|column| column == name);
+        let fixed: Vec<Option<usize>> = FIXED_COLUMNS.iter().map(|&name| position(name)).collect();
+        if fixed[1].is_none() {
+            return Err("The header has no question column.".into());
+        }
+
+        let mut options = Vec::new();
+        for number in 1.. {
+            match position(&format!("option {}", number)) {
+                Some(option) => options.push((
+                    option,
+                    position(&format!("correct {}", number)),
+                    position(&format!("feedback {}", number)),
+                )),
+                None => break,
+            }
+        }
+        Ok(Columns { fixed, options })
+    }
+
+    /// Parses a record, or skips it if it is blank.
+    fn parse(&self, row: usize, record: &StringRecord) -> Result<Option<CsvQuestion>, String> {
+        let field = |position: Option<usize>| {
+            position
+                .and_then(|position| record.get(position))
+                .map(str::trim)
+                .unwrap_or("")
+        };
+        let optional = |position| Some(field(position).to_string()).filter(|text| !text.is_empty());
+        if record.iter().all(|text| text.trim().is_empty()) {
+            return Ok(None);
+        }
+
+        let id = match field(self.fixed[0]) {
+            "" => None,
+            id => Some(Uuid::parse_str(id).map_err(|_| format!("`{}` is not a valid id.", id))?),
+        };
+        let question = field(self.fixed[1]);
+        if question.is_empty() {
+            return Err("The question cannot be empty.".into());
+        }
+        let difficulty = match field(self.fixed[3]) {
+            "" => None,
+            name => Some(parse_difficulty(name)?),
+        };
+        let tags = field(self.fixed[4])
+            .split(';')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let mut options = Vec::new();
+        for (index, &(option, correct, feedback)) in self.options.iter().enumerate() {
+            let option = field(Some(option));
+            let correct = field(correct);
+            if option.is_empty() {
+                if !correct.is_empty() {
+                    return Err(format!("Option {} is empty but has a correct flag.", index + 1));
+                }
+                if !field(feedback).is_empty() {
+                    return Err(format!("Option {} is empty but has feedback.", index + 1));
+                }
+                continue;
+            }
+            options.push(CsvOption {
+                option: option.to_string(),
+                is_correct: parse_flag(index + 1, correct)?,
+                feedback: optional(feedback),
+            });
+        }
+        if options.len() < 2 {
+            return Err("A question needs at least two options.".into());
+        }
+        if !options.iter().any(|option| option.is_correct) {
+            return Err("A question needs at least one correct option.".into());
+        }
+
+        Ok(Some(CsvQuestion {
+            row,
+            id,
+            question: question.to_string(),
+            topic: optional(self.fixed[2]),
+            difficulty,
+            tags,
+            explanation: optional(self.fixed[5]),
+            options,
+        }))
+    }
+}
+
+fn difficulty_name(difficulty: Difficulty) -> &'static str {
+    match difficulty {
+        Difficulty::Easy => "EASY",
+        Difficulty::Medium => "MEDIUM",
+        Difficulty::Hard => "HARD",
+    }
+}
+
+fn parse_difficulty(name: &str) -> Result<Difficulty, String> {
+    match name.to_uppercase().as_str() {
+        "EASY" => Ok(Difficulty::Easy),
+        "MEDIUM" => Ok(Difficulty::Medium),
+        "HARD" => Ok(Difficulty::Hard),
+        _ => Err(format!("`{}` is not one of EASY, MEDIUM or HARD.", name)),
+    }
+}
+
+fn parse_flag(number: usize, flag: &str) -> Result<bool, String> {
+    match flag.to_uppercase().as_str() {
+        "TRUE" | "YES" | "Y" | "1" => Ok(true),
+        "" | "FALSE" | "NO" | "N" | "0" => Ok(false),
+        _ => Err(format!("Correct {} must be TRUE or FALSE.", number)),
+    }
+}
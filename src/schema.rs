@@ -1,3 +1,28 @@
+table! {
+    use diesel::sql_types::*;
+    use db_types::*;
+
+    attempt_questions (id) {
+        id -> Int4,
+        test_attempt_id -> Int4,
+        test_question_id -> Int4,
+        position -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use db_types::*;
+
+    draw_rules (id) {
+        id -> Int4,
+        uuid -> Uuid,
+        test_paper_id -> Int4,
+        question_bank_id -> Int4,
+        question_count -> Int4,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use db_types::*;
@@ -10,6 +35,18 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use db_types::*;
+
+    question_banks (id) {
+        id -> Int4,
+        uuid -> Uuid,
+        name -> Text,
+        description -> Nullable<Text>,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use db_types::*;
@@ -20,6 +57,8 @@ table! {
         option -> Text,
         test_question_id -> Int4,
         is_correct -> Nullable<Bool>,
+        position -> Int4,
+        feedback -> Nullable<Text>,
     }
 }
 
@@ -36,6 +75,7 @@ table! {
         start_time -> Timestamp,
         finish_time -> Nullable<Timestamp>,
         has_withdrawn -> Nullable<Bool>,
+        shuffle_seed -> Nullable<Int8>,
     }
 }
 
@@ -50,6 +90,13 @@ table! {
         description -> Nullable<Text>,
         #[sql_name = "type"]
         type_ -> Test_type,
+        shuffle -> Bool,
+        status -> Paper_status,
+        version -> Int4,
+        previous_version_id -> Nullable<Int4>,
+        is_latest -> Bool,
+        created_at -> Timestamp,
+        lock_version -> Int4,
     }
 }
 
@@ -61,7 +108,14 @@ table! {
         id -> Int4,
         uuid -> Uuid,
         question -> Text,
-        test_paper_id -> Int4,
+        test_paper_id -> Nullable<Int4>,
+        position -> Int4,
+        question_bank_id -> Nullable<Int4>,
+        topic -> Nullable<Text>,
+        tags -> Array<Text>,
+        difficulty -> Nullable<Difficulty_type>,
+        explanation -> Nullable<Text>,
+        lock_version -> Int4,
     }
 }
 
@@ -75,6 +129,7 @@ table! {
         test_paper_id -> Int4,
         time -> Timestamp,
         duration -> Int4,
+        lock_version -> Int4,
     }
 }
 
@@ -106,13 +161,19 @@ table! {
         contact -> Nullable<Text>,
         #[sql_name = "type"]
         type_ -> User_type,
+        created_at -> Timestamp,
     }
 }
 
+joinable!(attempt_questions -> test_attempts (test_attempt_id));
+joinable!(attempt_questions -> test_questions (test_question_id));
+joinable!(draw_rules -> question_banks (question_bank_id));
+joinable!(draw_rules -> test_papers (test_paper_id));
 joinable!(question_answers -> question_options (answered_option));
 joinable!(question_answers -> test_attempts (test_attempt_id));
 joinable!(question_answers -> test_questions (test_question_id));
 joinable!(question_options -> test_questions (test_question_id));
+joinable!(test_questions -> question_banks (question_bank_id));
 joinable!(test_questions -> test_papers (test_paper_id));
 joinable!(test_schedules -> test_papers (test_paper_id));
 joinable!(test_subscriptions -> test_papers (test_paper_id));
@@ -120,7 +181,10 @@ joinable!(test_subscriptions -> test_schedules (test_schedule_id));
 joinable!(test_subscriptions -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
+    attempt_questions,
+    draw_rules,
     question_answers,
+    question_banks,
     question_options,
     test_attempts,
     test_papers,
@@ -0,0 +1,225 @@
+//! Ranked search of users and questions. The words of a query are matched
+//! with Postgres full-text search, and its trigrams are matched too so that a
+//! misspelled query still finds something. Both are backed by the indexes of
+//! the `search` migration, whose expressions the queries here must repeat.
+
+use diesel::{
+    self,
+    sql_types::{BigInt, Float4, Int4, Text},
+    PgConnection, RunQueryDsl,
+};
+use errors::SResult;
+use models::{test_question::TestQuestion, user::User};
+use std::collections::HashMap;
+use Context;
+
+/// Number of results when none is asked for.
+const DEFAULT_LIMIT: i32 = 20;
+
+/// Most results of a search.
+const MAX_LIMIT: i32 = 100;
+
+/// Control characters which Postgres puts around the matching words of a
+/// snippet, to be replaced with `<mark>` tags once the text is escaped.
+/// They are removed from the text beforehand.
+const START_SEL: char = '\u{2}';
+const STOP_SEL: char = '\u{3}';
+
+/// Ranks the users whose words or trigrams match the query, with a snippet
+/// of each.
+const USERS_SQL: &str = "
+    SELECT id,
+           ts_rank(to_tsvector('simple', body), query) + word_similarity($1, body) AS rank,
+           ts_headline(
+               'simple', translate(body, chr(2) || chr(3), ''), query,
+               'StartSel=' || chr(2) || ', StopSel=' || chr(3)
+           ) AS snippet
+    FROM (
+        SELECT id, user_search_text(first_name, last_name, email, contact) AS body
+        FROM users
+    ) AS texts, plainto_tsquery('simple', $1) AS query
+    WHERE to_tsvector('simple', body) @@ query OR $1 <% body
+    ORDER BY rank DESC, id
+    LIMIT $2";
+
+/// Ranks the questions whose text or the text of one of whose options
+/// matches the query. A question is ranked by its best matching text, which
+/// is also the one it gets a snippet of. Questions of the earlier versions of
+/// test papers are left out.
+const QUESTIONS_SQL: &str = "
+    SELECT id, rank, snippet
+    FROM (
+        SELECT DISTINCT ON (id) id,
+               ts_rank(to_tsvector('english', body), query) + word_similarity($1, body) AS rank,
+               ts_headline(
+                   'english', translate(body, chr(2) || chr(3), ''), query,
+                   'StartSel=' || chr(2) || ', StopSel=' || chr(3)
+               ) AS snippet
+        FROM (
+            SELECT id, question AS body FROM test_questions
+            UNION ALL
+            SELECT test_question_id, option FROM question_options
+        ) AS texts, plainto_tsquery('english', $1) AS query
+        WHERE to_tsvector('english', body) @@ query OR $1 <% body
+        ORDER BY id, rank DESC
+    ) AS hits
+    WHERE id IN (
+        SELECT test_questions.id
+        FROM test_questions
+        LEFT JOIN test_papers ON test_papers.id = test_questions.test_paper_id
+        WHERE test_questions.question_bank_id IS NOT NULL OR test_papers.is_latest
+    )
+    ORDER BY rank DESC, id
+    LIMIT $2";
+
+#[derive(QueryableByName)]
+struct Hit {
+    #[sql_type = "Int4"]
+    id: i32,
+    #[sql_type = "Float4"]
+    rank: f32,
+    #[sql_type = "Text"]
+    snippet: String,
+}
+
+/// A user found by a search.
+pub struct UserHit {
+    user: User,
+    rank: f32,
+    snippet: String,
+}
+
+/// A question found by a search.
+pub struct QuestionHit {
+    question: TestQuestion,
+    rank: f32,
+    snippet: String,
+}
+
+pub fn search_users(
+    query: &str,
+    first: Option<i32>,
+    conn: &PgConnection,
+) -> SResult<Vec<UserHit>> {
+    let hits = find_hits(USERS_SQL, query, first, conn)?;
+    let ids: Vec<i32> = hits.iter().map(|hit| hit.id).collect();
+    let users = User::find_many(&ids, conn)?
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect();
+    Ok(pair(hits, users, |user, rank, snippet| UserHit {
+        user,
+        rank,
+        snippet,
+    }))
+}
+
+pub fn search_questions(
+    query: &str,
+    first: Option<i32>,
+    conn: &PgConnection,
+) -> SResult<Vec<QuestionHit>> {
+    let hits = find_hits(QUESTIONS_SQL, query, first, conn)?;
+    let ids: Vec<i32> = hits.iter().map(|hit| hit.id).collect();
+    let questions = TestQuestion::find_many(&ids, conn)?
+        .into_iter()
+        .map(|question| (question.id, question))
+        .collect();
+    Ok(pair(hits, questions, |question, rank, snippet| QuestionHit {
+        question,
+        rank,
+        snippet,
+    }))
+}
+
+fn find_hits(
+    sql: &str,
+    query: &str,
+    first: Option<i32>,
+    conn: &PgConnection,
+) -> SResult<Vec<Hit>> {
+    let query = query.trim();
+    let limit = first.unwrap_or(DEFAULT_LIMIT).max(0).min(MAX_LIMIT);
+    if query.is_empty() || limit == 0 {
+        return Ok(Vec::new());
+    }
+    Ok(diesel::sql_query(sql)
+        .bind::<Text, _>(query)
+        .bind::<BigInt, _>(i64::from(limit))
+        .load(conn)?)
+}
+
+/// Pairs the hits with their rows in the order of the hits, leaving out the
+/// rows which were deleted in between.
+fn pair<T, R, F>(hits: Vec<Hit>, mut rows: HashMap<i32, T>, result: F) -> Vec<R>
+where
+    F: Fn(T, f32, String) -> R,
+{
+    hits.into_iter()
+        .filter_map(|hit| {
+            let row = rows.remove(&hit.id)?;
+            Some(result(row, hit.rank, highlight(&hit.snippet)))
+        }).collect()
+}
+
+/// Escapes the text of a snippet as HTML and marks its matching words.
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            START_SEL => html.push_str("<mark>"),
+            STOP_SEL => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            _ => html.push(c),
+        }
+    }
+    html
+}
+
+graphql_object!(UserHit: Context |&self| {
+    description: "A user found by a search."
+
+    field user() -> &User
+        as "The user."
+    {
+        &self.user
+    }
+
+    field rank() -> f64
+        as "Relevance of the user to the search, higher being more relevant."
+    {
+        f64::from(self.rank)
+    }
+
+    field snippet() -> &str
+        as "Text of the user as escaped HTML, with the matching words between `<mark>` and `</mark>`."
+    {
+        &self.snippet
+    }
+});
+
+graphql_object!(QuestionHit: Context |&self| {
+    description: "A question found by a search."
+
+    field question() -> &TestQuestion
+        as "The question."
+    {
+        &self.question
+    }
+
+    field rank() -> f64
+        as "Relevance of the question to the search, higher being more relevant."
+    {
+        f64::from(self.rank)
+    }
+
+    field snippet() -> &str
+        as "Text of the question, or of its option which matched best, as escaped HTML with the matching words between `<mark>` and `</mark>`."
+    {
+        &self.snippet
+    }
+});
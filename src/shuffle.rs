@@ -0,0 +1,46 @@
+use chrono::Utc;
+
+/// A small deterministic random number generator (SplitMix64).
+///
+/// The same seed always produces the same sequence, which lets an attempt be
+/// shuffled again on every request from nothing but its stored seed.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A random number in `0..upper`.
+    fn below(&mut self, upper: usize) -> usize {
+        (self.next() % upper as u64) as usize
+    }
+}
+
+/// Creates a fresh seed for a new attempt.
+pub fn new_seed(salt: i32) -> i64 {
+    let now = Utc::now();
+    let nanos = (now.timestamp() as u64)
+        .wrapping_mul(1_000_000_000)
+        .wrapping_add(u64::from(now.timestamp_subsec_nanos()));
+    SplitMix64(nanos ^ ((salt as u64) << 32)).next() as i64
+}
+
+/// Derives a seed for a nested list, e.g. the options of a question, so that
+/// every question of an attempt gets a different option order.
+pub fn derive_seed(seed: i64, salt: i32) -> i64 {
+    SplitMix64(seed as u64 ^ salt as u64).next() as i64
+}
+
+/// Shuffles the items in place. The order only depends on the seed.
+pub fn shuffle<T>(items: &mut [T], seed: i64) {
+    let mut rng = SplitMix64(seed as u64);
+    for i in (1..items.len()).rev() {
+        let j = rng.below(i + 1);
+        items.swap(i, j);
+    }
+}
@@ -0,0 +1,755 @@
+//! Live events of the tests over WebSockets, following the `graphql-ws`
+//! protocol of `subscriptions-transport-ws`.
+//!
+//! Juniper cannot execute subscriptions, so a subscription is executed as a
+//! query of a schema of its own: once when it starts, which checks it and
+//! finds out what it listens to, and then once for every event it gets, with
+//! the event in its context.
+
+use basic::{self, BasicUser};
+use chrono::{NaiveDateTime, Utc};
+use diesel::PgConnection;
+use errors::{Error, SResult};
+use events::{self, AttemptEvent, AttemptEventKind, Event};
+use futures::{stream, Future, Sink, Stream};
+use futures_cpupool::CpuPool;
+use graphql_http::{self, Extensions};
+use juniper::{http::GraphQLRequest, EmptyMutation, InputValue, RootNode};
+use models::{test_schedule::TestSchedule, test_subscription::TestSubscription, user::User};
+use persisted_queries::PersistedQueries;
+use query_limits::{self, QueryLimits};
+use serde_json::{self, Value};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+use warp::ws::{Message, WebSocket};
+use {user_lookup, PgPool, PooledPg};
+
+/// Ticks between two keep alive messages.
+const KEEP_ALIVE_TICKS: u64 = 15;
+
+/// Ticks a connection has to send `connection_init` in before it is closed.
+const INIT_TIMEOUT_TICKS: u64 = 10;
+
+type SubscriptionSchema = RootNode<'static, Subscription, EmptyMutation<SubscriptionContext>>;
+
+/// Root of the subscription schema.
+pub struct Subscription;
+
+pub struct SubscriptionContext {
+    user_id: i32,
+    is_admin: bool,
+    stage: Stage,
+    topics: RefCell<Vec<Topic>>,
+}
+
+enum Stage {
+    /// The subscription is being started, so its topics are looked up.
+    Starting(PooledPg),
+    /// An event is being delivered to the subscription.
+    Delivering(Delivery),
+}
+
+enum Delivery {
+    Schedule(ScheduleEvent),
+    Attempt {
+        test_schedule_id: Uuid,
+        event: AttemptEvent,
+    },
+}
+
+impl juniper::Context for SubscriptionContext {}
+
+impl SubscriptionContext {
+    fn new(user: &User, stage: Stage) -> SubscriptionContext {
+        SubscriptionContext {
+            user_id: user.id,
+            is_admin: user.is_admin(),
+            stage,
+            topics: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Listens to a test schedule, which its candidates and the admins can.
+    fn subscribe_schedule(&self, id: Uuid, conn: &PgConnection) -> SResult<()> {
+        let schedule = TestSchedule::find_by_uuid(id, conn)?;
+        let is_candidate = TestSubscription::exists_for_schedule(self.user_id, schedule.id, conn)?;
+        if !self.is_admin && !is_candidate {
+            Err(Error::Unauthorized)?;
+        }
+        let now = Utc::now().naive_utc();
+        self.topics
+            .borrow_mut()
+            .push(Topic::Schedule(ScheduleTimer::new(&schedule, now)));
+        Ok(())
+    }
+
+    /// Listens to the attempts of a test schedule, which only the admins can.
+    fn subscribe_attempts(&self, test_schedule_id: Uuid, conn: &PgConnection) -> SResult<()> {
+        if !self.is_admin {
+            Err(Error::Unauthorized)?;
+        }
+        let schedule = TestSchedule::find_by_uuid(test_schedule_id, conn)?;
+        self.topics.borrow_mut().push(Topic::Attempts {
+            id: schedule.id,
+            uuid: schedule.uuid,
+        });
+        Ok(())
+    }
+}
+
+graphql_object!(Subscription: SubscriptionContext |&self| {
+    description: "Root subscription type, served over the WebSocket at `/subscriptions`."
+
+    field test_schedule(&executor, id: Uuid) -> SResult<Option<&ScheduleEvent>>
+        as "Start, end and remaining time of a test schedule. Its candidates and admins can subscribe to it."
+    {
+        let ctx = executor.context();
+        match ctx.stage {
+            Stage::Starting(ref conn) => ctx.subscribe_schedule(id, conn).map(|_| None),
+            Stage::Delivering(Delivery::Schedule(ref event)) if event.test_schedule_id == id => {
+                Ok(Some(event))
+            }
+            Stage::Delivering(_) => Ok(None),
+        }
+    }
+
+    field test_attempts(&executor, test_schedule_id: Uuid) -> SResult<Option<&AttemptEvent>>
+        as "Attempts of a test schedule being started, finished and withdrawn. Only admins can subscribe to them."
+    {
+        let ctx = executor.context();
+        match ctx.stage {
+            Stage::Starting(ref conn) => {
+                ctx.subscribe_attempts(test_schedule_id, conn).map(|_| None)
+            }
+            Stage::Delivering(Delivery::Attempt { test_schedule_id: id, ref event })
+                if id == test_schedule_id =>
+            {
+                Ok(Some(event))
+            }
+            Stage::Delivering(_) => Ok(None),
+        }
+    }
+});
+
+/// What happened to a test schedule.
+#[derive(Clone, Copy, GraphQLEnum)]
+pub enum ScheduleEventKind {
+    /// Sent once the subscription has started.
+    Current,
+    Started,
+    /// Sent every second while the test is happening.
+    Tick,
+    Ended,
+    Rescheduled,
+    Cancelled,
+}
+
+pub struct ScheduleEvent {
+    kind: ScheduleEventKind,
+    test_schedule_id: Uuid,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+    time: NaiveDateTime,
+}
+
+impl ScheduleEvent {
+    fn seconds_until(&self, time: NaiveDateTime) -> i32 {
+        (time - self.time).num_seconds().max(0) as i32
+    }
+}
+
+graphql_object!(ScheduleEvent: () |&self| {
+    description: "An event of a test schedule, timed by the server."
+
+    field kind() -> ScheduleEventKind
+        as "What happened to the test schedule."
+    {
+        self.kind
+    }
+
+    field test_schedule_id() -> Uuid
+        as "Id of the test schedule."
+    {
+        self.test_schedule_id
+    }
+
+    field start_time() -> &NaiveDateTime
+        as "When the test starts."
+    {
+        &self.start_time
+    }
+
+    field end_time() -> &NaiveDateTime
+        as "When the test ends."
+    {
+        &self.end_time
+    }
+
+    field time() -> &NaiveDateTime
+        as "Time of the server when the event happened."
+    {
+        &self.time
+    }
+
+    field is_happening() -> bool
+        as "Specifies whether the test is happening."
+    {
+        self.start_time <= self.time && self.time < self.end_time
+    }
+
+    field has_ended() -> bool
+        as "Specifies whether the test has ended."
+    {
+        self.end_time <= self.time
+    }
+
+    field seconds_to_start() -> i32
+        as "Seconds left until the test starts."
+    {
+        self.seconds_until(self.start_time)
+    }
+
+    field remaining_seconds() -> i32
+        as "Seconds left to take the test, which is the whole duration before it starts."
+    {
+        self.seconds_until(self.end_time) - self.seconds_until(self.start_time)
+    }
+});
+
+graphql_object!(AttemptEvent: () |&self| {
+    description: "An event of a test attempt."
+
+    field kind() -> AttemptEventKind
+        as "What happened to the test attempt."
+    {
+        self.kind
+    }
+
+    field test_attempt_id() -> Uuid
+        as "Id of the test attempt."
+    {
+        self.test_attempt_id
+    }
+
+    field user_id() -> Uuid
+        as "Id of the user taking the test."
+    {
+        self.user_id
+    }
+
+    field time() -> &NaiveDateTime
+        as "When it happened."
+    {
+        &self.time
+    }
+});
+
+/// What a subscription listens to.
+enum Topic {
+    Schedule(ScheduleTimer),
+    Attempts { id: i32, uuid: Uuid },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Pending,
+    Happening,
+    Ended,
+    Cancelled,
+}
+
+/// Follows a test schedule as time passes.
+struct ScheduleTimer {
+    id: i32,
+    uuid: Uuid,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    phase: Phase,
+}
+
+impl ScheduleTimer {
+    fn new(schedule: &TestSchedule, now: NaiveDateTime) -> ScheduleTimer {
+        let mut timer = ScheduleTimer {
+            id: schedule.id,
+            uuid: schedule.uuid,
+            start: schedule.time,
+            end: schedule.end_time(),
+            phase: Phase::Pending,
+        };
+        timer.phase = timer.phase_at(now);
+        timer
+    }
+
+    fn phase_at(&self, now: NaiveDateTime) -> Phase {
+        if now < self.start {
+            Phase::Pending
+        } else if now < self.end {
+            Phase::Happening
+        } else {
+            Phase::Ended
+        }
+    }
+
+    fn event(&self, kind: ScheduleEventKind, now: NaiveDateTime) -> ScheduleEvent {
+        ScheduleEvent {
+            kind,
+            test_schedule_id: self.uuid,
+            start_time: self.start,
+            end_time: self.end,
+            time: now,
+        }
+    }
+
+    fn update(&mut self, event: &Event, now: NaiveDateTime) -> Option<ScheduleEvent> {
+        match *event {
+            Event::Tick => {
+                let phase = self.phase_at(now);
+                let kind = match (self.phase, phase) {
+                    (Phase::Pending, Phase::Happening) => ScheduleEventKind::Started,
+                    (Phase::Pending, Phase::Ended) | (Phase::Happening, Phase::Ended) => {
+                        ScheduleEventKind::Ended
+                    }
+                    (Phase::Happening, Phase::Happening) => ScheduleEventKind::Tick,
+                    _ => return None,
+                };
+                self.phase = phase;
+                Some(self.event(kind, now))
+            }
+            Event::ScheduleChanged {
+                test_schedule_id,
+                time,
+            } if test_schedule_id == self.id =>
+            {
+                match time {
+                    Some((start, end)) => {
+                        self.start = start;
+                        self.end = end;
+                        self.phase = self.phase_at(now);
+                        Some(self.event(ScheduleEventKind::Rescheduled, now))
+                    }
+                    None => {
+                        self.phase = Phase::Cancelled;
+                        Some(self.event(ScheduleEventKind::Cancelled, now))
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.phase == Phase::Ended || self.phase == Phase::Cancelled
+    }
+}
+
+impl Topic {
+    fn update(&mut self, event: &Event, now: NaiveDateTime) -> Option<Delivery> {
+        match *self {
+            Topic::Schedule(ref mut timer) => timer.update(event, now).map(Delivery::Schedule),
+            Topic::Attempts { id, uuid } => match *event {
+                Event::Attempt(ref event) if event.test_schedule_id == id => {
+                    Some(Delivery::Attempt {
+                        test_schedule_id: uuid,
+                        event: event.clone(),
+                    })
+                }
+                _ => None,
+            },
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        match *self {
+            Topic::Schedule(ref timer) => timer.is_over(),
+            Topic::Attempts { .. } => false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    ConnectionInit { payload: Option<InitPayload> },
+    Start { id: String, payload: OperationPayload },
+    Stop { id: String },
+    ConnectionTerminate,
+}
+
+#[derive(Deserialize)]
+struct InitPayload {
+    /// Value of an `Authorization` header, as browsers cannot set headers on
+    /// WebSockets.
+    authorization: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OperationPayload {
+    query: Option<String>,
+    operation_name: Option<String>,
+    variables: Option<InputValue>,
+    extensions: Option<Extensions>,
+}
+
+struct Operation {
+    query: String,
+    operation_name: Option<String>,
+    variables: Option<InputValue>,
+    topics: Vec<Topic>,
+}
+
+impl Operation {
+    fn execute(&self, schema: &SubscriptionSchema, ctx: &SubscriptionContext) -> (Value, bool) {
+        let request = GraphQLRequest::new(
+            self.query.clone(),
+            self.operation_name.clone(),
+            self.variables.clone(),
+        );
+        let response = request.execute(schema, ctx);
+        let is_ok = response.is_ok();
+        (serde_json::to_value(&response).unwrap_or(Value::Null), is_ok)
+    }
+
+    /// Executes the operation for a delivery, giving nothing when none of its
+    /// fields are interested in it.
+    fn deliver(
+        &self,
+        id: &str,
+        schema: &SubscriptionSchema,
+        user: &User,
+        delivery: Delivery,
+    ) -> Option<Message> {
+        let ctx = SubscriptionContext::new(user, Stage::Delivering(delivery));
+        let (response, _) = self.execute(schema, &ctx);
+        let has_data = response["data"]
+            .as_object()
+            .map_or(false, |data| data.values().any(|value| !value.is_null()));
+        if has_data || response.get("errors").is_some() {
+            Some(outgoing(json!({ "type": "data", "id": id, "payload": response })))
+        } else {
+            None
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.topics.iter().all(Topic::is_over)
+    }
+}
+
+enum Input {
+    Message(Message),
+    Event(Arc<Event>),
+    Closed,
+}
+
+/// A WebSocket connection and the subscriptions started over it.
+pub struct Session {
+    pool: PgPool,
+    persisted: Arc<PersistedQueries>,
+    limits: Arc<QueryLimits>,
+    /// Every session has a schema of its own, as a context holding a
+    /// connection cannot be shared between threads.
+    schema: SubscriptionSchema,
+    cache_ttl: Duration,
+    /// Credentials of the upgrade request, used unless `connection_init`
+    /// has some.
+    credentials: Option<BasicUser>,
+    user: Option<User>,
+    operations: HashMap<String, Operation>,
+    ticks: u64,
+    is_closed: bool,
+}
+
+impl Session {
+    pub fn new(
+        pool: PgPool,
+        persisted: Arc<PersistedQueries>,
+        limits: Arc<QueryLimits>,
+        cache_ttl: Duration,
+        credentials: Option<BasicUser>,
+    ) -> Session {
+        Session {
+            pool,
+            persisted,
+            limits,
+            schema: SubscriptionSchema::new(Subscription, EmptyMutation::new()),
+            cache_ttl,
+            credentials,
+            user: None,
+            operations: HashMap::new(),
+            ticks: 0,
+            is_closed: false,
+        }
+    }
+
+    /// Serves the connection until either side closes it. Messages and
+    /// events are handled on a thread pool as they may block on the
+    /// database, but one at a time and in order.
+    pub fn run(self, socket: WebSocket, cpu_pool: CpuPool) -> impl Future<Item = (), Error = ()> {
+        let (sink, client) = socket.split();
+        let session = Arc::new(Mutex::new(self));
+        let client = client
+            .map(Input::Message)
+            .map_err(|err| debug!("WebSocket error: {}", err))
+            .chain(stream::once(Ok(Input::Closed)));
+        // The events end when the session falls too far behind them.
+        let events = events::subscribe()
+            .map(Input::Event)
+            .chain(stream::once(Ok(Input::Closed)));
+
+        client
+            .select(events)
+            .and_then(move |input| {
+                let session = session.clone();
+                cpu_pool.spawn_fn(move || {
+                    let mut session = session.lock().unwrap();
+                    let mut output: Vec<_> =
+                        session.handle(input).into_iter().map(Some).collect();
+                    if session.is_closed {
+                        output.push(None);
+                    }
+                    Ok::<_, ()>(output)
+                })
+            }).map(stream::iter_ok)
+            .flatten()
+            .take_while(|output| Ok(output.is_some()))
+            .filter_map(|output| output)
+            .forward(sink.sink_map_err(|err| debug!("WebSocket error: {}", err)))
+            .map(|_| ())
+    }
+
+    fn handle(&mut self, input: Input) -> Vec<Message> {
+        match input {
+            Input::Message(ref message) if message.is_close() => {
+                self.is_closed = true;
+                Vec::new()
+            }
+            Input::Message(message) => match message.to_str() {
+                Ok(text) => match serde_json::from_str(text) {
+                    Ok(message) => self.receive(message),
+                    Err(err) => vec![outgoing(json!({
+                        "type": "error",
+                        "payload": { "message": format!("Invalid message: {}", err) },
+                    }))],
+                },
+                Err(_) => Vec::new(),
+            },
+            Input::Event(event) => self.deliver(&event),
+            Input::Closed => {
+                self.is_closed = true;
+                Vec::new()
+            }
+        }
+    }
+
+    fn receive(&mut self, message: ClientMessage) -> Vec<Message> {
+        match message {
+            ClientMessage::ConnectionInit { payload } => {
+                let credentials = payload
+                    .and_then(|payload| payload.authorization)
+                    .and_then(|auth| basic::parse_authorization(auth).ok())
+                    .or_else(|| self.credentials.take());
+                match self.authenticate(credentials) {
+                    Ok(user) => {
+                        self.user = Some(user);
+                        vec![
+                            outgoing(json!({ "type": "connection_ack" })),
+                            outgoing(json!({ "type": "ka" })),
+                        ]
+                    }
+                    Err(err) => {
+                        self.is_closed = true;
+                        vec![outgoing(json!({
+                            "type": "connection_error",
+                            "payload": { "message": err },
+                        }))]
+                    }
+                }
+            }
+            ClientMessage::Start { id, payload } => self.start(id, payload),
+            ClientMessage::Stop { id } => {
+                self.operations.remove(&id);
+                vec![outgoing(json!({ "type": "complete", "id": id }))]
+            }
+            ClientMessage::ConnectionTerminate => {
+                self.is_closed = true;
+                Vec::new()
+            }
+        }
+    }
+
+    fn authenticate(&self, credentials: Option<BasicUser>) -> Result<User, &'static str> {
+        let conn = self.pool.get().map_err(|err| {
+            warn!("Could not authenticate a subscriber: {}", err);
+            "Something bad happened.."
+        })?;
+        match user_lookup(conn, credentials, self.cache_ttl) {
+            Ok((_, Some(user))) => Ok(user),
+            Ok((_, None)) => Err("This is an unauthorized request."),
+            Err(_) => Err("Password for the user is incorrect."),
+        }
+    }
+
+    /// Starts a subscription, sending the current state of its schedules
+    /// right away.
+    fn start(&mut self, id: String, payload: OperationPayload) -> Vec<Message> {
+        let user = match self.user {
+            Some(ref user) => user,
+            None => return vec![error_message(&id, "The connection is not initialised.")],
+        };
+        let OperationPayload {
+            query,
+            operation_name,
+            variables,
+            extensions,
+        } = payload;
+        let hash = extensions.as_ref().and_then(Extensions::persisted_hash);
+        let limits = &self.limits;
+        let checked = self.persisted.resolve(query, hash).and_then(|query| {
+            let name = operation_name.as_ref().map(String::as_str);
+            limits.check(&query, name, variables.as_ref())?;
+            Ok(query)
+        });
+        let query = match checked {
+            Ok(query) => query,
+            Err(err) => {
+                return vec![outgoing(json!({
+                    "type": "error",
+                    "id": id,
+                    "payload": graphql_http::error_body(err)["errors"],
+                }))]
+            }
+        };
+        let conn = match self.pool.get() {
+            Ok(conn) => conn,
+            Err(err) => {
+                warn!("Could not start a subscription: {}", err);
+                return vec![error_message(&id, "Something bad happened..")];
+            }
+        };
+
+        let mut operation = Operation {
+            query: as_query(&query),
+            operation_name,
+            variables,
+            topics: Vec::new(),
+        };
+        let ctx = SubscriptionContext::new(user, Stage::Starting(conn));
+        let (response, is_ok) = operation.execute(&self.schema, &ctx);
+        if !is_ok {
+            return vec![outgoing(json!({
+                "type": "error",
+                "id": id,
+                "payload": response["errors"],
+            }))];
+        }
+        operation.topics = ctx.topics.into_inner();
+
+        let now = Utc::now().naive_utc();
+        let current: Vec<_> = operation
+            .topics
+            .iter()
+            .filter_map(|topic| match *topic {
+                Topic::Schedule(ref timer) => {
+                    Some(Delivery::Schedule(timer.event(ScheduleEventKind::Current, now)))
+                }
+                Topic::Attempts { .. } => None,
+            }).collect();
+        let mut messages: Vec<_> = current
+            .into_iter()
+            .filter_map(|delivery| operation.deliver(&id, &self.schema, user, delivery))
+            .collect();
+        if operation.is_over() {
+            messages.push(outgoing(json!({ "type": "complete", "id": id })));
+        } else {
+            self.operations.insert(id, operation);
+        }
+        messages
+    }
+
+    /// Delivers an event to every subscription interested in it, completing
+    /// the ones which are over. A connection which is not initialised in
+    /// time is closed.
+    fn deliver(&mut self, event: &Event) -> Vec<Message> {
+        let mut messages = Vec::new();
+        if let Event::Tick = *event {
+            self.ticks += 1;
+            if self.user.is_none() && self.ticks >= INIT_TIMEOUT_TICKS {
+                self.is_closed = true;
+                return vec![outgoing(json!({
+                    "type": "connection_error",
+                    "payload": { "message": "The connection was not initialised in time." },
+                }))];
+            }
+        }
+        let user = match self.user {
+            Some(ref user) => user,
+            None => return messages,
+        };
+        if let Event::Tick = *event {
+            if self.ticks % KEEP_ALIVE_TICKS == 0 {
+                messages.push(outgoing(json!({ "type": "ka" })));
+            }
+        }
+
+        let now = Utc::now().naive_utc();
+        let schema = &self.schema;
+        let mut over = Vec::new();
+        for (id, operation) in &mut self.operations {
+            let deliveries: Vec<_> = operation
+                .topics
+                .iter_mut()
+                .filter_map(|topic| topic.update(event, now))
+                .collect();
+            for delivery in deliveries {
+                messages.extend(operation.deliver(id, schema, user, delivery));
+            }
+            if operation.is_over() {
+                over.push(id.clone());
+            }
+        }
+        for id in over {
+            self.operations.remove(&id);
+            messages.push(outgoing(json!({ "type": "complete", "id": id })));
+        }
+        messages
+    }
+}
+
+fn outgoing(value: Value) -> Message {
+    Message::text(value.to_string())
+}
+
+fn error_message(id: &str, text: &str) -> Message {
+    outgoing(json!({
+        "type": "error",
+        "id": id,
+        "payload": [{ "message": text }],
+    }))
+}
+
+/// Juniper only parses queries and mutations, so a subscription is parsed as
+/// a query of the subscription schema. A document which cannot be read is
+/// left for Juniper to reject.
+fn as_query(document: &str) -> String {
+    let keywords = match query_limits::operation_keywords(document) {
+        Some(keywords) => keywords,
+        None => return document.to_string(),
+    };
+    let mut query = String::with_capacity(document.len());
+    let mut end = 0;
+    for (offset, keyword) in keywords {
+        if keyword == "subscription" {
+            query.push_str(&document[end..offset]);
+            query.push_str("query");
+            end = offset + keyword.len();
+        }
+    }
+    query.push_str(&document[end..]);
+    query
+}
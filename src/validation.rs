@@ -0,0 +1,134 @@
+//! Checks of the inputs of the mutations, run before anything is saved so
+//! that every invalid field is reported at once instead of the first
+//! database error. Paths are the GraphQL names of the fields relative to the
+//! input, like `["questions", 2, "options"]`.
+
+use errors::{Error, SResult};
+use juniper::Value;
+
+/// An invalid field of an input.
+#[derive(Debug)]
+pub struct Violation {
+    pub path: Vec<PathSegment>,
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PathSegment {
+    Field(&'static str),
+    Index(usize),
+}
+
+/// An input which can be checked before it is saved.
+pub trait Validate {
+    fn validate(&self, validator: &mut Validator);
+}
+
+/// Collects the violations of an input while walking through its fields.
+#[derive(Default)]
+pub struct Validator {
+    path: Vec<PathSegment>,
+    violations: Vec<Violation>,
+}
+
+/// Fails with every violation of the input, if it has any.
+pub fn validate<T: Validate>(input: &T) -> SResult<()> {
+    let mut validator = Validator::default();
+    input.validate(&mut validator);
+    if validator.violations.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(validator.violations))
+    }
+}
+
+impl Validator {
+    /// Records a violation of a field of the current input.
+    pub fn add<S: Into<String>>(&mut self, field: &'static str, code: &'static str, message: S) {
+        let mut path = self.path.clone();
+        path.push(PathSegment::Field(field));
+        self.violations.push(Violation {
+            path,
+            code,
+            message: message.into(),
+        });
+    }
+
+    /// Validates an input nested in a field.
+    pub fn nested<T: Validate>(&mut self, field: &'static str, input: &T) {
+        self.path.push(PathSegment::Field(field));
+        input.validate(self);
+        self.path.pop();
+    }
+
+    /// Validates every input of a list.
+    pub fn each<T: Validate>(&mut self, field: &'static str, inputs: &[T]) {
+        self.path.push(PathSegment::Field(field));
+        for (index, input) in inputs.iter().enumerate() {
+            self.path.push(PathSegment::Index(index));
+            input.validate(self);
+            self.path.pop();
+        }
+        self.path.pop();
+    }
+
+    pub fn not_blank(&mut self, field: &'static str, value: &str) {
+        if value.trim().is_empty() {
+            self.add(field, "BLANK", "Must not be blank.");
+        }
+    }
+
+    pub fn email(&mut self, field: &'static str, value: &str) {
+        if value.trim().is_empty() {
+            self.add(field, "BLANK", "Must not be blank.");
+            return;
+        }
+        let is_valid = match value.rfind('@') {
+            Some(at) => {
+                let (local, domain) = (&value[..at], &value[at + 1..]);
+                !local.is_empty()
+                    && domain.contains('.')
+                    && !domain.starts_with('.')
+                    && !domain.ends_with('.')
+                    && !value.chars().any(char::is_whitespace)
+            }
+            None => false,
+        };
+        if !is_valid {
+            self.add(field, "INVALID_EMAIL", "Must be an email like `name@example.com`.");
+        }
+    }
+
+    pub fn positive(&mut self, field: &'static str, value: i32) {
+        if value <= 0 {
+            self.add(field, "NOT_POSITIVE", "Must be greater than zero.");
+        }
+    }
+
+    pub fn not_empty<T>(&mut self, field: &'static str, items: &[T]) {
+        if items.is_empty() {
+            self.add(field, "EMPTY", "Must have at least one item.");
+        }
+    }
+}
+
+impl Violation {
+    pub fn to_value(&self) -> Value {
+        let path = Value::List(
+            self.path
+                .iter()
+                .map(|segment| match *segment {
+                    PathSegment::Field(field) => Value::String(field.to_string()),
+                    PathSegment::Index(index) => Value::Int(index as i32),
+                }).collect(),
+        );
+        let code = self.code;
+        let message = self.message.as_str();
+        graphql_value!({
+            "path": path,
+            "code": code,
+            "message": message
+        })
+    }
+}